@@ -1,6 +1,5 @@
-use challenger_rs::position;
-use challenger_rs::position::Position;
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use challenger_rs::position::{str_to_move, Move, Position};
+use criterion::{criterion_group, criterion_main, Criterion};
 
 // Cheat sheet
 //
@@ -11,14 +10,21 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 // 'cargo bench --bench challenger_benchmark -- --load-benchmark base_2 --baseline base_1
 pub fn play_moves(c: &mut Criterion) {
     let start: Position =
-        Position::from("1nb1k2r/ppppppp1/r4n2/P1bq4/2BQ3p/R4N2/1PPPPPPP/1NB1K2R w - - 0 1");
-    let moves: Vec<u16> = vec![
-        //"g2g4", "h4g3", "f3e5", "b7b5", "a5b6", "f6e4", "e1g1", "e8g8", "c4c6", "c5a3", "d4e4",
-        //"d5e5", "f1d1", "f8d8", "g1g2", "g8f1", "b6c7", "g3f2", "c7b2N", "f2f1n",
-        1934, 34207, 2325, 2161, 35424, 1837, 388, 4028, 2714, 1058, 1819, 2339, 197, 3837, 902,
-        382, 3241, 854, 17010, 16717,
-    ];
-    let bit_moves: Vec<u16> = vec![];
+        Position::from("1nb1k2r/ppppppp1/r4n2/P1bq4/2BQ3p/R4N2/1PPPPPPP/1NB1K2R w Kk - 0 1");
+    let mut position = start;
+    // A legal game with double pushes, en passant, castling, captures, checks
+    // and promotions
+    let moves: Vec<Move> = [
+        "g2g4", "h4g3", "f3e5", "b7b5", "a5b6", "f6e4", "e1g1", "e8g8", "b6c7", "g3h2", "g1g2",
+        "h2h1q", "g2h1", "c5d4", "c7b8n", "e4f2", "h1g1", "f2d3", "e2e3", "d3c1", "f1c1",
+    ]
+    .iter()
+    .map(|x| {
+        let mov = str_to_move(x, position).unwrap();
+        position.play_move(mov);
+        mov
+    })
+    .collect();
 
     c.bench_function("play_moves", |b| {
         b.iter(|| {
@@ -30,9 +36,49 @@ pub fn play_moves(c: &mut Criterion) {
     });
 }
 
+// Move generation exercises every attack table: leapers, pawns, sliders and
+// the between table used to find pinned pieces.
+pub fn generate_moves(c: &mut Criterion) {
+    let positions: Vec<Position> = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+    ]
+    .iter()
+    .map(|&x| Position::from(x))
+    .collect();
+
+    c.bench_function("generate_moves", |b| {
+        b.iter(|| positions.iter().map(|x| x.moves().len()).sum::<usize>())
+    });
+    c.bench_function("perft_3", |b| b.iter(|| positions[1].perft(3)));
+    c.bench_function("perft_3_vec", |b| b.iter(|| perft_vec(positions[1], 3)));
+}
+
+// Perft with the moves of each position generated into a Vec, to compare
+// against Position::perft(), which uses the fixed-capacity MoveList
+fn perft_vec(position: Position, depth: u32) -> u64 {
+    let mut moves = Vec::new();
+    position.generate_moves(&mut moves);
+    if depth <= 1 {
+        return if depth == 0 { 1 } else { moves.len() as u64 };
+    }
+
+    moves
+        .into_iter()
+        .map(|x| {
+            let mut next = position;
+            next.play_move(x);
+            perft_vec(next, depth - 1)
+        })
+        .sum()
+}
+
 criterion_group! {
     name = play_move;
     config = Criterion::default().sample_size(300);
     targets = play_moves
 }
-criterion_main!(play_move);
+criterion_group!(movegen, generate_moves);
+criterion_main!(play_move, movegen);
@@ -0,0 +1,488 @@
+// attacks.rs provides the attack tables used by move generation and
+// evaluation, all computed at compile time by const functions. Knights, kings
+// and pawns use simple per-square lookup tables, while rooks and bishops use
+// magic bitboards (https://www.chessprogramming.org/Magic_Bitboards) so that
+// the attacks of a sliding piece for any board occupancy can be found with a
+// single multiply, shift and table lookup. Tables of the squares between and
+// on the line through two aligned squares serve pin detection.
+
+// Magic numbers for each square, found offline by random trial. Each magic
+// maps every subset of the square's relevant occupancy mask to a unique index
+// (or to an index shared only by occupancies with identical attack sets).
+const ROOK_MAGICS: [u64; 64] = [
+    0x0080068051E04000,
+    0x0040001000402000,
+    0x0080100020008008,
+    0x4E000A0010208440,
+    0x4200040802002010,
+    0x0100010008020400,
+    0x9080608019000600,
+    0x8100020080204100,
+    0x4103800480400020,
+    0x8015004004802100,
+    0x000200108A002040,
+    0x0801000821001000,
+    0x0015000500080070,
+    0x0120800400800200,
+    0x0109000432001100,
+    0x020080055B000080,
+    0x0080004000402002,
+    0x5260848020004008,
+    0x2402020014402080,
+    0x3000808010000802,
+    0x0304018004810800,
+    0x0000808004000200,
+    0x0002040001500248,
+    0x0012020000408401,
+    0x8440008080004020,
+    0x0804200840100040,
+    0x0820008080201000,
+    0x2080100100082100,
+    0x0001000500100800,
+    0x00A1000900028400,
+    0x0100100400C80102,
+    0x000001120000A044,
+    0x800080C004800620,
+    0x4040081000202000,
+    0x0D08802008801000,
+    0x1000800800801004,
+    0x1004000801010010,
+    0x0402800400800200,
+    0x0004080204008110,
+    0x0000404082000401,
+    0x00C0118861408000,
+    0x1100220081020048,
+    0x09A0430420050010,
+    0x0000082200420010,
+    0x2110080004008080,
+    0x2004201040680104,
+    0x1106001451820008,
+    0x0002224104820014,
+    0x00800C8044210500,
+    0x02A0200040100040,
+    0x040100A0001E4100,
+    0x00204023108A0200,
+    0x2400080080040080,
+    0x1289008400020900,
+    0x0002088250010400,
+    0x0001006084010200,
+    0x0001023480002141,
+    0x0006400021810015,
+    0x8400100840200101,
+    0x40003000A1000825,
+    0x1002011008200402,
+    0x100D000400080201,
+    0x0020048806102904,
+    0x8401000020804201,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0x4C40240122060016,
+    0x8048110404004A80,
+    0x8004440410414020,
+    0x021C410060405000,
+    0x80CD1040D0480812,
+    0x0002021104000082,
+    0x08440082A8200001,
+    0x00202A0800841002,
+    0x0200C40810842088,
+    0x60C0081000C08901,
+    0x00A3D0040042510C,
+    0x1C00110400808541,
+    0x0400820211084005,
+    0x0000008860080800,
+    0x002002020202C000,
+    0x0400344E08040A81,
+    0x812800102098A080,
+    0x00202010823A2040,
+    0x4086400800830201,
+    0x5008012A22004000,
+    0x0004801C00A00000,
+    0x0000400200505400,
+    0x0480408401080820,
+    0x8000400029082824,
+    0x0008880804501000,
+    0x0001600048084100,
+    0x0108220624040400,
+    0x0008080000820002,
+    0xC804040010410041,
+    0x01080A0040208400,
+    0x2018030480A88800,
+    0x4040410020410810,
+    0x1108044010100210,
+    0x084A100400029800,
+    0x0801080100820C00,
+    0x8010400808108200,
+    0x0084008400020500,
+    0x0002004200290481,
+    0x0010150200032090,
+    0x8404042220404102,
+    0x0302080308004008,
+    0x1200420820000408,
+    0x0802002024200800,
+    0x4020824208000084,
+    0x000002020C008200,
+    0x2C40208081000882,
+    0x2082223441000401,
+    0x8804080081101020,
+    0x4401011002220808,
+    0x81020C4202100000,
+    0x4005004404040308,
+    0x0820400C42020001,
+    0x0020206421820010,
+    0x0150401001424008,
+    0x02A20242020C0608,
+    0x5020110109011200,
+    0x2050840108410401,
+    0x0100090880842108,
+    0x220008960142187A,
+    0x1111028880208820,
+    0x4400200042028200,
+    0x4400010802084206,
+    0x0000400242040100,
+    0x0002201104010944,
+];
+
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_OFFSETS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+// A Magic holds everything needed to index the shared sliding attack table
+// for a single square.
+#[derive(Copy, Clone)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    #[inline(always)]
+    const fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+// Every table below is built by const evaluation at compile time, so lookups
+// are plain indexing into static arrays.
+static KNIGHT_ATTACKS: [u64; 64] = leaper_table(&KNIGHT_OFFSETS);
+static KING_ATTACKS: [u64; 64] = leaper_table(&KING_OFFSETS);
+static PAWN_ATTACKS: [[u64; 64]; 2] = [
+    leaper_table(&[(1, -1), (1, 1)]),
+    leaper_table(&[(-1, -1), (-1, 1)]),
+];
+
+const ROOK_TABLE_SIZE: usize = table_size(&ROOK_DIRECTIONS);
+const BISHOP_TABLE_SIZE: usize = table_size(&BISHOP_DIRECTIONS);
+
+static ROOK_ENTRIES: [Magic; 64] = magic_entries(&ROOK_MAGICS, &ROOK_DIRECTIONS);
+static BISHOP_ENTRIES: [Magic; 64] = magic_entries(&BISHOP_MAGICS, &BISHOP_DIRECTIONS);
+static ROOK_ATTACKS: [u64; ROOK_TABLE_SIZE] = slider_table(&ROOK_ENTRIES, &ROOK_DIRECTIONS);
+static BISHOP_ATTACKS: [u64; BISHOP_TABLE_SIZE] = slider_table(&BISHOP_ENTRIES, &BISHOP_DIRECTIONS);
+
+static BETWEEN: [[u64; 64]; 64] = line_table(false);
+static LINE: [[u64; 64]; 64] = line_table(true);
+
+const fn on_board(rank: i32, file: i32) -> bool {
+    rank >= 0 && rank < 8 && file >= 0 && file < 8
+}
+
+// Build a table of attacks for a piece that "leaps" to a fixed set of
+// (rank, file) offsets from its square.
+const fn leaper_table(offsets: &[(i32, i32)]) -> [u64; 64] {
+    let mut table = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let (rank, file) = (sq as i32 / 8, sq as i32 % 8);
+        let mut i = 0;
+        while i < offsets.len() {
+            let (r, f) = (rank + offsets[i].0, file + offsets[i].1);
+            if on_board(r, f) {
+                table[sq] |= 1u64 << (r * 8 + f);
+            }
+            i += 1;
+        }
+        sq += 1;
+    }
+    table
+}
+
+// Walk each direction from 'sq' until the edge of the board or the first
+// occupied square, which is included in the attack set.
+const fn sliding_attacks(sq: usize, occupied: u64, directions: &[(i32, i32); 4]) -> u64 {
+    let mut attacks = 0;
+    let (rank, file) = (sq as i32 / 8, sq as i32 % 8);
+    let mut i = 0;
+    while i < 4 {
+        let (dr, df) = directions[i];
+        let (mut r, mut f) = (rank + dr, file + df);
+        while on_board(r, f) {
+            let bit = 1u64 << (r * 8 + f);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+            r += dr;
+            f += df;
+        }
+        i += 1;
+    }
+    attacks
+}
+
+// The relevant occupancy mask for a slider excludes the last square in each
+// direction, since a piece there can never block anything further along.
+const fn relevant_mask(sq: usize, directions: &[(i32, i32); 4]) -> u64 {
+    let mut mask = 0;
+    let (rank, file) = (sq as i32 / 8, sq as i32 % 8);
+    let mut i = 0;
+    while i < 4 {
+        let (dr, df) = directions[i];
+        let (mut r, mut f) = (rank + dr, file + df);
+        while on_board(r + dr, f + df) {
+            mask |= 1u64 << (r * 8 + f);
+            r += dr;
+            f += df;
+        }
+        i += 1;
+    }
+    mask
+}
+
+// The number of attack sets over all squares, one per relevant occupancy
+const fn table_size(directions: &[(i32, i32); 4]) -> usize {
+    let mut size = 0;
+    let mut sq = 0;
+    while sq < 64 {
+        size += 1 << relevant_mask(sq, directions).count_ones();
+        sq += 1;
+    }
+    size
+}
+
+const fn magic_entries(magic_numbers: &[u64; 64], directions: &[(i32, i32); 4]) -> [Magic; 64] {
+    let empty = Magic {
+        mask: 0,
+        magic: 0,
+        shift: 0,
+        offset: 0,
+    };
+    let mut entries = [empty; 64];
+    let mut offset = 0;
+    let mut sq = 0;
+    while sq < 64 {
+        let mask = relevant_mask(sq, directions);
+        let bits = mask.count_ones();
+        entries[sq] = Magic {
+            mask,
+            magic: magic_numbers[sq],
+            shift: 64 - bits,
+            offset,
+        };
+        offset += 1 << bits;
+        sq += 1;
+    }
+    entries
+}
+
+const fn slider_table<const N: usize>(
+    entries: &[Magic; 64],
+    directions: &[(i32, i32); 4],
+) -> [u64; N] {
+    let mut attacks = [0; N];
+    let mut sq = 0;
+    while sq < 64 {
+        let entry = &entries[sq];
+
+        // Enumerate every subset of the mask (Carry-Rippler trick)
+        let mut subset: u64 = 0;
+        loop {
+            attacks[entry.index(subset)] = sliding_attacks(sq, subset, directions);
+            subset = subset.wrapping_sub(entry.mask) & entry.mask;
+            if subset == 0 {
+                break;
+            }
+        }
+        sq += 1;
+    }
+    attacks
+}
+
+// For each pair of squares on a common rank, file or diagonal, either the
+// squares strictly between them or the whole line through both (including
+// the squares themselves). Pairs of unaligned squares map to 0.
+const fn line_table(whole_line: bool) -> [[u64; 64]; 64] {
+    let mut table = [[0; 64]; 64];
+    let mut from = 0;
+    while from < 64 {
+        let mut dirs = 0;
+        while dirs < 2 {
+            let directions = if dirs == 0 {
+                &ROOK_DIRECTIONS
+            } else {
+                &BISHOP_DIRECTIONS
+            };
+            let mut to = 0;
+            while to < 64 {
+                let to_bit = 1u64 << to;
+                if to != from && sliding_attacks(from, 0, directions) & to_bit != 0 {
+                    table[from][to] = if whole_line {
+                        (sliding_attacks(from, 0, directions) & sliding_attacks(to, 0, directions))
+                            | (1u64 << from)
+                            | to_bit
+                    } else {
+                        sliding_attacks(from, to_bit, directions)
+                            & sliding_attacks(to, 1u64 << from, directions)
+                    };
+                }
+                to += 1;
+            }
+            dirs += 1;
+        }
+        from += 1;
+    }
+    table
+}
+
+#[inline(always)]
+pub fn knight_attacks(sq: usize) -> u64 {
+    KNIGHT_ATTACKS[sq]
+}
+
+#[inline(always)]
+pub fn king_attacks(sq: usize) -> u64 {
+    KING_ATTACKS[sq]
+}
+
+// Squares attacked by a pawn of the given color standing on 'sq'.
+#[inline(always)]
+pub fn pawn_attacks(is_white: bool, sq: usize) -> u64 {
+    PAWN_ATTACKS[!is_white as usize][sq]
+}
+
+#[inline(always)]
+pub fn rook_attacks(sq: usize, occupied: u64) -> u64 {
+    ROOK_ATTACKS[ROOK_ENTRIES[sq].index(occupied)]
+}
+
+#[inline(always)]
+pub fn bishop_attacks(sq: usize, occupied: u64) -> u64 {
+    BISHOP_ATTACKS[BISHOP_ENTRIES[sq].index(occupied)]
+}
+
+#[inline(always)]
+pub fn queen_attacks(sq: usize, occupied: u64) -> u64 {
+    rook_attacks(sq, occupied) | bishop_attacks(sq, occupied)
+}
+
+// Squares strictly between two squares sharing a rank, file or diagonal
+#[inline(always)]
+pub fn between(a: usize, b: usize) -> u64 {
+    BETWEEN[a][b]
+}
+
+// The full rank, file or diagonal through two aligned squares
+#[inline(always)]
+pub fn line(a: usize, b: usize) -> u64 {
+    LINE[a][b]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Check magic lookups against the slow ray-walking generator for a
+    // handful of occupancies on every square.
+    macro_rules! test_slider_attacks {
+        ($test_name:ident, $lookup:ident, $directions:expr) => {
+            #[test]
+            fn $test_name() {
+                let occupancies = [
+                    0,
+                    u64::MAX,
+                    0x0000_FFFF_0000_FFFF,
+                    0x8142_2418_1824_4281,
+                    0x00FF_0000_0000_FF00,
+                    0x1234_5678_9ABC_DEF0,
+                ];
+                for sq in 0..64 {
+                    for &occupied in occupancies.iter() {
+                        assert_eq!(
+                            $lookup(sq, occupied),
+                            sliding_attacks(sq, occupied, &$directions)
+                        );
+                    }
+                }
+            }
+        };
+    }
+
+    test_slider_attacks!(rook_attacks_match_rays, rook_attacks, ROOK_DIRECTIONS);
+    test_slider_attacks!(bishop_attacks_match_rays, bishop_attacks, BISHOP_DIRECTIONS);
+
+    macro_rules! test_attacks {
+        ($test_name:ident, $attacks:expr, $expected:expr) => {
+            #[test]
+            fn $test_name() {
+                assert_eq!($attacks, $expected);
+            }
+        };
+    }
+
+    test_attacks!(knight_a1, knight_attacks(0), 0x20400);
+    test_attacks!(knight_e4, knight_attacks(28), 0x284400442800);
+    test_attacks!(king_a1, king_attacks(0), 0x302);
+    test_attacks!(king_h8, king_attacks(63), 0x40C0000000000000);
+    test_attacks!(w_pawn_e4, pawn_attacks(true, 28), 0x2800000000);
+    test_attacks!(b_pawn_e4, pawn_attacks(false, 28), 0x280000);
+    test_attacks!(w_pawn_a8, pawn_attacks(true, 56), 0);
+    test_attacks!(rook_a1_empty, rook_attacks(0, 0), 0x01010101010101FE);
+    test_attacks!(bishop_d4_empty, bishop_attacks(27, 0), 0x8041221400142241);
+    test_attacks!(queen_a1_blocked, queen_attacks(0, 0x302), 0x302);
+    test_attacks!(between_a1_h8, between(0, 63), 0x0040201008040200);
+    test_attacks!(between_a1_a8, between(0, 56), 0x0001010101010100);
+    test_attacks!(between_adjacent, between(0, 1), 0);
+    test_attacks!(between_unaligned, between(0, 10), 0);
+    test_attacks!(line_b2_c3, line(9, 18), 0x8040201008040201);
+    test_attacks!(line_d1_d5, line(3, 35), 0x0808080808080808);
+    test_attacks!(line_unaligned, line(0, 10), 0);
+
+    #[test]
+    fn between_lies_on_line() {
+        for a in 0..64 {
+            for b in 0..64 {
+                assert_eq!(between(a, b), between(b, a));
+                assert_eq!(line(a, b), line(b, a));
+                assert_eq!(between(a, b) & !line(a, b), 0);
+                if a != b && line(a, b) != 0 {
+                    let ranks = (a / 8).abs_diff(b / 8);
+                    let files = (a % 8).abs_diff(b % 8);
+                    assert_eq!(between(a, b).count_ones() as usize, ranks.max(files) - 1);
+                    let ends = 1u64 << a | 1u64 << b;
+                    assert_eq!(line(a, b) & ends, ends);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,577 @@
+// datagen.rs generates training data for the evaluation by playing games of
+// the engine against itself. Each game starts from a few random moves, then
+// every move is chosen by a fixed nodes (or depth) search. The quiet positions
+// of the game are written out with their search score and the game result.
+//
+// Two output formats are written side by side:
+//
+//   <output>.txt   one "<fen> | <score> | <result>" line per position, with the
+//                  score in centipawns from white's point of view, and the
+//                  result as 1-0, 0-1 or 1/2-1/2. The tune command reads these.
+//   <output>.bin   fixed size 32 byte records, described at encode().
+
+use crate::movepick::History;
+use crate::position::{Position, B_PIECES, W_PIECES};
+use crate::search::{self, Limits, SearchResult, Searcher, MATE_BOUND};
+use crate::tt::TranspositionTable;
+use crate::types::Color;
+use crate::variant::Variant;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+const USAGE: &str = "Usage: challenger-rs datagen <output name> [games=N] [threads=N] \
+                     [nodes=N | depth=N] [random=N] [seed=N]";
+
+// Games are drawn once they reach this length
+const MAX_PLIES: usize = 400;
+
+// Random openings that search scores beyond this are discarded
+const MAX_OPENING_SCORE: i32 = 400;
+
+// Size of the transposition table of each thread
+const HASH_MB: usize = 1;
+
+pub const RECORD_SIZE: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub games: usize,
+    pub threads: usize,
+    pub limits: Limits,
+    pub random_plies: usize,
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            games: 100,
+            threads: 1,
+            limits: Limits {
+                nodes: Some(5000),
+                ..Limits::default()
+            },
+            random_plies: 8,
+            seed: 1,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GameResult {
+    BlackWin,
+    Draw,
+    WhiteWin,
+}
+
+impl GameResult {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::WhiteWin => "1-0",
+        }
+    }
+}
+
+// A position from a game, with its score from white's point of view
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Record {
+    pub position: Position,
+    pub score: i16,
+    pub result: GameResult,
+}
+
+impl Record {
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} | {} | {}",
+            self.position.to_fen(),
+            self.score,
+            self.result.as_str()
+        )
+    }
+
+    // Binary record layout (multi-byte values little-endian):
+    //
+    //   bytes  0- 7  occupancy bitboard
+    //   bytes  8-23  4 bit piece codes of the occupied squares from a1 to h8,
+    //                low nibble first. Codes are indexed like the 'pieces'
+    //                array, with black pieces starting at 8.
+    //   byte     24  bit 0: black to move, bits 1-4: castling rights KQkq
+    //   byte     25  en passant square, or 64 if there is none
+    //   byte     26  halfmove clock
+    //   byte     27  result: 0 black win, 1 draw, 2 white win
+    //   bytes 28-29  score (i16)
+    //   bytes 30-31  fullmove number (u16)
+    pub fn encode(&self) -> [u8; RECORD_SIZE] {
+        let position = &self.position;
+        let mut bytes = [0u8; RECORD_SIZE];
+
+        let occupied = position.occupied(Color::White) | position.occupied(Color::Black);
+        bytes[0..8].copy_from_slice(&occupied.0.to_le_bytes());
+
+        for (index, sq) in occupied.enumerate() {
+            let piece = position.piece_on(sq).unwrap();
+            let code = piece.piece_type.index() as u8 | (piece.color.index() as u8) << 3;
+            bytes[8 + index / 2] |= code << (4 * (index % 2));
+        }
+
+        bytes[24] = !position.is_white_move as u8;
+        for (i, rook) in position.castling_rights().iter().enumerate() {
+            bytes[24] |= (rook.is_some() as u8) << (i + 1);
+        }
+        bytes[25] = match position.passant_sq {
+            0 => 64,
+            sq => sq.trailing_zeros() as u8,
+        };
+        bytes[26] = position.hlf_clock;
+        bytes[27] = self.result as u8;
+        bytes[28..30].copy_from_slice(&self.score.to_le_bytes());
+        bytes[30..32].copy_from_slice(&position.full_num.to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; RECORD_SIZE]) -> Result<Record, String> {
+        let mut pieces = [0u64; 14];
+        let mut occupancy = [0u8; 8];
+        occupancy.copy_from_slice(&bytes[0..8]);
+        let mut occupied = u64::from_le_bytes(occupancy);
+        if occupied.count_ones() > 32 {
+            return Err(String::from("Too many pieces in record"));
+        }
+
+        let mut index = 0;
+        while occupied != 0 {
+            let sq = occupied.trailing_zeros();
+            let code = (bytes[8 + index / 2] >> (4 * (index % 2))) as usize & 0xF;
+            let piece = (code & 7) + (code >> 3) * 7;
+            if code & 7 > 5 {
+                return Err(format!("Invalid piece code {}", code));
+            }
+            pieces[piece] |= 1u64 << sq;
+            pieces[if piece < 7 { W_PIECES } else { B_PIECES }] |= 1u64 << sq;
+            index += 1;
+            occupied &= occupied - 1;
+        }
+
+        let result = match bytes[27] {
+            0 => GameResult::BlackWin,
+            1 => GameResult::Draw,
+            2 => GameResult::WhiteWin,
+            x => return Err(format!("Invalid result {}", x)),
+        };
+        let passant_sq = match bytes[25] {
+            64 => 0,
+            sq if sq < 64 => 1u64 << sq,
+            sq => return Err(format!("Invalid en passant square {}", sq)),
+        };
+
+        // Castling rights are recorded by side only, with the rooks taken to
+        // stand in the corners as in standard chess
+        let castling_rooks = [7, 0, 63, 56]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| bytes[24] & 2 << i != 0)
+            .fold(0, |rooks, (_, sq)| rooks | 1u64 << sq);
+        let position = Position {
+            pieces,
+            passant_sq,
+            castling_rooks,
+            is_white_move: bytes[24] & 1 == 0,
+            hlf_clock: bytes[26],
+            full_num: u16::from_le_bytes([bytes[30], bytes[31]]),
+            variant: Variant::Standard,
+            checks: [0; 2],
+            hands: [[0; 5]; 2],
+            promoted: 0,
+        };
+        Ok(Record {
+            position,
+            score: i16::from_le_bytes([bytes[28], bytes[29]]),
+            result,
+        })
+    }
+}
+
+// xorshift64*, seeded per game so that every game can be replayed on its own
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64, game: usize) -> Rng {
+        // splitmix64 scrambles nearby seeds into unrelated states
+        let mut z = seed ^ (game as u64).wrapping_mul(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545F4914F6CDD1D) >> 32) as usize % n
+    }
+}
+
+// The transposition table and history tables of a thread. They are kept
+// from one move of a game to the next, and cleared between games so that
+// every game plays the same whichever thread plays it.
+pub struct Tables {
+    tt: TranspositionTable,
+    history: History,
+}
+
+impl Tables {
+    pub fn new() -> Tables {
+        Tables {
+            tt: TranspositionTable::new(HASH_MB),
+            history: History::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.tt.clear();
+        self.history.clear();
+    }
+
+    fn search(&mut self, position: &Position, game: &[u64], limits: Limits) -> SearchResult {
+        Searcher::new(limits, &self.tt, &mut self.history, None).search(position, game, |_| ())
+    }
+}
+
+impl Default for Tables {
+    fn default() -> Self {
+        Tables::new()
+    }
+}
+
+// Play random moves from the start position until reaching a playable
+// opening: the game is not over, and neither side is clearly winning.
+fn random_opening(config: &Config, rng: &mut Rng, tables: &mut Tables) -> (Position, Vec<u64>) {
+    let check_limits = Limits {
+        depth: Some(4),
+        ..Limits::default()
+    };
+
+    loop {
+        let mut position = Position::new();
+        let mut history = vec![];
+        for _ in 0..config.random_plies {
+            let moves = position.moves();
+            if moves.is_empty() {
+                break;
+            }
+            history.push(position.hash());
+            position.play_move(moves[rng.below(moves.len())]);
+        }
+
+        if position.moves().is_empty() {
+            continue;
+        }
+        let result = tables.search(&position, &history, check_limits.clone());
+        if result.score.abs() <= MAX_OPENING_SCORE {
+            return (position, history);
+        }
+    }
+}
+
+// Play one game, returning its quiet positions
+pub fn play_game(config: &Config, game: usize, tables: &mut Tables) -> Vec<Record> {
+    tables.clear();
+    let mut rng = Rng::new(config.seed, game);
+    let (mut position, mut history) = random_opening(config, &mut rng, tables);
+    let mut records = vec![];
+
+    let result = loop {
+        let moves = position.moves();
+        if moves.is_empty() {
+            break match (position.is_in_check(), position.is_white_move) {
+                (false, _) => GameResult::Draw,
+                (true, true) => GameResult::BlackWin,
+                (true, false) => GameResult::WhiteWin,
+            };
+        }
+
+        let hash = position.hash();
+        let repetitions = history.iter().filter(|&&x| x == hash).count();
+        if position.hlf_clock >= 100
+            || position.is_insufficient_material()
+            || repetitions >= 2
+            || history.len() >= MAX_PLIES
+        {
+            break GameResult::Draw;
+        }
+
+        let searched = tables.search(&position, &history, config.limits.clone());
+        let best_move = searched.best_move.unwrap();
+        let score = if position.is_white_move {
+            searched.score
+        } else {
+            -searched.score
+        };
+
+        // A forced mate decides the game
+        if score.abs() >= MATE_BOUND {
+            break if score > 0 {
+                GameResult::WhiteWin
+            } else {
+                GameResult::BlackWin
+            };
+        }
+        if !position.is_in_check() && !position.is_tactical(best_move) {
+            records.push(Record {
+                position,
+                score: score as i16,
+                result: GameResult::Draw,
+            });
+        }
+
+        history.push(hash);
+        position.play_move(best_move);
+    };
+
+    for record in &mut records {
+        record.result = result;
+    }
+    records
+}
+
+// Play the configured games over several threads. The records of each game
+// are passed to 'output' in game order, regardless of which thread played it.
+pub fn generate<F: FnMut(usize, &[Record])>(config: &Config, mut output: F) {
+    let next_game = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            let sender = sender.clone();
+            let next_game = &next_game;
+            let worker = thread::Builder::new().stack_size(search::STACK_SIZE);
+            let play_games = move || {
+                let mut tables = Tables::new();
+                loop {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= config.games {
+                        break;
+                    }
+                    sender
+                        .send((game, play_game(config, game, &mut tables)))
+                        .unwrap();
+                }
+            };
+            worker.spawn_scoped(scope, play_games).unwrap();
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next_output = 0;
+        for (game, records) in receiver {
+            pending.insert(game, records);
+            while let Some(records) = pending.remove(&next_output) {
+                output(next_output, &records);
+                next_output += 1;
+            }
+        }
+    });
+}
+
+fn parse_config(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+    for arg in args {
+        let (key, value) = arg.split_once('=').ok_or(USAGE)?;
+        let invalid = || format!("Invalid value for {}: {}", key, value);
+        let number: u64 = value.parse().map_err(|_| invalid())?;
+        match key {
+            "games" => config.games = number as usize,
+            "threads" => config.threads = number as usize,
+            "random" => config.random_plies = number as usize,
+            "seed" => config.seed = number,
+            "nodes" => {
+                config.limits = Limits {
+                    nodes: Some(number),
+                    ..Limits::default()
+                }
+            }
+            "depth" => {
+                config.limits = Limits {
+                    depth: Some(number as u32),
+                    ..Limits::default()
+                }
+            }
+            _ => return Err(format!("Unknown datagen option '{}'\n{}", key, USAGE)),
+        }
+    }
+    Ok(config)
+}
+
+// Entry point for the 'datagen' subcommand
+pub fn run(args: &[String]) -> Result<(), String> {
+    let name = args.first().ok_or(USAGE)?;
+    let config = parse_config(&args[1..])?;
+
+    let create = |path: String| {
+        File::create(&path)
+            .map(BufWriter::new)
+            .map_err(|e| format!("{}: {}", path, e))
+    };
+    let mut text = create(format!("{}.txt", name))?;
+    let mut binary = create(format!("{}.bin", name))?;
+
+    let mut positions = 0;
+    let mut error = Ok(());
+    generate(&config, |game, records| {
+        for record in records {
+            let written = writeln!(text, "{}", record.to_text())
+                .and_then(|_| binary.write_all(&record.encode()));
+            if let Err(e) = written {
+                error = Err(format!("{}: {}", name, e));
+            }
+        }
+        positions += records.len();
+        println!(
+            "Game {:>6} / {}: {} positions",
+            game + 1,
+            config.games,
+            positions
+        );
+    });
+    error?;
+
+    text.flush().map_err(|e| e.to_string())?;
+    binary.flush().map_err(|e| e.to_string())?;
+    println!("Wrote {} positions to {1}.txt and {1}.bin", positions, name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tune;
+
+    fn quick_config() -> Config {
+        Config {
+            games: 3,
+            threads: 2,
+            limits: Limits {
+                depth: Some(1),
+                ..Limits::default()
+            },
+            random_plies: 8,
+            seed: 7,
+        }
+    }
+
+    // Binary records should decode to exactly the position they encode
+    macro_rules! test_record_round_trip {
+        ($test_name:ident, $fen:literal, $score:literal, $result:expr) => {
+            #[test]
+            fn $test_name() {
+                let record = Record {
+                    position: Position::from($fen),
+                    score: $score,
+                    result: $result,
+                };
+                assert_eq!(Record::decode(&record.encode()), Ok(record));
+            }
+        };
+    }
+
+    test_record_round_trip!(
+        record_startpos,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        17,
+        GameResult::Draw
+    );
+    test_record_round_trip!(
+        record_passant,
+        "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w Kq c6 0 2",
+        -250,
+        GameResult::BlackWin
+    );
+    test_record_round_trip!(
+        record_endgame,
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 37 95",
+        1234,
+        GameResult::WhiteWin
+    );
+    test_record_round_trip!(
+        record_long_game,
+        "8/8/4k3/8/8/3QK3/8/8 w - - 12 300",
+        -3,
+        GameResult::Draw
+    );
+
+    #[test]
+    fn invalid_record() {
+        let mut bytes = [0u8; RECORD_SIZE];
+        bytes[27] = 3;
+        assert!(Record::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn text_records_can_be_tuned() {
+        let record = Record {
+            position: Position::from("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+            score: -35,
+            result: GameResult::BlackWin,
+        };
+        let text = record.to_text();
+        assert_eq!(
+            text,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1 | -35 | 0-1"
+        );
+        assert_eq!(tune::parse_entry(&text), Ok((record.position, 0.0)));
+    }
+
+    #[test]
+    fn games_are_deterministic() {
+        let config = quick_config();
+        let mut tables = Tables::new();
+        let first = play_game(&config, 1, &mut tables);
+        // The tables left over from a game do not change the next one
+        play_game(&config, 2, &mut tables);
+        assert_eq!(first, play_game(&config, 1, &mut tables));
+        assert_eq!(first, play_game(&config, 1, &mut Tables::new()));
+        assert!(!first.is_empty());
+        assert!(first.iter().all(|x| x.result == first[0].result));
+        assert_ne!(first, play_game(&config, 2, &mut tables));
+    }
+
+    #[test]
+    fn generate_outputs_games_in_order() {
+        let config = quick_config();
+        let mut games = vec![];
+        generate(&config, |game, records| {
+            games.push((game, records.to_vec()))
+        });
+
+        let mut tables = Tables::new();
+        let expected: Vec<_> = (0..3)
+            .map(|x| (x, play_game(&config, x, &mut tables)))
+            .collect();
+        assert_eq!(games, expected);
+    }
+
+    #[test]
+    fn config_arguments() {
+        let args: Vec<String> = ["games=5", "threads=3", "depth=6", "seed=9"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let config = parse_config(&args).unwrap();
+        assert_eq!(config.games, 5);
+        assert_eq!(config.threads, 3);
+        assert_eq!(config.limits.depth, Some(6));
+        assert_eq!(config.limits.nodes, None);
+        assert_eq!(config.seed, 9);
+
+        assert!(parse_config(&[String::from("depth")]).is_err());
+        assert!(parse_config(&[String::from("colour=red")]).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+// epdtest.rs runs test suites of positions in Extended Position Description
+// (EPD) format, such as Win At Chess, and reports how many the engine solves.
+// Each line of a suite holds the first four fields of a FEN followed by
+// operations ending in ';':
+//
+//   bm <moves>   the position is solved by playing one of these best moves
+//   am <moves>   the position is solved by avoiding all of these moves
+//   dm <n>       the position is solved by finding a mate in n moves
+//   id "<name>"  names the position in the report
+//
+// Moves are written in SAN, or in long algebraic notation. Other operations
+// are ignored, apart from the 'hmvc' and 'fmvn' move counters.
+//
+// Usage: challenger-rs epdtest <epd file> [time=MS | depth=N | nodes=N]
+//        [threads=N] [hash=N]
+
+use crate::movepick::History;
+use crate::position::{self, Move, Position};
+use crate::search::{Limits, SearchResult, Searcher, MATE};
+use crate::tt::TranspositionTable;
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: challenger-rs epdtest <epd file> [time=MS | depth=N | nodes=N] \
+                     [threads=N] [hash=N]";
+
+const DEFAULT_MOVETIME: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub limits: Limits,
+    pub threads: usize,
+    pub hash: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            limits: Limits {
+                movetime: Some(DEFAULT_MOVETIME),
+                ..Limits::default()
+            },
+            threads: 1,
+            hash: 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Epd {
+    pub position: Position,
+    pub id: Option<String>,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    pub mate: Option<u32>,
+}
+
+// Split the operations of an EPD line at the semicolons outside of quotes
+fn split_operations(text: &str) -> Vec<&str> {
+    let mut operations = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                operations.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    operations.push(text[start..].trim());
+    operations.retain(|x| !x.is_empty());
+    operations
+}
+
+fn parse_moves(position: &Position, operands: &str) -> Result<Vec<Move>, String> {
+    let legal = position.moves();
+    operands
+        .split_whitespace()
+        .map(|x| {
+            legal
+                .iter()
+                .copied()
+                .find(|&m| position::move_to_str(m) == x)
+                .map_or_else(|| position.parse_san(x), Ok)
+        })
+        .collect()
+}
+
+pub fn parse_epd(line: &str) -> Result<Epd, String> {
+    let fields: Vec<&str> = line.splitn(5, char::is_whitespace).collect();
+    if fields.len() < 4 {
+        return Err(format!("Invalid EPD: {}", line));
+    }
+    let operations = split_operations(fields.get(4).unwrap_or(&""));
+    let operation = |name: &str| {
+        operations.iter().find_map(|x| {
+            let (opcode, operands) = x.split_once(char::is_whitespace)?;
+            Some(operands.trim()).filter(|_| opcode == name)
+        })
+    };
+
+    let fen = format!(
+        "{} {} {} {} {} {}",
+        fields[0],
+        fields[1],
+        fields[2],
+        fields[3],
+        operation("hmvc").unwrap_or("0"),
+        operation("fmvn").unwrap_or("1")
+    );
+    let position = Position::try_from(&fen).map_err(|e| format!("{} in: {}", e, line))?;
+
+    let moves = |name| -> Result<Vec<Move>, String> {
+        let moves = operation(name).map_or(Ok(vec![]), |x| parse_moves(&position, x));
+        moves.map_err(|e| format!("{} in: {}", e, line))
+    };
+    let mate = match operation("dm") {
+        Some(x) => Some(x.parse().map_err(|_| format!("Invalid dm in: {}", line))?),
+        None => None,
+    };
+    Ok(Epd {
+        position,
+        id: operation("id").map(|x| x.trim_matches('"').to_string()),
+        best_moves: moves("bm")?,
+        avoid_moves: moves("am")?,
+        mate,
+    })
+}
+
+impl Epd {
+    pub fn is_solved(&self, result: &SearchResult) -> bool {
+        let best_move = match result.best_move {
+            Some(best_move) => best_move,
+            None => return false,
+        };
+        let mate_found = match self.mate {
+            Some(moves) => result.score > MATE - 2 * moves as i32,
+            None => true,
+        };
+        mate_found
+            && (self.best_moves.is_empty() || self.best_moves.contains(&best_move))
+            && !self.avoid_moves.contains(&best_move)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub result: SearchResult,
+    pub solved: bool,
+    // When the search settled on a solution for good, or its total time if
+    // the position was not solved
+    pub time: Duration,
+}
+
+// Search one position from scratch, as at the start of a new game
+pub fn run_test(epd: &Epd, config: &Config) -> Outcome {
+    let tt = TranspositionTable::new(config.hash);
+    let mut histories: Vec<History> = (0..config.threads).map(|_| History::new()).collect();
+    let (history, helpers) = histories.split_first_mut().unwrap();
+    let limits = Limits {
+        mate: epd.mate,
+        ..config.limits.clone()
+    };
+
+    let start = Instant::now();
+    let mut solved_at = None;
+    let result = Searcher::new(limits, &tt, history, None).search_with_helpers(
+        helpers,
+        &epd.position,
+        &[],
+        |x| match (epd.is_solved(x), solved_at) {
+            (true, None) => solved_at = Some(start.elapsed()),
+            (false, _) => solved_at = None,
+            _ => (),
+        },
+    );
+
+    let solved = epd.is_solved(&result);
+    Outcome {
+        solved,
+        time: match solved_at {
+            Some(time) if solved => time,
+            _ => start.elapsed(),
+        },
+        result,
+    }
+}
+
+fn parse_config(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+    for arg in args {
+        let (key, value) = arg.split_once('=').ok_or(USAGE)?;
+        let number: u64 = match value.parse() {
+            Ok(x) if x > 0 => x,
+            _ => return Err(format!("Invalid value for {}: {}", key, value)),
+        };
+        match key {
+            "time" => {
+                config.limits = Limits {
+                    movetime: Some(Duration::from_millis(number)),
+                    ..Limits::default()
+                }
+            }
+            "depth" => {
+                config.limits = Limits {
+                    depth: Some(number as u32),
+                    ..Limits::default()
+                }
+            }
+            "nodes" => {
+                config.limits = Limits {
+                    nodes: Some(number),
+                    ..Limits::default()
+                }
+            }
+            "threads" => config.threads = number as usize,
+            "hash" => config.hash = number as usize,
+            _ => return Err(format!("Unknown epdtest option '{}'\n{}", key, USAGE)),
+        }
+    }
+    Ok(config)
+}
+
+// Entry point for the 'epdtest' subcommand
+pub fn run(args: &[String]) -> Result<(), String> {
+    let path = args.first().ok_or(USAGE)?;
+    let config = parse_config(&args[1..])?;
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let suite = text
+        .lines()
+        .filter(|x| !x.trim().is_empty())
+        .map(parse_epd)
+        .collect::<Result<Vec<Epd>, String>>()?;
+
+    let mut solved = 0;
+    let mut total_time = Duration::default();
+    for (i, epd) in suite.iter().enumerate() {
+        let outcome = run_test(epd, &config);
+        let best_move = outcome
+            .result
+            .best_move
+            .map_or(String::from("(none)"), |x| epd.position.move_to_san(x));
+        println!(
+            "{:>4} {:<16} {:<7} {:<8} {:>8} ms",
+            i + 1,
+            epd.id.as_deref().unwrap_or("-"),
+            if outcome.solved { "solved" } else { "failed" },
+            best_move,
+            outcome.time.as_millis()
+        );
+        solved += outcome.solved as usize;
+        total_time += outcome.time;
+    }
+
+    println!("===========================");
+    println!(
+        "Solved          : {} / {} ({:.1}%)",
+        solved,
+        suite.len(),
+        100.0 * solved as f64 / suite.len().max(1) as f64
+    );
+    println!("Total time (ms) : {}", total_time.as_millis());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::str_to_move;
+
+    const WAC_1: &str =
+        r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#;
+
+    fn config(depth: u32) -> Config {
+        Config {
+            limits: Limits {
+                depth: Some(depth),
+                ..Limits::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn parse_operations() {
+        let epd = parse_epd(WAC_1).unwrap();
+        assert_eq!(
+            epd.position.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+        assert_eq!(
+            epd.best_moves,
+            vec![str_to_move("g3g6", epd.position).unwrap()]
+        );
+        assert!(epd.avoid_moves.is_empty());
+        assert_eq!(epd.mate, None);
+
+        let epd = parse_epd(
+            r#"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - am f3 g2g4; bm e4 d4; id "A; B"; hmvc 3; fmvn 7; c0 "ignored";"#,
+        )
+        .unwrap();
+        assert_eq!(epd.id.as_deref(), Some("A; B"));
+        assert_eq!(epd.best_moves.len(), 2);
+        assert_eq!(epd.avoid_moves.len(), 2);
+        assert!(epd.position.to_fen().ends_with(" 3 7"));
+
+        let epd = parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - dm 1;").unwrap();
+        assert_eq!(epd.mate, Some(1));
+        assert_eq!(epd.id, None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_epd("8/8/8/8").is_err());
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra9;").is_err());
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - dm one;").is_err());
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - fmvn 70000;").is_err());
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - hmvc x;").is_err());
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K9 w - - bm Ra8;").is_err());
+        assert!(parse_epd("6k1/5ppp/8/8/8/8/5PPP/R5K1 x - - bm Ra8;").is_err());
+    }
+
+    #[test]
+    fn solve_best_move() {
+        let epd = parse_epd(WAC_1).unwrap();
+        let outcome = run_test(&epd, &config(6));
+        assert!(outcome.solved);
+        assert_eq!(outcome.result.best_move, epd.best_moves.first().copied());
+    }
+
+    #[test]
+    fn solve_avoid_move() {
+        // The check on d8 gives the queen away to the king
+        let epd = parse_epd("4k3/4r3/8/8/8/8/4P3/3QK3 w - - am Qd8+;").unwrap();
+        let result = |mov: &str| SearchResult {
+            best_move: Some(str_to_move(mov, epd.position).unwrap()),
+            score: 0,
+            depth: 1,
+            nodes: 0,
+            pv: vec![],
+        };
+        assert!(!epd.is_solved(&result("d1d8")));
+        assert!(epd.is_solved(&result("d1d2")));
+        assert!(run_test(&epd, &config(4)).solved);
+    }
+
+    #[test]
+    fn solve_mate() {
+        let epd = parse_epd("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - dm 2;").unwrap();
+        let outcome = run_test(&epd, &config(20));
+        assert!(outcome.solved);
+        assert_eq!(outcome.result.score, MATE - 3);
+
+        // A mate in two is not found in a handful of nodes
+        let limits = Limits {
+            nodes: Some(10),
+            ..Limits::default()
+        };
+        let outcome = run_test(
+            &epd,
+            &Config {
+                limits,
+                ..Config::default()
+            },
+        );
+        assert!(!outcome.solved);
+    }
+
+    #[test]
+    fn parse_args() {
+        let args = |x: &str| -> Vec<String> { x.split_whitespace().map(String::from).collect() };
+        assert_eq!(parse_config(&args("")), Ok(Config::default()));
+        let config = parse_config(&args("depth=8 threads=2 hash=64")).unwrap();
+        assert_eq!(config.limits.depth, Some(8));
+        assert_eq!(config.limits.movetime, None);
+        assert_eq!((config.threads, config.hash), (2, 64));
+        assert_eq!(
+            parse_config(&args("time=50")).unwrap().limits.movetime,
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(
+            parse_config(&args("nodes=1000")).unwrap().limits.nodes,
+            Some(1000)
+        );
+        assert!(parse_config(&args("depth=0")).is_err());
+        assert!(parse_config(&args("depth")).is_err());
+        assert!(parse_config(&args("speed=3")).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// eval_params.rs holds the weights used by challenger's evaluation function.
+// These are hand-picked starting values, not yet tuned: material from PeSTO
+// and piece-square tables from the Simplified Evaluation Function, both on
+// the Chess Programming Wiki, with the remaining terms set by hand.
+// Running the 'tune' subcommand (see tune.rs) replaces this file.
+
+use crate::evaluate::Score;
+
+// Material values, indexed like the 'pieces' array
+#[rustfmt::skip]
+pub const PIECE_VALUES: [Score; 6] = [
+    Score(  82,   94),
+    Score( 477,  512),
+    Score( 337,  281),
+    Score( 365,  297),
+    Score(1025,  936),
+    Score(   0,    0),
+];
+
+// Piece square tables from white's point of view, with the 8th rank first
+#[rustfmt::skip]
+pub const PST: [[Score; 64]; 6] = [
+    [ // Pawn
+        Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0),
+        Score(  50,   80), Score(  50,   80), Score(  50,   80), Score(  50,   80), Score(  50,   80), Score(  50,   80), Score(  50,   80), Score(  50,   80),
+        Score(  10,   50), Score(  10,   50), Score(  20,   50), Score(  30,   50), Score(  30,   50), Score(  20,   50), Score(  10,   50), Score(  10,   50),
+        Score(   5,   30), Score(   5,   30), Score(  10,   30), Score(  25,   30), Score(  25,   30), Score(  10,   30), Score(   5,   30), Score(   5,   30),
+        Score(   0,   20), Score(   0,   20), Score(   0,   20), Score(  20,   20), Score(  20,   20), Score(   0,   20), Score(   0,   20), Score(   0,   20),
+        Score(   5,   10), Score(  -5,   10), Score( -10,   10), Score(   0,   10), Score(   0,   10), Score( -10,   10), Score(  -5,   10), Score(   5,   10),
+        Score(   5,    0), Score(  10,    0), Score(  10,    0), Score( -20,    0), Score( -20,    0), Score(  10,    0), Score(  10,    0), Score(   5,    0),
+        Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0),
+    ],
+    [ // Rook
+        Score(   0,    5), Score(   0,    5), Score(   0,    5), Score(   0,    5), Score(   0,    5), Score(   0,    5), Score(   0,    5), Score(   0,    5),
+        Score(   5,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(   5,   10),
+        Score(  -5,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(  -5,    0),
+        Score(  -5,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(  -5,    0),
+        Score(  -5,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(  -5,    0),
+        Score(  -5,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(  -5,    0),
+        Score(  -5,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(  -5,    0),
+        Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   5,    0), Score(   5,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0),
+    ],
+    [ // Knight
+        Score( -50,  -50), Score( -40,  -40), Score( -30,  -30), Score( -30,  -30), Score( -30,  -30), Score( -30,  -30), Score( -40,  -40), Score( -50,  -50),
+        Score( -40,  -40), Score( -20,  -20), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score( -20,  -20), Score( -40,  -40),
+        Score( -30,  -30), Score(   0,    0), Score(  10,   10), Score(  15,   15), Score(  15,   15), Score(  10,   10), Score(   0,    0), Score( -30,  -30),
+        Score( -30,  -30), Score(   5,    5), Score(  15,   15), Score(  20,   20), Score(  20,   20), Score(  15,   15), Score(   5,    5), Score( -30,  -30),
+        Score( -30,  -30), Score(   0,    0), Score(  15,   15), Score(  20,   20), Score(  20,   20), Score(  15,   15), Score(   0,    0), Score( -30,  -30),
+        Score( -30,  -30), Score(   5,    5), Score(  10,   10), Score(  15,   15), Score(  15,   15), Score(  10,   10), Score(   5,    5), Score( -30,  -30),
+        Score( -40,  -40), Score( -20,  -20), Score(   0,    0), Score(   5,    5), Score(   5,    5), Score(   0,    0), Score( -20,  -20), Score( -40,  -40),
+        Score( -50,  -50), Score( -40,  -40), Score( -30,  -30), Score( -30,  -30), Score( -30,  -30), Score( -30,  -30), Score( -40,  -40), Score( -50,  -50),
+    ],
+    [ // Bishop
+        Score( -20,  -20), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -20,  -20),
+        Score( -10,  -10), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score( -10,  -10),
+        Score( -10,  -10), Score(   0,    0), Score(   5,    5), Score(  10,   10), Score(  10,   10), Score(   5,    5), Score(   0,    0), Score( -10,  -10),
+        Score( -10,  -10), Score(   5,    5), Score(   5,    5), Score(  10,   10), Score(  10,   10), Score(   5,    5), Score(   5,    5), Score( -10,  -10),
+        Score( -10,  -10), Score(   0,    0), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(   0,    0), Score( -10,  -10),
+        Score( -10,  -10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score(  10,   10), Score( -10,  -10),
+        Score( -10,  -10), Score(   5,    5), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   5,    5), Score( -10,  -10),
+        Score( -20,  -20), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -10,  -10), Score( -20,  -20),
+    ],
+    [ // Queen
+        Score( -20,  -20), Score( -10,  -10), Score( -10,  -10), Score(  -5,   -5), Score(  -5,   -5), Score( -10,  -10), Score( -10,  -10), Score( -20,  -20),
+        Score( -10,  -10), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score( -10,  -10),
+        Score( -10,  -10), Score(   0,    0), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   0,    0), Score( -10,  -10),
+        Score(  -5,   -5), Score(   0,    0), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   0,    0), Score(  -5,   -5),
+        Score(   0,   -5), Score(   0,    0), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   0,    0), Score(  -5,   -5),
+        Score( -10,  -10), Score(   5,    0), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   5,    5), Score(   0,    0), Score( -10,  -10),
+        Score( -10,  -10), Score(   0,    0), Score(   5,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score( -10,  -10),
+        Score( -20,  -20), Score( -10,  -10), Score( -10,  -10), Score(  -5,   -5), Score(  -5,   -5), Score( -10,  -10), Score( -10,  -10), Score( -20,  -20),
+    ],
+    [ // King
+        Score( -30,  -50), Score( -40,  -40), Score( -40,  -30), Score( -50,  -20), Score( -50,  -20), Score( -40,  -30), Score( -40,  -40), Score( -30,  -50),
+        Score( -30,  -30), Score( -40,  -20), Score( -40,  -10), Score( -50,    0), Score( -50,    0), Score( -40,  -10), Score( -40,  -20), Score( -30,  -30),
+        Score( -30,  -30), Score( -40,  -10), Score( -40,   20), Score( -50,   30), Score( -50,   30), Score( -40,   20), Score( -40,  -10), Score( -30,  -30),
+        Score( -30,  -30), Score( -40,  -10), Score( -40,   30), Score( -50,   40), Score( -50,   40), Score( -40,   30), Score( -40,  -10), Score( -30,  -30),
+        Score( -20,  -30), Score( -30,  -10), Score( -30,   30), Score( -40,   40), Score( -40,   40), Score( -30,   30), Score( -30,  -10), Score( -20,  -30),
+        Score( -10,  -30), Score( -20,  -10), Score( -20,   20), Score( -20,   30), Score( -20,   30), Score( -20,   20), Score( -20,  -10), Score( -10,  -30),
+        Score(  20,  -30), Score(  20,  -30), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(   0,    0), Score(  20,  -30), Score(  20,  -30),
+        Score(  20,  -50), Score(  30,  -30), Score(  10,  -30), Score(   0,  -30), Score(   0,  -30), Score(  10,  -30), Score(  30,  -30), Score(  20,  -50),
+    ],
+];
+
+// Pawn structure
+pub const DOUBLED_PAWN: Score = Score(-10, -20);
+pub const ISOLATED_PAWN: Score = Score(-10, -15);
+
+// Passed pawn bonus by relative rank
+#[rustfmt::skip]
+pub const PASSED_PAWN: [Score; 8] = [
+    Score(   0,    0),
+    Score(   5,   10),
+    Score(   5,   15),
+    Score(  10,   25),
+    Score(  25,   45),
+    Score(  45,   75),
+    Score(  70,  110),
+    Score(   0,    0),
+];
+
+// Pieces
+pub const BISHOP_PAIR: Score = Score(30, 50);
+pub const ROOK_OPEN_FILE: Score = Score(25, 10);
+pub const ROOK_SEMI_OPEN_FILE: Score = Score(10, 5);
+
+// Mobility bonus per safe square attacked, indexed like the 'pieces' array
+#[rustfmt::skip]
+pub const MOBILITY: [Score; 6] = [
+    Score(   0,    0),
+    Score(   2,    4),
+    Score(   4,    4),
+    Score(   5,    5),
+    Score(   1,    2),
+    Score(   0,    0),
+];
+
+// King safety
+pub const KING_ATTACK: Score = Score(-6, -1);
+pub const PAWN_SHIELD: Score = Score(12, 0);
@@ -0,0 +1,657 @@
+// evaluate.rs contains challenger's hand-crafted evaluation function. The
+// evaluation is split into a number of independent terms (material, piece
+// square tables, pawn structure, ...) each of which produce a middlegame and an
+// endgame score for both sides. The final evaluation is tapered between the two
+// based on the amount of material left on the board.
+//
+// Every term reports its result through the 'Trace' trait. During search the
+// zero-sized 'NoTrace' is used, which compiles the reporting away entirely,
+// while the 'eval' command uses 'EvalTrace' to collect a per-term breakdown.
+// The weights themselves live in eval_params.rs, which is written by the tuner.
+
+use crate::attacks;
+use crate::eval_params::*;
+use crate::position::*;
+use crate::types::{Color, PieceType};
+use crate::variant::{Variant, CENTER};
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+// A Score holds a (middlegame, endgame) pair of values in centipawns.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Score(pub i32, pub i32);
+
+impl Score {
+    pub fn mg(self) -> i32 {
+        self.0
+    }
+
+    pub fn eg(self) -> i32 {
+        self.1
+    }
+
+    // Blend the middlegame and endgame values according to the game phase,
+    // where MAX_PHASE is a full board and 0 is a bare king and pawn ending.
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.0 * phase + self.1 * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        Score(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        Score(self.0 - other.0, self.1 - other.1)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score(-self.0, -self.1)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, rhs: i32) -> Score {
+        Score(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+// The individual terms making up an evaluation. The order here is the order in
+// which they are displayed by the 'eval' command.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Term {
+    Material,
+    PieceSquare,
+    Pawns,
+    PassedPawns,
+    Bishops,
+    Rooks,
+    Mobility,
+    KingSafety,
+    Variant,
+}
+
+pub const TERM_COUNT: usize = 9;
+
+const TERMS: [Term; TERM_COUNT] = [
+    Term::Material,
+    Term::PieceSquare,
+    Term::Pawns,
+    Term::PassedPawns,
+    Term::Bishops,
+    Term::Rooks,
+    Term::Mobility,
+    Term::KingSafety,
+    Term::Variant,
+];
+
+impl Term {
+    fn name(self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::PieceSquare => "PST",
+            Term::Pawns => "Pawns",
+            Term::PassedPawns => "Passed",
+            Term::Bishops => "Bishops",
+            Term::Rooks => "Rooks",
+            Term::Mobility => "Mobility",
+            Term::KingSafety => "King safety",
+            Term::Variant => "Variant",
+        }
+    }
+}
+
+// A single evaluation weight. Pieces are indexed like the 'pieces' array and
+// piece square table entries use the table's own (8th rank first) ordering.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Param {
+    PieceValue(usize),
+    Pst(usize, usize),
+    DoubledPawn,
+    IsolatedPawn,
+    PassedPawn(usize),
+    BishopPair,
+    RookOpenFile,
+    RookSemiOpenFile,
+    Mobility(usize),
+    KingAttack,
+    PawnShield,
+}
+
+// A Trace receives the white and black scores for each term as they are
+// computed, as well as how many times each weight was applied to each side.
+// Both default to doing nothing so implementors only pick what they need.
+pub trait Trace {
+    fn add(&mut self, _term: Term, _white: Score, _black: Score) {}
+    fn param(&mut self, _param: Param, _is_white: bool, _count: i32) {}
+}
+
+// NoTrace discards everything it is given. It is the trace used on the normal
+// evaluation path.
+pub struct NoTrace;
+
+impl Trace for NoTrace {}
+
+// EvalTrace records the score of every term for both sides so that it can be
+// displayed as a table by the 'eval' command.
+#[derive(Debug, Default, PartialEq)]
+pub struct EvalTrace {
+    pub scores: [[Score; 2]; TERM_COUNT],
+    pub phase: i32,
+    pub evaluation: i32,
+}
+
+impl Trace for EvalTrace {
+    fn add(&mut self, term: Term, white: Score, black: Score) {
+        self.scores[term as usize][0] += white;
+        self.scores[term as usize][1] += black;
+    }
+}
+
+impl EvalTrace {
+    pub fn total(&self, is_white: bool) -> Score {
+        let side = !is_white as usize;
+        self.scores
+            .iter()
+            .fold(Score::default(), |sum, term| sum + term[side])
+    }
+}
+
+// Centipawns are displayed in pawn units, as is conventional for eval output.
+fn pawns(centipawns: i32) -> String {
+    format!("{:.2}", centipawns as f64 / 100.0)
+}
+
+fn write_row(f: &mut fmt::Formatter, name: &str, white: Score, black: Score) -> fmt::Result {
+    let total = white - black;
+    writeln!(
+        f,
+        "{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+        name,
+        pawns(white.mg()),
+        pawns(white.eg()),
+        pawns(black.mg()),
+        pawns(black.eg()),
+        pawns(total.mg()),
+        pawns(total.eg()),
+    )
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = "-------------+---------------+---------------+--------------";
+        writeln!(
+            f,
+            "        Term |     White     |     Black     |     Total"
+        )?;
+        writeln!(
+            f,
+            "             |   MG     EG   |   MG     EG   |   MG     EG"
+        )?;
+        writeln!(f, "{}", separator)?;
+        for term in TERMS.iter() {
+            let [white, black] = self.scores[*term as usize];
+            write_row(f, term.name(), white, black)?;
+        }
+        writeln!(f, "{}", separator)?;
+        write_row(f, "Total", self.total(true), self.total(false))?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {} / {}", self.phase, MAX_PHASE)?;
+        writeln!(
+            f,
+            "Final evaluation: {} (white side)",
+            pawns(self.evaluation)
+        )
+    }
+}
+
+// Game phase weights for each non-pawn piece, indexed like the 'pieces' array
+const PHASE_WEIGHTS: [i32; 6] = [0, 2, 1, 1, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+// Attack units for each piece attacking the enemy king zone, indexed like the
+// 'pieces' array.
+const KING_ATTACK_UNITS: [i32; 6] = [0, 3, 2, 2, 5, 0];
+
+// Bonus for a king in King of the Hill, indexed by its distance in king moves
+// from the nearest center square
+const HILL_DISTANCE_BONUS: [Score; 4] =
+    [Score(400, 400), Score(60, 120), Score(20, 40), Score(0, 0)];
+
+// Bonus for the checks given in Three-check, indexed by their number. A third
+// check ends the game.
+const CHECK_BONUS: [Score; 3] = [Score(0, 0), Score(120, 120), Score(350, 350)];
+
+// In antichess every piece, the king included, counts against its owner
+const ANTICHESS_PIECE: Score = Score(-100, -100);
+
+lazy_static! {
+    // Squares in front of a pawn (on its own and adjacent files) which must
+    // be clear of enemy pawns for that pawn to be passed.
+    static ref PASSED_MASKS: [[u64; 64]; 2] = {
+        let mut masks = [[0; 64]; 2];
+        for sq in 0..64 {
+            let file = FILES[sq % 8] | adjacent_files(sq % 8);
+            let rank = sq / 8;
+            let above = if rank == 7 { 0 } else { u64::MAX << ((rank + 1) * 8) };
+            let below = if rank == 0 { 0 } else { u64::MAX >> ((8 - rank) * 8) };
+            masks[0][sq] = file & above;
+            masks[1][sq] = file & below;
+        }
+        masks
+    };
+}
+
+const FILES: [u64; 8] = [
+    A_FILE, B_FILE, C_FILE, D_FILE, E_FILE, F_FILE, G_FILE, H_FILE,
+];
+
+fn adjacent_files(file: usize) -> u64 {
+    let left = if file > 0 { FILES[file - 1] } else { 0 };
+    let right = if file < 7 { FILES[file + 1] } else { 0 };
+    left | right
+}
+
+fn pawn_attack_span(pawns: u64, is_white: bool) -> u64 {
+    if is_white {
+        ((pawns << 7) & !H_FILE) | ((pawns << 9) & !A_FILE)
+    } else {
+        ((pawns >> 9) & !H_FILE) | ((pawns >> 7) & !A_FILE)
+    }
+}
+
+// Iterate over the square indices of the set bits of a bitboard
+fn squares(mut bitboard: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let sq = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(sq)
+    })
+}
+
+pub fn game_phase(position: &Position) -> i32 {
+    let phase: i32 = (0..6)
+        .map(|piece| {
+            let count = position.pieces[piece] | position.pieces[piece + 7];
+            count.count_ones() as i32 * PHASE_WEIGHTS[piece]
+        })
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+// Evaluate a position from white's point of view, reporting each term to the
+// given trace.
+pub fn evaluate<T: Trace>(position: &Position, trace: &mut T) -> i32 {
+    // Losing material is the aim of antichess, so none of the standard terms
+    // apply
+    if position.variant() == Variant::Antichess {
+        let total = term(position, trace, Term::Variant, variant);
+        return total.taper(game_phase(position));
+    }
+
+    let total = term(position, trace, Term::Material, material)
+        + term(position, trace, Term::PieceSquare, piece_squares)
+        + term(position, trace, Term::Pawns, pawn_structure)
+        + term(position, trace, Term::PassedPawns, passed_pawns)
+        + term(position, trace, Term::Bishops, bishops)
+        + term(position, trace, Term::Rooks, rooks)
+        + term(position, trace, Term::Mobility, mobility)
+        + term(position, trace, Term::KingSafety, king_safety)
+        + term(position, trace, Term::Variant, variant);
+
+    total.taper(game_phase(position))
+}
+
+// Evaluate a single term for both sides, report it to the trace and return
+// the white-relative score.
+#[inline(always)]
+fn term<T, F>(position: &Position, trace: &mut T, term: Term, term_fn: F) -> Score
+where
+    T: Trace,
+    F: Fn(&Position, bool, &mut T) -> Score,
+{
+    let white = term_fn(position, true, trace);
+    let black = term_fn(position, false, trace);
+    trace.add(term, white, black);
+    white - black
+}
+
+// Evaluate a position and collect the per-term breakdown.
+pub fn trace(position: &Position) -> EvalTrace {
+    let mut trace = EvalTrace::default();
+    trace.evaluation = evaluate(position, &mut trace);
+    trace.phase = game_phase(position);
+    trace
+}
+
+fn offset(is_white: bool) -> usize {
+    (!is_white as usize) * 7
+}
+
+// Apply a weight 'count' times, reporting it to the trace
+#[inline(always)]
+fn apply<T: Trace>(trace: &mut T, param: Param, is_white: bool, count: i32) -> Score {
+    trace.param(param, is_white, count);
+    param_value(param) * count
+}
+
+// The current value of an evaluation weight
+pub fn param_value(param: Param) -> Score {
+    match param {
+        Param::PieceValue(piece) => PIECE_VALUES[piece],
+        Param::Pst(piece, sq) => PST[piece][sq],
+        Param::DoubledPawn => DOUBLED_PAWN,
+        Param::IsolatedPawn => ISOLATED_PAWN,
+        Param::PassedPawn(rank) => PASSED_PAWN[rank],
+        Param::BishopPair => BISHOP_PAIR,
+        Param::RookOpenFile => ROOK_OPEN_FILE,
+        Param::RookSemiOpenFile => ROOK_SEMI_OPEN_FILE,
+        Param::Mobility(piece) => MOBILITY[piece],
+        Param::KingAttack => KING_ATTACK,
+        Param::PawnShield => PAWN_SHIELD,
+    }
+}
+
+fn material<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let offset = offset(is_white);
+    (0..6).fold(Score::default(), |score, piece| {
+        let count = position.pieces[piece + offset].count_ones() as i32;
+        score + apply(trace, Param::PieceValue(piece), is_white, count)
+    })
+}
+
+fn piece_squares<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let offset = offset(is_white);
+    let flip = if is_white { 56 } else { 0 };
+    let mut score = Score::default();
+    for piece in 0..6 {
+        for sq in squares(position.pieces[piece + offset]) {
+            score += apply(trace, Param::Pst(piece, sq ^ flip), is_white, 1);
+        }
+    }
+    score
+}
+
+fn pawn_structure<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let pawns = position.pieces[W_PAWN + offset(is_white)];
+    let mut doubled = 0;
+    let mut isolated = 0;
+    for (file, file_mask) in FILES.iter().enumerate() {
+        let count = (pawns & file_mask).count_ones() as i32;
+        if count == 0 {
+            continue;
+        }
+        doubled += count - 1;
+        if pawns & adjacent_files(file) == 0 {
+            isolated += count;
+        }
+    }
+    apply(trace, Param::DoubledPawn, is_white, doubled)
+        + apply(trace, Param::IsolatedPawn, is_white, isolated)
+}
+
+fn passed_pawns<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let pawns = position.pieces[W_PAWN + offset(is_white)];
+    let enemy_pawns = position.pieces[W_PAWN + offset(!is_white)];
+    let mut score = Score::default();
+    for sq in squares(pawns) {
+        if PASSED_MASKS[!is_white as usize][sq] & enemy_pawns == 0 {
+            let relative_rank = if is_white { sq / 8 } else { 7 - sq / 8 };
+            score += apply(trace, Param::PassedPawn(relative_rank), is_white, 1);
+        }
+    }
+    score
+}
+
+fn bishops<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let has_pair = position.pieces[W_BISHOP + offset(is_white)].count_ones() >= 2;
+    apply(trace, Param::BishopPair, is_white, has_pair as i32)
+}
+
+fn rooks<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let own_pawns = position.pieces[W_PAWN + offset(is_white)];
+    let enemy_pawns = position.pieces[W_PAWN + offset(!is_white)];
+    let mut open = 0;
+    let mut semi_open = 0;
+    for sq in squares(position.pieces[W_ROOK + offset(is_white)]) {
+        let file = FILES[sq % 8];
+        if file & own_pawns == 0 {
+            if file & enemy_pawns == 0 {
+                open += 1;
+            } else {
+                semi_open += 1;
+            }
+        }
+    }
+    apply(trace, Param::RookOpenFile, is_white, open)
+        + apply(trace, Param::RookSemiOpenFile, is_white, semi_open)
+}
+
+// Attacks of a single non-pawn piece on 'sq' given the board occupancy
+fn piece_attacks(piece: usize, sq: usize, occupied: u64) -> u64 {
+    match piece {
+        W_ROOK => attacks::rook_attacks(sq, occupied),
+        W_KNIGHT => attacks::knight_attacks(sq),
+        W_BISHOP => attacks::bishop_attacks(sq, occupied),
+        W_QUEEN => attacks::queen_attacks(sq, occupied),
+        W_KING => attacks::king_attacks(sq),
+        _ => 0,
+    }
+}
+
+fn mobility<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let offset = offset(is_white);
+    let occupied = position.pieces[W_PIECES] | position.pieces[B_PIECES];
+    let enemy_pawn_attacks = pawn_attack_span(position.pieces[W_PAWN + (7 - offset)], !is_white);
+    let safe = !(position.pieces[W_PIECES + offset] | enemy_pawn_attacks);
+
+    let mut score = Score::default();
+    for piece in [W_ROOK, W_KNIGHT, W_BISHOP, W_QUEEN].iter() {
+        let mut count = 0;
+        for sq in squares(position.pieces[piece + offset]) {
+            count += (piece_attacks(*piece, sq, occupied) & safe).count_ones() as i32;
+        }
+        score += apply(trace, Param::Mobility(*piece), is_white, count);
+    }
+    score
+}
+
+fn king_safety<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let own = offset(is_white);
+    let enemy = offset(!is_white);
+    let king = position.pieces[W_KING + own];
+    if king == 0 {
+        return Score::default();
+    }
+    let king_sq = king.trailing_zeros() as usize;
+    let zone = attacks::king_attacks(king_sq) | king;
+    let occupied = position.pieces[W_PIECES] | position.pieces[B_PIECES];
+
+    let mut units = 0;
+    for piece in [W_ROOK, W_KNIGHT, W_BISHOP, W_QUEEN].iter() {
+        for sq in squares(position.pieces[piece + enemy]) {
+            let hits = (piece_attacks(*piece, sq, occupied) & zone).count_ones() as i32;
+            units += hits * KING_ATTACK_UNITS[*piece];
+        }
+    }
+
+    // Attack units grow quadratically so that several attackers are much more
+    // dangerous than a single one.
+    let attack = apply(trace, Param::KingAttack, is_white, units * units / 8);
+
+    let front = if is_white { king << 8 } else { king >> 8 };
+    let shield = front | ((front << 1) & !A_FILE) | ((front >> 1) & !H_FILE);
+    let shield_pawns = (shield & position.pieces[W_PAWN + own]).count_ones() as i32;
+    attack + apply(trace, Param::PawnShield, is_white, shield_pawns)
+}
+
+// Terms for the rules of the position's variant: closeness to the hill, checks
+// given, the number of pieces left to lose, or the pieces in hand
+fn variant<T: Trace>(position: &Position, is_white: bool, trace: &mut T) -> Score {
+    let own = offset(is_white);
+    let king = position.pieces[W_KING + own];
+    match position.variant() {
+        Variant::Standard => Score::default(),
+        Variant::KingOfTheHill if king == 0 => Score::default(),
+        Variant::KingOfTheHill => {
+            let king_sq = king.trailing_zeros() as usize;
+            let distance = squares(CENTER)
+                .map(|sq| {
+                    let files = (sq % 8).abs_diff(king_sq % 8);
+                    let ranks = (sq / 8).abs_diff(king_sq / 8);
+                    files.max(ranks)
+                })
+                .min()
+                .unwrap();
+            HILL_DISTANCE_BONUS[distance]
+        }
+        Variant::ThreeCheck => {
+            let checks = position.checks_given(Color::from_is_white(is_white));
+            CHECK_BONUS[(checks as usize).min(CHECK_BONUS.len() - 1)]
+        }
+        Variant::Antichess => ANTICHESS_PIECE * position.pieces[W_PIECES + own].count_ones() as i32,
+        // A piece in hand is worth as much as one on the board
+        Variant::Crazyhouse => {
+            PieceType::ALL[..5]
+                .iter()
+                .fold(Score::default(), |score, &piece| {
+                    let count = position.in_hand(Color::from_is_white(is_white), piece) as i32;
+                    score + apply(trace, Param::PieceValue(piece.index()), is_white, count)
+                })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_evaluate {
+        ($test_name:ident, $fen:expr, $expected:expr) => {
+            #[test]
+            fn $test_name() {
+                assert_eq!(evaluate(&Position::from($fen), &mut NoTrace), $expected);
+            }
+        };
+    }
+
+    test_evaluate!(
+        evaluate_startpos_is_even,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        0
+    );
+    test_evaluate!(evaluate_bare_kings, "4k3/8/8/8/8/8/8/4K3 w - - 0 1", 0);
+
+    fn evaluate_variant(fen: &str, variant: Variant) -> i32 {
+        evaluate(&Position::from_variant(fen, variant), &mut NoTrace)
+    }
+
+    #[test]
+    fn evaluate_king_of_the_hill() {
+        let fen = "4k3/8/8/8/8/4K3/8/8 w - - 0 1";
+        let standard = evaluate(&Position::from(fen), &mut NoTrace);
+        assert!(evaluate_variant(fen, Variant::KingOfTheHill) > standard);
+    }
+
+    #[test]
+    fn evaluate_three_check() {
+        let fen = "4k3/8/8/8/8/8/8/4K3 w - - 0 1 +2+1";
+        assert!(evaluate_variant(fen, Variant::ThreeCheck) > 0);
+        assert_eq!(evaluate_variant(fen, Variant::Standard), 0);
+    }
+
+    // Having fewer pieces is better in antichess
+    #[test]
+    fn evaluate_antichess() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w - - 0 1";
+        assert!(evaluate_variant(fen, Variant::Antichess) > 0);
+        assert!(evaluate(&Position::from(fen), &mut NoTrace) < 0);
+    }
+
+    // Pieces in hand count as material in Crazyhouse
+    #[test]
+    fn evaluate_crazyhouse() {
+        let fen = "4k3/8/8/8/8/8/8/4K3[Nq] w - - 0 1";
+        assert!(evaluate_variant(fen, Variant::Crazyhouse) < 0);
+        assert_eq!(evaluate_variant(fen, Variant::Standard), 0);
+        let trace = trace(&Position::from_variant(fen, Variant::Crazyhouse));
+        let total = trace.total(true) - trace.total(false);
+        assert_eq!(trace.evaluation, total.taper(trace.phase));
+    }
+
+    // Mirroring a position vertically and swapping the colors should negate
+    // the evaluation.
+    macro_rules! test_symmetry {
+        ($test_name:ident, $fen:expr, $mirrored:expr) => {
+            #[test]
+            fn $test_name() {
+                let eval = evaluate(&Position::from($fen), &mut NoTrace);
+                let mirrored = evaluate(&Position::from($mirrored), &mut NoTrace);
+                assert_eq!(eval, -mirrored);
+            }
+        };
+    }
+
+    test_symmetry!(
+        symmetry_complex_2,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b KQkq - 0 1"
+    );
+    test_symmetry!(
+        symmetry_complex_3,
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "8/4p1p1/8/1r3P1K/kp5R/3P4/2P5/8 b - - 0 1"
+    );
+
+    #[test]
+    fn trace_matches_evaluation() {
+        let position =
+            Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let trace = trace(&position);
+        let total = trace.total(true) - trace.total(false);
+
+        assert_eq!(trace.evaluation, evaluate(&position, &mut NoTrace));
+        assert_eq!(trace.evaluation, total.taper(trace.phase));
+    }
+
+    #[test]
+    fn trace_startpos_phase() {
+        assert_eq!(trace(&Position::new()).phase, MAX_PHASE);
+    }
+
+    #[test]
+    fn passed_pawn_bonus() {
+        let passed = Position::from("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1");
+        let blocked = Position::from("4k3/3p4/8/3P4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(passed_pawns(&passed, true, &mut NoTrace), PASSED_PAWN[4]);
+        assert_eq!(passed_pawns(&blocked, true, &mut NoTrace), Score::default());
+    }
+
+    #[test]
+    fn doubled_and_isolated_pawns() {
+        let position = Position::from("4k3/8/8/8/3P4/3P4/8/4K3 w - - 0 1");
+        assert_eq!(
+            pawn_structure(&position, true, &mut NoTrace),
+            DOUBLED_PAWN + ISOLATED_PAWN * 2
+        );
+    }
+}
@@ -1,20 +1,184 @@
+use crate::book::Book;
+use crate::movepick::History;
+use crate::nnue::Network;
+use crate::options::Options;
 use crate::position;
+use crate::skill::Rng;
+use crate::tt::TranspositionTable;
+use crate::variant::Variant;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 
 pub struct GameState {
     pub game_position: position::Position,
+    // Hashes of the positions played before game_position, oldest first
+    pub game_hashes: Vec<u64>,
     pub debug: bool,
+    pub options: Options,
+    pub network: Option<Arc<Network>>,
+    pub book: Option<Arc<Book>>,
+    pub tt: Arc<TranspositionTable>,
+    // Move ordering statistics, one table per search thread
+    pub histories: Arc<Mutex<Vec<History>>>,
+    // The search running in the background, and the flag that stops it
+    search: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
 }
 
 impl GameState {
     pub fn new() -> GameState {
+        let options = Options::new();
         GameState {
             game_position: position::Position::new(),
+            game_hashes: vec![],
             debug: false,
+            tt: Arc::new(TranspositionTable::new(options.get_int("Hash") as usize)),
+            options,
+            network: None,
+            book: None,
+            histories: Arc::new(Mutex::new(vec![History::new()])),
+            search: None,
         }
     }
 
     pub fn reset_game(&mut self) {
-        self.game_position = position::Position::new();
+        let variant = self.variant();
+        self.game_position = position::Position::from_variant(variant.start_fen(), variant);
+        self.game_hashes.clear();
+    }
+
+    // Forget everything learned while searching the previous game
+    pub fn new_game(&mut self) {
+        self.reset_game();
+        self.tt.clear();
+        for history in self.histories.lock().unwrap().iter_mut() {
+            history.clear();
+        }
+    }
+
+    pub fn play_move(&mut self, mov: position::Move) {
+        self.game_hashes.push(self.game_position.hash());
+        self.game_position.play_move(mov);
+    }
+
+    // Set a UCI option, applying any side effects it has
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let previous = self.options.clone();
+        let previous_variant = self.variant();
+        let name = self.options.set(name, value)?;
+        if name == "Hash" {
+            let size_mb = self.options.get_int("Hash") as usize;
+            self.tt = Arc::new(TranspositionTable::new(size_mb));
+        }
+        if name == "Threads" {
+            let threads = self.options.get_int("Threads") as usize;
+            self.histories
+                .lock()
+                .unwrap()
+                .resize_with(threads, History::new);
+        }
+        if name == "UCI_Variant" && self.variant() != previous_variant {
+            // Positions of different variants share their hashes, so scores
+            // searched under the old rules must not be found under the new
+            self.tt.clear();
+        }
+        if name == "EvalFile" {
+            let path = self.options.get_str("EvalFile");
+            self.network = if path.is_empty() {
+                None
+            } else {
+                match Network::load(path) {
+                    Ok(network) => Some(Arc::new(network)),
+                    Err(message) => {
+                        // Keep the previous network (and file name) in place
+                        self.options = previous;
+                        return Err(message);
+                    }
+                }
+            };
+        }
+        if name == "BookFile" {
+            let path = self.options.get_str("BookFile");
+            self.book = if path.is_empty() {
+                None
+            } else {
+                match Book::load(path) {
+                    Ok(book) => Some(Arc::new(book)),
+                    Err(message) => {
+                        self.options = previous;
+                        return Err(message);
+                    }
+                }
+            };
+        }
+        Ok(())
+    }
+
+    // A move from the opening book, if it is enabled and has one for the
+    // current position within the first BookDepth moves of the game. Books
+    // are of standard chess games, so none is played in other variants.
+    pub fn book_move(&self) -> Option<position::Move> {
+        let book = self.book.as_ref()?;
+        if self.variant() != Variant::Standard {
+            return None;
+        }
+        let depth = self.options.get_int("BookDepth");
+        if !self.options.get_bool("OwnBook") || self.game_position.full_num as i64 > depth {
+            return None;
+        }
+        let best = self.options.get_bool("BookBestMove");
+        book.pick(&self.game_position, best, &mut Rng::from_time())
+    }
+
+    // The variant chosen with the UCI_Variant option
+    pub fn variant(&self) -> Variant {
+        Variant::from_name(self.options.get_str("UCI_Variant")).unwrap_or_default()
+    }
+
+    // The network to evaluate with, if NNUE is enabled and a network is loaded
+    pub fn active_network(&self) -> Option<&Network> {
+        if self.options.get_bool("Use NNUE") {
+            self.network.as_deref()
+        } else {
+            None
+        }
+    }
+
+    // Keep track of a search started in the background
+    pub fn set_search(&mut self, stop: Arc<AtomicBool>, handle: JoinHandle<()>) {
+        self.stop_search();
+        self.search = Some((stop, handle));
+    }
+
+    // Stop the background search, if any, and wait for it to finish
+    pub fn stop_search(&mut self) {
+        if let Some((stop, handle)) = self.search.take() {
+            stop.store(true, Ordering::Relaxed);
+            handle.join().unwrap();
+        }
+    }
+
+    // Wait for the background search, if any, to finish by itself
+    pub fn wait_search(&mut self) {
+        if let Some((_, handle)) = self.search.take() {
+            handle.join().unwrap();
+        }
+    }
+
+    // Evaluate the current position from white's point of view, using the
+    // network when one is active and the hand-crafted evaluation otherwise.
+    pub fn evaluate(&self) -> isize {
+        match self.active_network() {
+            Some(network) => network.evaluate_position(&self.game_position) as isize,
+            None => self.game_position.evaluate(),
+        }
+    }
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        GameState::new()
     }
 }
 
@@ -27,7 +191,7 @@ mod tests {
         let mut game_state = GameState::new();
         game_state.debug = true;
 
-        assert_eq!(game_state.debug, true);
+        assert!(game_state.debug);
     }
 
     #[test]
@@ -35,6 +199,126 @@ mod tests {
         let mut game_state = GameState::new();
         game_state.debug = false;
 
-        assert_eq!(game_state.debug, false);
+        assert!(!game_state.debug);
+    }
+
+    #[test]
+    fn test_evaluate_falls_back_to_classical() {
+        let mut game_state = GameState::new();
+        game_state.network = Some(Arc::new(Network::random(1)));
+        assert_eq!(game_state.evaluate(), game_state.game_position.evaluate());
+
+        game_state.set_option("Use NNUE", "true").unwrap();
+        let network = Network::random(1);
+        assert_eq!(
+            game_state.evaluate(),
+            network.evaluate_position(&game_state.game_position) as isize
+        );
+    }
+
+    #[test]
+    fn test_play_move_records_history() {
+        let mut game_state = GameState::new();
+        let start = game_state.game_position.hash();
+        let mov = position::str_to_move("e2e4", game_state.game_position).unwrap();
+        game_state.play_move(mov);
+        assert_eq!(game_state.game_hashes, vec![start]);
+
+        game_state.reset_game();
+        assert!(game_state.game_hashes.is_empty());
+    }
+
+    #[test]
+    fn test_set_hash_resizes_table() {
+        let mut game_state = GameState::new();
+        let old = Arc::clone(&game_state.tt);
+        game_state.set_option("Hash", "2").unwrap();
+        assert!(!Arc::ptr_eq(&old, &game_state.tt));
+        assert!(game_state.set_option("Hash", "0").is_err());
+    }
+
+    #[test]
+    fn test_set_threads() {
+        let mut game_state = GameState::new();
+        game_state.set_option("Threads", "4").unwrap();
+        assert_eq!(game_state.histories.lock().unwrap().len(), 4);
+        game_state.set_option("Threads", "2").unwrap();
+        assert_eq!(game_state.histories.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_set_eval_file() {
+        let path = std::env::temp_dir().join("challenger_gamestate_test.nnue");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, Network::random(5).to_bytes()).unwrap();
+
+        let mut game_state = GameState::new();
+        game_state.set_option("EvalFile", path).unwrap();
+        assert_eq!(game_state.network.as_deref(), Some(&Network::random(5)));
+
+        assert!(game_state.set_option("EvalFile", "no/such/file").is_err());
+        assert_eq!(game_state.options.get_str("EvalFile"), path);
+        assert!(game_state.network.is_some());
+
+        game_state.set_option("EvalFile", "<empty>").unwrap();
+        assert!(game_state.network.is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_set_variant_clears_table() {
+        let mut game_state = GameState::new();
+        let entry = crate::tt::Entry {
+            best_move: None,
+            score: 50,
+            depth: 5,
+            bound: crate::tt::Bound::Exact,
+        };
+        game_state.tt.store(1, 0, entry);
+        game_state.set_option("UCI_Variant", "chess").unwrap();
+        assert!(game_state.tt.probe(1, 0).is_some());
+
+        game_state.set_option("UCI_Variant", "3check").unwrap();
+        assert!(game_state.tt.probe(1, 0).is_none());
+    }
+
+    #[test]
+    fn test_book_move() {
+        let start = position::Position::new();
+        let entry = crate::book::Entry {
+            key: crate::book::key(&start),
+            mov: 12 << 6 | 28, // e2e4
+            weight: 1,
+            learn: 0,
+        };
+        let path = std::env::temp_dir().join("challenger_gamestate_test.bin");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, entry.encode()).unwrap();
+
+        let mut game_state = GameState::new();
+        game_state.set_option("BookFile", path).unwrap();
+        assert_eq!(game_state.book.as_ref().map(|x| x.len()), Some(1));
+        assert_eq!(game_state.book_move(), None);
+
+        game_state.set_option("OwnBook", "true").unwrap();
+        let e4 = position::str_to_move("e2e4", start).unwrap();
+        assert_eq!(game_state.book_move(), Some(e4));
+        game_state.play_move(e4);
+        assert_eq!(game_state.book_move(), None);
+
+        game_state.game_position = start;
+        game_state.game_position.full_num = 21;
+        assert_eq!(game_state.book_move(), None);
+
+        // The book is of standard chess, whatever the position
+        game_state.game_position = start;
+        game_state
+            .set_option("UCI_Variant", "kingofthehill")
+            .unwrap();
+        assert_eq!(game_state.book_move(), None);
+
+        assert!(game_state.set_option("BookFile", "no/such/file").is_err());
+        assert!(game_state.book.is_some());
+        std::fs::remove_file(path).unwrap();
     }
 }
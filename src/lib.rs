@@ -1,6 +1,24 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod attacks;
+pub mod bench;
+pub mod book;
+pub mod datagen;
+pub mod epdtest;
+pub mod eval_params;
+pub mod evaluate;
 pub mod gamestate;
+pub mod movelist;
+pub mod movepick;
+pub mod nnue;
+pub mod options;
+pub mod pgn;
 pub mod position;
+pub mod search;
+pub mod skill;
+pub mod tt;
+pub mod tune;
+pub mod types;
 pub mod uci;
+pub mod variant;
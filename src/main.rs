@@ -1,10 +1,26 @@
-mod gamestate;
-mod position;
-mod uci;
+use challenger_rs::{bench, book, datagen, epdtest, tune, uci};
 
-#[macro_use]
-extern crate lazy_static;
+use std::env;
+use std::process;
 
 fn main() {
-    uci::start_uci_engine();
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Without a subcommand challenger runs as a UCI engine
+    let result = match args.first().map(String::as_str) {
+        Some("bench") => bench::run(&args[1..]),
+        Some("book") => book::run(&args[1..]),
+        Some("datagen") => datagen::run(&args[1..]),
+        Some("epdtest") => epdtest::run(&args[1..]),
+        Some("tune") => tune::run(&args[1..]),
+        _ => {
+            uci::start_uci_engine();
+            Ok(())
+        }
+    };
+
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,296 @@
+// movelist.rs provides MoveList, a list of moves stored inline with a fixed
+// capacity, so that generating and ordering moves never touches the heap. No
+// legal chess position has more than 218 moves, which MAX_MOVES covers with
+// room for the pseudo-legal moves generated before the legality check.
+// Crazyhouse drops add up to five pieces times the empty squares on top of
+// those, so lists that may hold drops use MAX_DROP_MOVES, the capacity
+// Fairy-Stockfish uses for drop variants. The search and perft pick the
+// capacity from the variant, as every node fills several lists.
+// Each move carries a score, which is 0 unless set, for move ordering to sort
+// on.
+
+use crate::position::Move;
+use std::fmt;
+use std::ops::Deref;
+
+pub const MAX_MOVES: usize = 256;
+pub const MAX_DROP_MOVES: usize = 1024;
+
+#[derive(Clone)]
+pub struct MoveList<const N: usize = MAX_MOVES> {
+    moves: [Move; N],
+    scores: [i32; N],
+    len: usize,
+}
+
+impl<const N: usize> MoveList<N> {
+    pub fn new() -> MoveList<N> {
+        MoveList {
+            moves: [Move::NONE; N],
+            scores: [0; N],
+            len: 0,
+        }
+    }
+
+    // Panics if the list is full
+    pub fn push(&mut self, mov: Move) {
+        self.push_scored(mov, 0);
+    }
+
+    pub fn push_scored(&mut self, mov: Move, score: i32) {
+        self.moves[self.len] = mov;
+        self.scores[self.len] = score;
+        self.len += 1;
+    }
+
+    pub fn score(&self, index: usize) -> i32 {
+        self.scores[..self.len][index]
+    }
+
+    pub fn set_score(&mut self, index: usize, score: i32) {
+        self.scores[..self.len][index] = score;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    // Keep only the moves for which 'keep' returns true, in their order
+    pub fn retain<F: FnMut(&Move) -> bool>(&mut self, mut keep: F) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                self.scores[kept] = self.scores[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    // Remove a move, replacing it with the last one
+    pub fn swap_remove(&mut self, index: usize) -> Move {
+        let mov = self[index];
+        self.len -= 1;
+        self.moves[index] = self.moves[self.len];
+        self.scores[index] = self.scores[self.len];
+        mov
+    }
+
+    // Remove a move, shifting the moves after it down
+    pub fn remove(&mut self, index: usize) -> Move {
+        let mov = self[index];
+        self.moves.copy_within(index + 1..self.len, index);
+        self.scores.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        mov
+    }
+}
+
+impl<const N: usize> Default for MoveList<N> {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+impl<const N: usize> Deref for MoveList<N> {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl<const N: usize> fmt::Debug for MoveList<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<const N: usize> PartialEq for MoveList<N> {
+    fn eq(&self, other: &MoveList<N>) -> bool {
+        **self == **other
+    }
+}
+
+impl<const N: usize> Extend<Move> for MoveList<N> {
+    fn extend<I: IntoIterator<Item = Move>>(&mut self, moves: I) {
+        for mov in moves {
+            self.push(mov);
+        }
+    }
+}
+
+impl<const N: usize> std::iter::FromIterator<Move> for MoveList<N> {
+    fn from_iter<I: IntoIterator<Item = Move>>(moves: I) -> Self {
+        let mut list = MoveList::new();
+        list.extend(moves);
+        list
+    }
+}
+
+pub struct IntoIter<const N: usize> {
+    list: MoveList<N>,
+    index: usize,
+}
+
+impl<const N: usize> Iterator for IntoIter<N> {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        let mov = self.list.get(self.index).copied();
+        self.index += 1;
+        mov
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.list.len().saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+impl<const N: usize> ExactSizeIterator for IntoIter<N> {}
+
+impl<const N: usize> IntoIterator for MoveList<N> {
+    type Item = Move;
+    type IntoIter = IntoIter<N>;
+
+    fn into_iter(self) -> IntoIter<N> {
+        IntoIter {
+            list: self,
+            index: 0,
+        }
+    }
+}
+
+impl<'a, const N: usize> IntoIterator for &'a MoveList<N> {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Storage that move generation can fill: a MoveList, or a Vec when the
+// number of moves must not be bounded
+pub trait MoveStore {
+    fn push(&mut self, mov: Move);
+    fn retain<F: FnMut(&Move) -> bool>(&mut self, keep: F);
+    fn clear(&mut self);
+    fn as_slice(&self) -> &[Move];
+}
+
+impl<const N: usize> MoveStore for MoveList<N> {
+    fn push(&mut self, mov: Move) {
+        MoveList::push(self, mov);
+    }
+
+    fn retain<F: FnMut(&Move) -> bool>(&mut self, keep: F) {
+        MoveList::retain(self, keep);
+    }
+
+    fn clear(&mut self) {
+        MoveList::clear(self);
+    }
+
+    fn as_slice(&self) -> &[Move] {
+        self
+    }
+}
+
+impl MoveStore for Vec<Move> {
+    fn push(&mut self, mov: Move) {
+        Vec::push(self, mov);
+    }
+
+    fn retain<F: FnMut(&Move) -> bool>(&mut self, keep: F) {
+        Vec::retain(self, keep);
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+
+    fn as_slice(&self) -> &[Move] {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::{str_to_move, Position};
+    use crate::variant::Variant;
+
+    fn list(position: Position, moves: &[&str]) -> MoveList {
+        moves
+            .iter()
+            .map(|x| str_to_move(x, position).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn push_and_remove() {
+        let position = Position::new();
+        let mut moves = list(position, &["e2e4", "d2d4", "g1f3", "c2c4"]);
+        moves.set_score(2, 50);
+        assert_eq!(moves.len(), 4);
+        assert_eq!(moves.score(2), 50);
+
+        assert_eq!(moves.swap_remove(0), str_to_move("e2e4", position).unwrap());
+        assert_eq!(*moves, list(position, &["c2c4", "d2d4", "g1f3"])[..]);
+        assert_eq!(moves.score(2), 50);
+
+        assert_eq!(moves.remove(0), str_to_move("c2c4", position).unwrap());
+        assert_eq!(moves, list(position, &["d2d4", "g1f3"]));
+        assert_eq!(moves.score(1), 50);
+
+        moves.clear();
+        assert!(moves.is_empty());
+    }
+
+    #[test]
+    fn retain_keeps_scores() {
+        let position = Position::new();
+        let mut moves: MoveList = MoveList::new();
+        for (i, mov) in position.moves().into_iter().enumerate() {
+            moves.push_scored(mov, i as i32);
+        }
+        moves.retain(|x| x.to().index() >= 24);
+        let scores: Vec<i32> = (0..moves.len()).map(|i| moves.score(i)).collect();
+        assert_eq!(moves.len(), 8);
+        assert!(scores.windows(2).all(|x| x[0] < x[1]));
+        assert!(moves.iter().all(|x| x.to().index() >= 24));
+    }
+
+    #[test]
+    fn iterate_by_value_and_reference() {
+        let position = Position::new();
+        let moves = position.moves();
+        let by_reference: Vec<Move> = (&moves).into_iter().copied().collect();
+        let iter = moves.into_iter();
+        assert_eq!(iter.len(), 20);
+        assert_eq!(iter.collect::<Vec<Move>>(), by_reference);
+    }
+
+    // With a full hand, the drops alone outnumber the moves a board-only list
+    // has room for
+    #[test]
+    fn drops_need_their_own_capacity() {
+        let fen = "4k3/8/8/8/8/8/8/4K3[QRBNP] w - - 0 1";
+        let position = Position::from_variant(fen, Variant::Crazyhouse);
+        let moves = position.moves();
+        assert!(moves.len() > MAX_MOVES, "{}", moves.len());
+        assert!(moves.len() <= MAX_DROP_MOVES);
+    }
+
+    #[test]
+    #[should_panic]
+    fn overflow_panics() {
+        let mut moves: MoveList = MoveList::new();
+        for _ in 0..=MAX_MOVES {
+            moves.push(Move::NONE);
+        }
+    }
+}
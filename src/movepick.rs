@@ -0,0 +1,504 @@
+// movepick.rs orders moves for the search. Moves that are likely to cause a
+// beta cutoff are tried first, in stages:
+//
+//   1. the transposition table move
+//   2. captures and promotions that do not lose material by static exchange,
+//      most valuable victim / least valuable attacker
+//   3. the two killer moves of the current ply, then the counter move to the
+//      opponent's previous move
+//   4. the remaining quiet moves, by butterfly and continuation history
+//   5. the losing captures put aside in stage 2
+//
+// Each stage picks its best remaining move when asked for the next one, so
+// when a cutoff happens early the later moves are never scored or sorted.
+
+use crate::movelist::{MoveList, MAX_MOVES};
+use crate::position::{Move, Position};
+use crate::search::MAX_PLY;
+
+// History scores are kept within +/- MAX_HISTORY
+pub const MAX_HISTORY: i32 = 16384;
+
+// Values used to order captures by most valuable victim, least valuable
+// attacker. Indexed like the 'pieces' array.
+const ORDER_VALUES: [i32; 6] = [1, 5, 3, 3, 9, 100];
+
+// Butterfly history is indexed by where a move comes from: one of the 64
+// squares, or for a drop one of the five piece types that can be dropped
+const ORIGINS: usize = 64 + 5;
+
+// A move identified by the piece that moved (indexed like the 'pieces' array)
+// and its destination square, used to index counter moves and continuation
+// history.
+pub type PieceTo = (usize, usize);
+
+pub fn piece_to(position: &Position, mov: Move) -> PieceTo {
+    let (from, to) = squares(mov);
+    match mov.dropped() {
+        Some(piece) => (position.side_to_move().index() * 7 + piece.index(), to),
+        None => (position.piece_index(from).unwrap(), to),
+    }
+}
+
+fn squares(mov: Move) -> (usize, usize) {
+    (mov.from().index(), mov.to().index())
+}
+
+// Move ordering statistics gathered during search
+pub struct History {
+    killers: [[Move; 2]; MAX_PLY + 1],
+    butterfly: Vec<i32>,      // [color][origin][to]
+    counter_moves: Vec<Move>, // [previous piece][previous to]
+    continuation: Vec<i32>,   // [previous piece][previous to][piece][to]
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            killers: [[Move::NONE; 2]; MAX_PLY + 1],
+            butterfly: vec![0; 2 * ORIGINS * 64],
+            counter_moves: vec![Move::NONE; 14 * 64],
+            continuation: vec![0; 14 * 64 * 14 * 64],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::new();
+    }
+
+    // Forget the killers of a previous search, whose plies no longer match
+    pub fn clear_killers(&mut self) {
+        self.killers = [[Move::NONE; 2]; MAX_PLY + 1];
+    }
+
+    pub fn killers(&self, ply: usize) -> [Move; 2] {
+        self.killers[ply.min(MAX_PLY)]
+    }
+
+    pub fn counter_move(&self, previous: Option<PieceTo>) -> Move {
+        previous.map_or(Move::NONE, |(piece, to)| {
+            self.counter_moves[piece * 64 + to]
+        })
+    }
+
+    fn butterfly_index(is_white: bool, mov: Move) -> usize {
+        let (from, to) = squares(mov);
+        let origin = mov.dropped().map_or(from, |x| 64 + x.index());
+        ((!is_white as usize) * ORIGINS + origin) * 64 + to
+    }
+
+    fn continuation_index(previous: PieceTo, current: PieceTo) -> usize {
+        ((previous.0 * 64 + previous.1) * 14 + current.0) * 64 + current.1
+    }
+
+    // The history score of a quiet move, given the move played before it
+    pub fn quiet_score(&self, position: &Position, mov: Move, previous: Option<PieceTo>) -> i32 {
+        let mut score = self.butterfly[History::butterfly_index(position.is_white_move, mov)];
+        if let Some(previous) = previous {
+            let index = History::continuation_index(previous, piece_to(position, mov));
+            score += self.continuation[index];
+        }
+        score
+    }
+
+    // Update the statistics after 'best' caused a beta cutoff. The quiet moves
+    // searched before it are penalised.
+    pub fn update(
+        &mut self,
+        position: &Position,
+        best: Move,
+        quiets_tried: &[Move],
+        depth: u32,
+        ply: usize,
+        previous: Option<PieceTo>,
+    ) {
+        if position.is_tactical(best) {
+            return;
+        }
+
+        let killers = &mut self.killers[ply.min(MAX_PLY)];
+        if killers[0] != best {
+            killers[1] = killers[0];
+            killers[0] = best;
+        }
+        if let Some((piece, to)) = previous {
+            self.counter_moves[piece * 64 + to] = best;
+        }
+
+        let bonus = (depth * depth).min(400) as i32;
+        self.update_quiet(position, best, bonus, previous);
+        for &mov in quiets_tried.iter().filter(|&&x| x != best) {
+            self.update_quiet(position, mov, -bonus, previous);
+        }
+    }
+
+    fn update_quiet(
+        &mut self,
+        position: &Position,
+        mov: Move,
+        bonus: i32,
+        previous: Option<PieceTo>,
+    ) {
+        // Scaling the bonus down as the score grows keeps it within the bounds
+        fn apply(entry: &mut i32, bonus: i32) {
+            *entry += bonus * 32 - *entry * bonus.abs() * 32 / MAX_HISTORY;
+        }
+
+        apply(
+            &mut self.butterfly[History::butterfly_index(position.is_white_move, mov)],
+            bonus,
+        );
+        if let Some(previous) = previous {
+            let index = History::continuation_index(previous, piece_to(position, mov));
+            apply(&mut self.continuation[index], bonus);
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Stage {
+    TtMove,
+    GoodCaptures,
+    Refutations,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// N is the capacity of the move lists, which depends on the variant (see
+// movelist.rs)
+pub struct MovePicker<const N: usize = MAX_MOVES> {
+    stage: Stage,
+    tt_move: Option<Move>,
+    captures: MoveList<N>,
+    // Captures that lose material, in the order they were put aside
+    bad_captures: MoveList<N>,
+    quiets: MoveList<N>,
+    // The counter move and killer moves, tried from the back
+    refutations: [Move; 3],
+    refutations_left: usize,
+    quiets_scored: bool,
+    // Whether this picker only returns good captures, for the quiescence search
+    tactical: bool,
+}
+
+impl<const N: usize> MovePicker<N> {
+    // Order the legal moves of a position for the main search
+    pub fn new(
+        position: &Position,
+        moves: MoveList<N>,
+        tt_move: Option<Move>,
+        history: &History,
+        ply: usize,
+        previous: Option<PieceTo>,
+    ) -> MovePicker<N> {
+        let mut captures = MoveList::new();
+        let mut quiets = MoveList::new();
+        for &mov in moves.iter() {
+            if position.is_tactical(mov) {
+                captures.push_scored(mov, mvv_lva(position, mov));
+            } else {
+                quiets.push(mov);
+            }
+        }
+
+        let [first_killer, second_killer] = history.killers(ply);
+        MovePicker {
+            stage: Stage::TtMove,
+            tt_move,
+            captures,
+            bad_captures: MoveList::new(),
+            quiets,
+            refutations: [history.counter_move(previous), second_killer, first_killer],
+            refutations_left: 3,
+            quiets_scored: false,
+            tactical: false,
+        }
+    }
+
+    // Order only the captures and promotions, for the quiescence search.
+    // Captures that lose material are left out altogether.
+    pub fn tactical(position: &Position, moves: MoveList<N>) -> MovePicker<N> {
+        let mut captures = MoveList::new();
+        for &mov in moves.iter().filter(|&&x| position.is_tactical(x)) {
+            captures.push_scored(mov, mvv_lva(position, mov));
+        }
+
+        MovePicker {
+            stage: Stage::GoodCaptures,
+            tt_move: None,
+            captures,
+            bad_captures: MoveList::new(),
+            quiets: MoveList::new(),
+            refutations: [Move::NONE; 3],
+            refutations_left: 0,
+            quiets_scored: true,
+            tactical: true,
+        }
+    }
+
+    // Drop the remaining quiet moves, once the search has decided that they
+    // are not worth trying. Captures are still picked.
+    pub fn skip_quiets(&mut self) {
+        self.refutations_left = 0;
+        self.quiets.clear();
+    }
+
+    pub fn next(
+        &mut self,
+        position: &Position,
+        history: &History,
+        previous: Option<PieceTo>,
+    ) -> Option<Move> {
+        loop {
+            let picked = match self.stage {
+                Stage::TtMove => self.tt_move.and_then(|x| {
+                    take(&mut self.captures, x).or_else(|| take(&mut self.quiets, x))
+                }),
+                Stage::GoodCaptures => loop {
+                    match pick_best(&mut self.captures) {
+                        Some(mov) if !position.see_ge(mov, 0) => self.bad_captures.push(mov),
+                        picked => break picked,
+                    }
+                },
+                Stage::Refutations => {
+                    // Refutations are only played if they are legal here and
+                    // were not already picked as the TT move
+                    let mut picked = None;
+                    while self.refutations_left > 0 {
+                        self.refutations_left -= 1;
+                        let refutation = self.refutations[self.refutations_left];
+                        picked = take(&mut self.quiets, refutation);
+                        if picked.is_some() {
+                            break;
+                        }
+                    }
+                    picked
+                }
+                Stage::Quiets => {
+                    if !self.quiets_scored {
+                        for i in 0..self.quiets.len() {
+                            let score = history.quiet_score(position, self.quiets[i], previous);
+                            self.quiets.set_score(i, score);
+                        }
+                        self.quiets_scored = true;
+                    }
+                    pick_best(&mut self.quiets)
+                }
+                Stage::BadCaptures if self.bad_captures.is_empty() => None,
+                Stage::BadCaptures => Some(self.bad_captures.remove(0)),
+                Stage::Done => return None,
+            };
+
+            match picked {
+                Some(mov) => return Some(mov),
+                None => {
+                    self.stage = match self.stage {
+                        Stage::TtMove => Stage::GoodCaptures,
+                        Stage::GoodCaptures if self.tactical => Stage::Done,
+                        Stage::GoodCaptures => Stage::Refutations,
+                        Stage::Refutations => Stage::Quiets,
+                        Stage::Quiets => Stage::BadCaptures,
+                        Stage::BadCaptures | Stage::Done => Stage::Done,
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn mvv_lva(position: &Position, mov: Move) -> i32 {
+    let (from, to) = squares(mov);
+    let attacker = ORDER_VALUES[position.piece_index(from).unwrap() % 7];
+    let victim = position.piece_index(to).map_or(1, |x| ORDER_VALUES[x % 7]);
+    victim * 100 - attacker
+}
+
+// Remove a specific move from the list, if present
+fn take<const N: usize>(moves: &mut MoveList<N>, mov: Move) -> Option<Move> {
+    let index = moves.iter().position(|&x| x == mov)?;
+    Some(moves.swap_remove(index))
+}
+
+// Remove the highest scoring move from the list
+fn pick_best<const N: usize>(moves: &mut MoveList<N>) -> Option<Move> {
+    let index = (0..moves.len()).max_by_key(|&i| (moves.score(i), std::cmp::Reverse(i)))?;
+    Some(moves.swap_remove(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::{move_to_str, str_to_move};
+
+    fn pick_all<const N: usize>(
+        position: &Position,
+        picker: &mut MovePicker<N>,
+        history: &History,
+        previous: Option<PieceTo>,
+    ) -> Vec<String> {
+        let mut moves = vec![];
+        while let Some(mov) = picker.next(position, history, previous) {
+            moves.push(move_to_str(mov));
+        }
+        moves
+    }
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn every_move_is_picked_once() {
+        let position = Position::from(KIWIPETE);
+        let history = History::new();
+        let tt_move = str_to_move("e2a6", position).unwrap();
+        let mut picker = MovePicker::new(
+            &position,
+            position.moves(),
+            Some(tt_move),
+            &history,
+            0,
+            None,
+        );
+        let mut picked = pick_all(&position, &mut picker, &history, None);
+        assert_eq!(picked[0], "e2a6");
+
+        let mut expected: Vec<String> = position.moves().into_iter().map(move_to_str).collect();
+        picked.sort();
+        expected.sort();
+        assert_eq!(picked, expected);
+    }
+
+    #[test]
+    fn skipping_quiets_keeps_captures() {
+        let position = Position::from(KIWIPETE);
+        let history = History::new();
+        let mut picker = MovePicker::new(&position, position.moves(), None, &history, 0, None);
+        let first = picker.next(&position, &history, None).unwrap();
+        picker.skip_quiets();
+        let rest = pick_all(&position, &mut picker, &history, None);
+        assert!(position.is_tactical(first));
+        assert_eq!(rest.len(), 7);
+        assert!(rest
+            .iter()
+            .all(|x| position.is_tactical(str_to_move(x, position).unwrap())));
+    }
+
+    // The number of captures in the position that do and do not lose material
+    fn count_captures(position: &Position) -> (usize, usize) {
+        let captures: Vec<Move> = position
+            .moves()
+            .into_iter()
+            .filter(|&x| position.is_tactical(x))
+            .collect();
+        let good = captures.iter().filter(|&&x| position.see_ge(x, 0)).count();
+        (good, captures.len() - good)
+    }
+
+    #[test]
+    fn good_captures_first_and_bad_captures_last() {
+        let position = Position::from(KIWIPETE);
+        let history = History::new();
+        let mut picker = MovePicker::new(&position, position.moves(), None, &history, 0, None);
+        let picked: Vec<Move> = pick_all(&position, &mut picker, &history, None)
+            .iter()
+            .map(|x| str_to_move(x, position).unwrap())
+            .collect();
+
+        let (good, bad) = count_captures(&position);
+        assert_eq!((good, bad), (3, 5));
+        assert!(picked[..good]
+            .iter()
+            .all(|&x| position.is_tactical(x) && position.see_ge(x, 0)));
+        assert!(picked[good..picked.len() - bad]
+            .iter()
+            .all(|&x| !position.is_tactical(x)));
+        assert!(picked[picked.len() - bad..]
+            .iter()
+            .all(|&x| position.is_tactical(x) && !position.see_ge(x, 0)));
+
+        // The bishop trade is the capture of the most valuable victim by the
+        // least valuable attacker
+        assert_eq!(move_to_str(picked[0]), "e2a6");
+    }
+
+    #[test]
+    fn killers_and_counter_moves_lead_quiets() {
+        let position = Position::from(KIWIPETE);
+        let mut history = History::new();
+        let killer = str_to_move("a2a3", position).unwrap();
+        let counter = str_to_move("g2g3", position).unwrap();
+        let previous = Some((crate::position::B_PAWN, 20));
+        history.update(&position, counter, &[], 3, 5, previous);
+        history.update(&position, killer, &[], 3, 2, None);
+
+        let mut picker = MovePicker::new(&position, position.moves(), None, &history, 2, previous);
+        let picked = pick_all(&position, &mut picker, &history, previous);
+        let (good, _) = count_captures(&position);
+        assert_eq!(picked[good], "a2a3");
+        assert_eq!(picked[good + 1], "g2g3");
+    }
+
+    #[test]
+    fn history_orders_quiets() {
+        let position = Position::new();
+        let mut history = History::new();
+        let good = str_to_move("b1c3", position).unwrap();
+        let bad = str_to_move("a2a3", position).unwrap();
+        history.update(&position, good, &[bad], 6, 0, None);
+        history.clear_killers();
+
+        assert!(history.quiet_score(&position, good, None) > 0);
+        assert!(history.quiet_score(&position, bad, None) < 0);
+
+        let mut picker = MovePicker::new(&position, position.moves(), None, &history, 0, None);
+        let picked = pick_all(&position, &mut picker, &history, None);
+        assert_eq!(picked.first().unwrap(), "b1c3");
+        assert_eq!(picked.last().unwrap(), "a2a3");
+    }
+
+    #[test]
+    fn drops_have_their_own_history() {
+        // The pawn drop is encoded with a1 as its origin, like the bishop move
+        let position = Position::from_variant(
+            "4k3/8/8/8/8/8/8/B3K3[P] w - - 0 1",
+            crate::variant::Variant::Crazyhouse,
+        );
+        let mut history = History::new();
+        let drop = str_to_move("P@e5", position).unwrap();
+        let bishop = str_to_move("a1e5", position).unwrap();
+        history.update(&position, drop, &[bishop], 6, 0, None);
+
+        assert!(history.quiet_score(&position, drop, None) > 0);
+        assert!(history.quiet_score(&position, bishop, None) < 0);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let position = Position::new();
+        let mut history = History::new();
+        let mov = str_to_move("e2e4", position).unwrap();
+        for _ in 0..1000 {
+            history.update(&position, mov, &[], 20, 0, None);
+        }
+        assert!(history.quiet_score(&position, mov, None) <= MAX_HISTORY);
+    }
+
+    #[test]
+    fn tactical_picker_skips_quiets_and_bad_captures() {
+        let position = Position::from(KIWIPETE);
+        let history = History::new();
+        let mut picker = MovePicker::tactical(&position, position.moves());
+        let picked = pick_all(&position, &mut picker, &history, None);
+        assert_eq!(picked.len(), count_captures(&position).0);
+        assert!(picked.iter().all(|x| {
+            let mov = str_to_move(x, position).unwrap();
+            position.is_tactical(mov) && position.see_ge(mov, 0)
+        }));
+    }
+}
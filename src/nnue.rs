@@ -0,0 +1,411 @@
+// nnue.rs implements an efficiently updatable neural network (NNUE) evaluator.
+//
+// The network is a simple 768 -> HIDDEN x 2 -> 1 perceptron. Each of the 768
+// inputs is a (piece, square) pair seen from one side's perspective, and the
+// hidden layer ("accumulator") is kept for both perspectives so that it can be
+// updated incrementally as pieces are added to and removed from the board.
+// The output layer combines the side to move's accumulator with the other
+// side's through a clipped ReLU, using quantised integer arithmetic only.
+
+use crate::position::{PieceObserver, Position, B_PIECES, W_PIECES};
+
+use std::fs;
+
+pub const INPUTS: usize = 768;
+pub const HIDDEN: usize = 128;
+
+// Quantisation factors of the accumulator (QA) and output weights (QB). SCALE
+// converts the network output into centipawns.
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+// Network file layout (all values little-endian):
+//   magic "CHNN", u32 version, u32 hidden layer size,
+//   i16 feature weights [INPUTS][HIDDEN], i16 feature biases [HIDDEN],
+//   i16 output weights [2 * HIDDEN], i32 output bias
+const MAGIC: &[u8; 4] = b"CHNN";
+const VERSION: u32 = 1;
+const FILE_SIZE: usize = 12 + 2 * (INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN) + 4;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Network {
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    // Load a network from a file in the format described above
+    pub fn load(path: &str) -> Result<Network, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Network::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, String> {
+        if bytes.len() < 12 || &bytes[0..4] != MAGIC {
+            return Err(String::from("not a network file"));
+        }
+
+        let mut reader = Reader { bytes, offset: 4 };
+        let version = reader.u32();
+        if version != VERSION {
+            return Err(format!("unsupported network version {}", version));
+        }
+        let hidden = reader.u32() as usize;
+        if hidden != HIDDEN {
+            return Err(format!(
+                "hidden layer size {} does not match {}",
+                hidden, HIDDEN
+            ));
+        }
+        if bytes.len() != FILE_SIZE {
+            return Err(format!(
+                "expected {} bytes, found {}",
+                FILE_SIZE,
+                bytes.len()
+            ));
+        }
+
+        Ok(Network {
+            feature_weights: reader.i16s(INPUTS * HIDDEN),
+            feature_biases: reader.i16s(HIDDEN),
+            output_weights: reader.i16s(2 * HIDDEN),
+            output_bias: reader.u32() as i32,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FILE_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(HIDDEN as u32).to_le_bytes());
+        let weights = self
+            .feature_weights
+            .iter()
+            .chain(&self.feature_biases)
+            .chain(&self.output_weights);
+        for weight in weights {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    // Create a network with small pseudo-random weights. Such a network plays
+    // terribly, but exercises every part of the evaluator.
+    pub fn random(seed: u64) -> Network {
+        let mut state = seed | 1;
+        let mut next = |range: i16| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % (2 * range as u64 + 1)) as i16 - range
+        };
+
+        let feature_weights = (0..INPUTS * HIDDEN).map(|_| next(32)).collect();
+        let feature_biases = (0..HIDDEN).map(|_| next(32)).collect();
+        let output_weights = (0..2 * HIDDEN).map(|_| next(64)).collect();
+        let output_bias = next(1000) as i32;
+
+        Network {
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        }
+    }
+
+    // Evaluate the accumulated position from the point of view of the side
+    // to move, in centipawns.
+    pub fn evaluate(&self, accumulator: &Accumulator, is_white_move: bool) -> i32 {
+        let (us, them) = if is_white_move {
+            (&accumulator.values[0], &accumulator.values[1])
+        } else {
+            (&accumulator.values[1], &accumulator.values[0])
+        };
+        let (us_weights, them_weights) = self.output_weights.split_at(HIDDEN);
+
+        let mut sum: i64 = 0;
+        for i in 0..HIDDEN {
+            sum += (clipped_relu(us[i]) * us_weights[i] as i32) as i64;
+            sum += (clipped_relu(them[i]) * them_weights[i] as i32) as i64;
+        }
+
+        ((sum + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+
+    // Evaluate a position from white's point of view, computing the
+    // accumulator from scratch.
+    pub fn evaluate_position(&self, position: &Position) -> i32 {
+        let accumulator = Accumulator::new(self, position);
+        let score = self.evaluate(&accumulator, position.is_white_move);
+        if position.is_white_move {
+            score
+        } else {
+            -score
+        }
+    }
+}
+
+#[inline(always)]
+fn clipped_relu(x: i16) -> i32 {
+    (x as i32).clamp(0, QA)
+}
+
+// Input feature index of a piece (indexed like the 'pieces' array) on a square,
+// seen from white's (perspective 0) or black's (perspective 1) side. Black sees
+// the board flipped vertically with the colors swapped.
+#[inline(always)]
+fn feature_index(perspective: usize, piece: usize, sq: usize) -> usize {
+    let color = piece / 7;
+    let kind = piece % 7;
+    if perspective == 0 {
+        (color * 6 + kind) * 64 + sq
+    } else {
+        ((1 - color) * 6 + kind) * 64 + (sq ^ 56)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn u32(&mut self) -> u32 {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(&self.bytes[self.offset..self.offset + 4]);
+        self.offset += 4;
+        u32::from_le_bytes(buf)
+    }
+
+    fn i16s(&mut self, count: usize) -> Vec<i16> {
+        let end = self.offset + 2 * count;
+        let values = self.bytes[self.offset..end]
+            .chunks_exact(2)
+            .map(|x| i16::from_le_bytes([x[0], x[1]]))
+            .collect();
+        self.offset = end;
+        values
+    }
+}
+
+// The hidden layer values of a position for both perspectives
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Accumulator {
+    values: [[i16; HIDDEN]; 2],
+}
+
+impl Accumulator {
+    // Compute the accumulator of a position from scratch
+    pub fn new(network: &Network, position: &Position) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [[0; HIDDEN]; 2],
+        };
+        for perspective in 0..2 {
+            accumulator.values[perspective].copy_from_slice(&network.feature_biases);
+        }
+
+        for (piece, &bitboard) in position.pieces.iter().enumerate() {
+            if piece == W_PIECES || piece == B_PIECES {
+                continue;
+            }
+            let mut bitboard = bitboard;
+            while bitboard != 0 {
+                accumulator.add(network, piece, bitboard.trailing_zeros() as usize);
+                bitboard &= bitboard - 1;
+            }
+        }
+        accumulator
+    }
+
+    pub fn add(&mut self, network: &Network, piece: usize, sq: usize) {
+        for perspective in 0..2 {
+            let offset = feature_index(perspective, piece, sq) * HIDDEN;
+            let weights = &network.feature_weights[offset..offset + HIDDEN];
+            for (value, weight) in self.values[perspective].iter_mut().zip(weights) {
+                *value += weight;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, network: &Network, piece: usize, sq: usize) {
+        for perspective in 0..2 {
+            let offset = feature_index(perspective, piece, sq) * HIDDEN;
+            let weights = &network.feature_weights[offset..offset + HIDDEN];
+            for (value, weight) in self.values[perspective].iter_mut().zip(weights) {
+                *value -= weight;
+            }
+        }
+    }
+}
+
+// Applies the piece changes of a move to an accumulator
+struct Update<'a> {
+    network: &'a Network,
+    accumulator: &'a mut Accumulator,
+}
+
+impl PieceObserver for Update<'_> {
+    fn add_piece(&mut self, piece: usize, sq: usize) {
+        self.accumulator.add(self.network, piece, sq);
+    }
+
+    fn remove_piece(&mut self, piece: usize, sq: usize) {
+        self.accumulator.remove(self.network, piece, sq);
+    }
+}
+
+// A stack of accumulators following the moves played from a root position.
+// Positions are copy-make, so unmaking a move only pops the top accumulator.
+pub struct AccumulatorStack {
+    stack: Vec<Accumulator>,
+}
+
+impl AccumulatorStack {
+    pub fn new(network: &Network, position: &Position) -> AccumulatorStack {
+        AccumulatorStack {
+            stack: vec![Accumulator::new(network, position)],
+        }
+    }
+
+    // Play a move on the given position, pushing the updated accumulator
+    pub fn play_move(
+        &mut self,
+        network: &Network,
+        position: &Position,
+        move_bits: crate::position::Move,
+    ) -> Position {
+        let mut accumulator = *self.current();
+        let mut next = *position;
+        next.play_move_observed(
+            move_bits,
+            &mut Update {
+                network,
+                accumulator: &mut accumulator,
+            },
+        );
+        self.stack.push(accumulator);
+        next
+    }
+
+    // A null move changes no pieces, so the accumulator is simply repeated
+    pub fn play_null_move(&mut self) {
+        let accumulator = *self.current();
+        self.stack.push(accumulator);
+    }
+
+    pub fn unmake_move(&mut self) {
+        assert!(self.stack.len() > 1, "Unmake without a matching move");
+        self.stack.pop();
+    }
+
+    pub fn current(&self) -> &Accumulator {
+        self.stack.last().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::str_to_move;
+
+    // Play the moves on the FEN position incrementally, checking that the
+    // accumulator always matches one computed from scratch, then unmake them.
+    macro_rules! test_incremental {
+        ($test_name:ident, $fen:literal, $moves:expr) => {
+            #[test]
+            fn $test_name() {
+                let network = Network::random(7);
+                let mut position = Position::from($fen);
+                let mut stack = AccumulatorStack::new(&network, &position);
+                let root = *stack.current();
+
+                for move_str in $moves.iter() {
+                    let move_bits = str_to_move(move_str, position).unwrap();
+                    position = stack.play_move(&network, &position, move_bits);
+                    assert_eq!(*stack.current(), Accumulator::new(&network, &position));
+                }
+
+                for _ in $moves.iter() {
+                    stack.unmake_move();
+                }
+                assert_eq!(*stack.current(), root);
+            }
+        };
+    }
+
+    test_incremental!(
+        incremental_quiet,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ["e2e4", "c7c5", "g1f3", "d7d6"]
+    );
+    test_incremental!(
+        incremental_captures,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a2"]
+    );
+    test_incremental!(
+        incremental_castling,
+        "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1",
+        ["e1g1", "e8c8", "f1e1", "d7d5"]
+    );
+    test_incremental!(
+        incremental_castling_2,
+        "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1",
+        ["e1c1", "e8g8"]
+    );
+    test_incremental!(
+        incremental_en_passant,
+        "4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1",
+        ["d7d5", "e5d6"]
+    );
+    test_incremental!(
+        incremental_promotion,
+        "1n6/P3k3/8/8/8/8/7p/4K1N1 w - - 0 1",
+        ["a7b8q", "h2g1n", "b8a8", "g1e2"]
+    );
+
+    #[test]
+    fn bytes_round_trip() {
+        let network = Network::random(42);
+        let bytes = network.to_bytes();
+        assert_eq!(bytes.len(), FILE_SIZE);
+        assert_eq!(Network::from_bytes(&bytes), Ok(network));
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let mut bytes = Network::random(42).to_bytes();
+        bytes[0] = b'X';
+        assert!(Network::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let bytes = Network::random(42).to_bytes();
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(&bytes[..6]).is_err());
+    }
+
+    #[test]
+    fn load_missing_file() {
+        assert!(Network::load("no/such/network.nnue").is_err());
+    }
+
+    #[test]
+    fn evaluation_is_color_symmetric() {
+        let network = Network::random(3);
+        let position =
+            Position::from("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let mirrored =
+            Position::from("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3");
+        assert_eq!(
+            network.evaluate_position(&position),
+            -network.evaluate_position(&mirrored)
+        );
+    }
+}
@@ -0,0 +1,339 @@
+// options.rs defines the UCI options supported by challenger, and stores the
+// values they have been set to with the 'setoption' command.
+
+use crate::skill;
+
+use std::fmt;
+
+pub enum OptionKind {
+    Check(bool),
+    Spin(i64, i64, i64), // Default, min, max
+    Str(&'static str),
+    Combo(&'static str, &'static [&'static str]), // Default, choices
+}
+
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+    // Hidden options are not listed in reply to 'uci', but can still be set.
+    // They switch search features on and off for testing.
+    pub hidden: bool,
+}
+
+pub const OPTIONS: &[UciOption] = &[
+    UciOption {
+        name: "Hash",
+        kind: OptionKind::Spin(16, 1, 4096),
+        hidden: false,
+    },
+    UciOption {
+        name: "Threads",
+        kind: OptionKind::Spin(1, 1, 256),
+        hidden: false,
+    },
+    UciOption {
+        name: "Use NNUE",
+        kind: OptionKind::Check(false),
+        hidden: false,
+    },
+    UciOption {
+        name: "EvalFile",
+        kind: OptionKind::Str(""),
+        hidden: false,
+    },
+    UciOption {
+        name: "Skill Level",
+        kind: OptionKind::Spin(20, 0, 20),
+        hidden: false,
+    },
+    UciOption {
+        name: "UCI_LimitStrength",
+        kind: OptionKind::Check(false),
+        hidden: false,
+    },
+    UciOption {
+        name: "UCI_Elo",
+        kind: OptionKind::Spin(1500, skill::MIN_ELO, skill::MAX_ELO),
+        hidden: false,
+    },
+    UciOption {
+        name: "Contempt",
+        kind: OptionKind::Spin(0, -100, 100),
+        hidden: false,
+    },
+    UciOption {
+        name: "DynamicContempt",
+        kind: OptionKind::Check(false),
+        hidden: false,
+    },
+    UciOption {
+        name: "UCI_Opponent",
+        kind: OptionKind::Str(""),
+        hidden: false,
+    },
+    UciOption {
+        name: "UCI_Variant",
+        kind: OptionKind::Combo(
+            "chess",
+            &[
+                "chess",
+                "kingofthehill",
+                "3check",
+                "antichess",
+                "crazyhouse",
+            ],
+        ),
+        hidden: false,
+    },
+    UciOption {
+        name: "UCI_Chess960",
+        kind: OptionKind::Check(false),
+        hidden: false,
+    },
+    UciOption {
+        name: "OwnBook",
+        kind: OptionKind::Check(false),
+        hidden: false,
+    },
+    UciOption {
+        name: "BookFile",
+        kind: OptionKind::Str(""),
+        hidden: false,
+    },
+    UciOption {
+        name: "BookDepth",
+        kind: OptionKind::Spin(20, 1, 255),
+        hidden: false,
+    },
+    UciOption {
+        name: "BookBestMove",
+        kind: OptionKind::Check(false),
+        hidden: false,
+    },
+    UciOption {
+        name: "NullMovePruning",
+        kind: OptionKind::Check(true),
+        hidden: true,
+    },
+    UciOption {
+        name: "LateMoveReductions",
+        kind: OptionKind::Check(true),
+        hidden: true,
+    },
+    UciOption {
+        name: "ReverseFutilityPruning",
+        kind: OptionKind::Check(true),
+        hidden: true,
+    },
+    UciOption {
+        name: "FutilityPruning",
+        kind: OptionKind::Check(true),
+        hidden: true,
+    },
+    UciOption {
+        name: "LateMovePruning",
+        kind: OptionKind::Check(true),
+        hidden: true,
+    },
+    UciOption {
+        name: "Razoring",
+        kind: OptionKind::Check(true),
+        hidden: true,
+    },
+];
+
+impl fmt::Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match self.kind {
+            OptionKind::Check(default) => write!(f, "check default {}", default),
+            OptionKind::Spin(default, min, max) => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptionKind::Str("") => write!(f, "string default <empty>"),
+            OptionKind::Str(default) => write!(f, "string default {}", default),
+            OptionKind::Combo(default, choices) => {
+                write!(f, "combo default {}", default)?;
+                choices.iter().try_for_each(|x| write!(f, " var {}", x))
+            }
+        }
+    }
+}
+
+// Find an option by name. As required by the UCI protocol, option names are
+// not case sensitive.
+pub fn find(name: &str) -> Option<&'static UciOption> {
+    OPTIONS.iter().find(|x| x.name.eq_ignore_ascii_case(name))
+}
+
+// The current value of every option in OPTIONS, stored as strings in the same
+// order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    values: Vec<String>,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        let values = OPTIONS
+            .iter()
+            .map(|x| match x.kind {
+                OptionKind::Check(default) => default.to_string(),
+                OptionKind::Spin(default, _, _) => default.to_string(),
+                OptionKind::Str(default) => default.to_string(),
+                OptionKind::Combo(default, _) => default.to_string(),
+            })
+            .collect();
+        Options { values }
+    }
+
+    // Set an option, validating the value against the option's type. Returns
+    // the canonical name of the option that was set.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<&'static str, String> {
+        let index = OPTIONS
+            .iter()
+            .position(|x| x.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("No such option: {}", name))?;
+        let option = &OPTIONS[index];
+
+        let value = match option.kind {
+            OptionKind::Check(_) => match value {
+                "true" | "false" => value.to_string(),
+                _ => return Err(format!("Invalid value for {}: {}", option.name, value)),
+            },
+            OptionKind::Spin(_, min, max) => match value.parse::<i64>() {
+                Ok(x) if (min..=max).contains(&x) => x.to_string(),
+                _ => return Err(format!("Invalid value for {}: {}", option.name, value)),
+            },
+            OptionKind::Str(_) if value == "<empty>" => String::new(),
+            OptionKind::Str(_) => value.to_string(),
+            // Combo values are not case sensitive, and are stored as listed
+            OptionKind::Combo(_, choices) => {
+                match choices.iter().find(|x| x.eq_ignore_ascii_case(value)) {
+                    Some(choice) => choice.to_string(),
+                    None => return Err(format!("Invalid value for {}: {}", option.name, value)),
+                }
+            }
+        };
+
+        self.values[index] = value;
+        Ok(option.name)
+    }
+
+    fn get(&self, name: &str) -> &str {
+        let index = OPTIONS.iter().position(|x| x.name == name);
+        &self.values[index.expect("Unknown option")]
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        self.get(name) == "true"
+    }
+
+    pub fn get_int(&self, name: &str) -> i64 {
+        self.get(name).parse().unwrap()
+    }
+
+    pub fn get_str(&self, name: &str) -> &str {
+        self.get(name)
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let options = Options::new();
+        assert_eq!(options.get_int("Hash"), 16);
+        assert!(!options.get_bool("Use NNUE"));
+        assert_eq!(options.get_str("EvalFile"), "");
+        assert!(options.get_bool("NullMovePruning"));
+    }
+
+    #[test]
+    fn set_check() {
+        let mut options = Options::new();
+        assert_eq!(options.set("use nnue", "true"), Ok("Use NNUE"));
+        assert!(options.get_bool("Use NNUE"));
+        assert!(options.set("Use NNUE", "yes").is_err());
+        assert!(options.get_bool("Use NNUE"));
+    }
+
+    #[test]
+    fn set_string() {
+        let mut options = Options::new();
+        assert!(options.set("EvalFile", "nets/my net.nnue").is_ok());
+        assert_eq!(options.get_str("EvalFile"), "nets/my net.nnue");
+        assert!(options.set("EvalFile", "<empty>").is_ok());
+        assert_eq!(options.get_str("EvalFile"), "");
+    }
+
+    #[test]
+    fn set_spin() {
+        let mut options = Options::new();
+        assert!(options.set("hash", "64").is_ok());
+        assert_eq!(options.get_int("Hash"), 64);
+        assert!(options.set("Hash", "0").is_err());
+        assert!(options.set("Hash", "lots").is_err());
+        assert_eq!(options.get_int("Hash"), 64);
+    }
+
+    #[test]
+    fn set_hidden() {
+        let mut options = Options::new();
+        assert_eq!(options.set("razoring", "false"), Ok("Razoring"));
+        assert!(!options.get_bool("Razoring"));
+    }
+
+    #[test]
+    fn set_combo() {
+        let mut options = Options::new();
+        assert_eq!(options.get_str("UCI_Variant"), "chess");
+        assert!(options.set("UCI_Variant", "KingOfTheHill").is_ok());
+        assert_eq!(options.get_str("UCI_Variant"), "kingofthehill");
+        assert!(options.set("UCI_Variant", "shogi").is_err());
+        assert_eq!(options.get_str("UCI_Variant"), "kingofthehill");
+    }
+
+    #[test]
+    fn set_unknown() {
+        assert!(Options::new().set("Nonexistent", "1").is_err());
+    }
+
+    #[test]
+    fn display() {
+        let lines: Vec<String> = OPTIONS
+            .iter()
+            .filter(|x| !x.hidden)
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "option name Hash type spin default 16 min 1 max 4096",
+                "option name Threads type spin default 1 min 1 max 256",
+                "option name Use NNUE type check default false",
+                "option name EvalFile type string default <empty>",
+                "option name Skill Level type spin default 20 min 0 max 20",
+                "option name UCI_LimitStrength type check default false",
+                "option name UCI_Elo type spin default 1500 min 1000 max 2800",
+                "option name Contempt type spin default 0 min -100 max 100",
+                "option name DynamicContempt type check default false",
+                "option name UCI_Opponent type string default <empty>",
+                "option name UCI_Variant type combo default chess var chess var kingofthehill var 3check var antichess var crazyhouse",
+                "option name UCI_Chess960 type check default false",
+                "option name OwnBook type check default false",
+                "option name BookFile type string default <empty>",
+                "option name BookDepth type spin default 20 min 1 max 255",
+                "option name BookBestMove type check default false",
+            ]
+        );
+    }
+}
@@ -0,0 +1,613 @@
+// pgn.rs reads and writes games in Portable Game Notation
+// (https://www.chessprogramming.org/Portable_Game_Notation). A game is a list
+// of tags followed by its movetext: moves in Standard Algebraic Notation
+// (SAN), annotated with comments, NAGs and variations, and then the result.
+//
+// The reader is tolerant of PGN as found in the wild. Move numbers may be
+// missing or written against their moves, suffix annotations such as '!?' are
+// read as their NAGs, and unbalanced variations or a missing result do not
+// lose the game.
+
+use crate::datagen::GameResult;
+use crate::position::{Move, Position};
+use crate::search::{MATE, MATE_BOUND};
+
+use std::fmt;
+use std::time::Duration;
+
+// The Seven Tag Roster, which is written first and in this order
+const ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+// Suffix annotations and the NAGs they stand for
+const SUFFIXES: [(&str, u8); 6] = [
+    ("!", 1),
+    ("?", 2),
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+];
+
+// Movetext is wrapped to lines of at most this length
+const LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PgnMove {
+    pub san: String,
+    // Numeric Annotation Glyphs, such as 1 for a good move
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    // Lines played instead of this move
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(san: &str) -> PgnMove {
+        PgnMove {
+            san: san.to_string(),
+            ..PgnMove::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    // A comment before the first move
+    pub comment: Option<String>,
+    // The main line
+    pub moves: Vec<PgnMove>,
+    pub result: Option<GameResult>,
+}
+
+impl Game {
+    // An empty game from a position, which is recorded in the FEN tag unless
+    // it is the standard start position
+    pub fn new(start: &Position) -> Game {
+        let mut game = Game::default();
+        if *start != Position::new() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &start.to_fen());
+        }
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|x| x.0 == name).map(|x| x.1.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|x| x.0 == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // The position the game starts from, given by the FEN tag if present.
+    // The FEN may leave out the move counters.
+    pub fn start(&self) -> Result<Position, String> {
+        match self.tag("FEN") {
+            Some(fen) => Position::try_from(fen),
+            None => Ok(Position::new()),
+        }
+    }
+
+    // Resolve the moves of the main line, stopping at the first one that is
+    // not legal
+    pub fn replay(&self) -> Result<Vec<Move>, String> {
+        let mut position = self.start()?;
+        let mut moves = vec![];
+        for pgn_move in &self.moves {
+            let mov = position.parse_san(&pgn_move.san)?;
+            position.play_move(mov);
+            moves.push(mov);
+        }
+        Ok(moves)
+    }
+
+    // Add a move played from 'position' to the main line
+    pub fn push(&mut self, position: &Position, mov: Move, comment: Option<String>) {
+        self.moves.push(PgnMove {
+            comment,
+            ..PgnMove::new(&position.move_to_san(mov))
+        });
+    }
+}
+
+// A comment with the engine's score for a move, from white's point of view,
+// and the time left on its clock, in the form most GUIs read:
+// '[%eval 0.35] [%clk 0:04:58]'
+pub fn annotation(score: i32, clock: Duration) -> String {
+    let eval = if score >= MATE_BOUND {
+        format!("#{}", (MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("#-{}", (MATE + score) / 2)
+    } else {
+        format!("{:.2}", score as f64 / 100.0)
+    };
+    let seconds = clock.as_secs();
+    format!(
+        "[%eval {}] [%clk {}:{:02}:{:02}]",
+        eval,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn result_str(result: Option<GameResult>) -> &'static str {
+    result.map_or("*", GameResult::as_str)
+}
+
+fn parse_result(token: &str) -> Option<Option<GameResult>> {
+    match token {
+        "1-0" => Some(Some(GameResult::WhiteWin)),
+        "0-1" => Some(Some(GameResult::BlackWin)),
+        "1/2-1/2" => Some(Some(GameResult::Draw)),
+        "*" => Some(None),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    OpenVariation,
+    CloseVariation,
+    Result(Option<GameResult>),
+    San(String),
+}
+
+// Split a tag pair such as '[White "Carlsen, Magnus"]' into its name and value
+fn parse_tag(text: &str) -> Option<Token> {
+    let (name, value) = text.trim().split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+    Some(Token::Tag(name.to_string(), value))
+}
+
+// Read a word of movetext: a NAG, a result, or a move with its number and
+// suffix annotation, either of which may be missing
+fn parse_word(word: &str, tokens: &mut Vec<Token>) {
+    if let Some(nag) = word.strip_prefix('$') {
+        tokens.extend(nag.parse().ok().map(Token::Nag));
+        return;
+    }
+    if let Some(result) = parse_result(word) {
+        tokens.push(Token::Result(result));
+        return;
+    }
+
+    let word = match word.find(|x: char| !x.is_ascii_digit()) {
+        Some(i) if word[i..].starts_with('.') => word[i..].trim_start_matches('.'),
+        None => "",
+        _ => word,
+    };
+    let san = word.trim_end_matches(['!', '?']);
+    let san = san.strip_suffix("e.p.").unwrap_or(san);
+    if !san.is_empty() {
+        tokens.push(Token::San(san.to_string()));
+    }
+    let suffix = &word[san.len()..];
+    if let Some(&(_, nag)) = SUFFIXES.iter().find(|x| x.0 == suffix) {
+        tokens.push(Token::Nag(nag));
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        let at_line_start = line_start;
+        line_start = c == '\n';
+        match c {
+            // Lines starting with '%' are escaped from PGN
+            '%' if at_line_start => {
+                chars.by_ref().find(|&x| x == '\n');
+                line_start = true;
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&x| x != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+                line_start = true;
+            }
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&x| x != '}').collect();
+                let words: Vec<&str> = comment.split_whitespace().collect();
+                tokens.push(Token::Comment(words.join(" ")));
+            }
+            '[' => {
+                let tag: String = chars.by_ref().take_while(|&x| x != ']').collect();
+                tokens.extend(parse_tag(&tag));
+            }
+            '(' => tokens.push(Token::OpenVariation),
+            ')' => tokens.push(Token::CloseVariation),
+            _ if c.is_whitespace() => (),
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&x) = chars.peek() {
+                    if x.is_whitespace() || "{};()[".contains(x) {
+                        break;
+                    }
+                    word.push(x);
+                    chars.next();
+                }
+                parse_word(&word, &mut tokens);
+            }
+        }
+    }
+    tokens
+}
+
+fn append_comment(comment: &mut Option<String>, text: String) {
+    *comment = match comment.take() {
+        Some(previous) => Some(format!("{} {}", previous, text)),
+        None => Some(text),
+    };
+}
+
+// Read every game from a PGN file. A game ends at its result, or when the tags
+// of the next game begin.
+pub fn parse(text: &str) -> Vec<Game> {
+    let mut games = vec![];
+    let mut game = Game::default();
+    // The main line followed by any variations open within it
+    let mut lines: Vec<Vec<PgnMove>> = vec![vec![]];
+    let mut in_movetext = false;
+
+    let mut finish = |game: &mut Game, lines: &mut Vec<Vec<PgnMove>>| {
+        while lines.len() > 1 {
+            close_variation(lines);
+        }
+        game.moves = std::mem::take(&mut lines[0]);
+        if game.result.is_none() {
+            game.result = game.tag("Result").and_then(parse_result).flatten();
+        }
+        if !game.tags.is_empty() || !game.moves.is_empty() {
+            games.push(std::mem::take(game));
+        }
+    };
+
+    for token in tokenize(text) {
+        if in_movetext && matches!(token, Token::Tag(..)) {
+            finish(&mut game, &mut lines);
+            in_movetext = false;
+        }
+        let depth = lines.len();
+        let line = lines.last_mut().unwrap();
+        match token {
+            Token::Tag(name, value) => game.tags.push((name, value)),
+            Token::Comment(text) => {
+                match line.last_mut() {
+                    Some(pgn_move) => append_comment(&mut pgn_move.comment, text),
+                    // Comments before the first move of a variation are lost
+                    None if depth == 1 => append_comment(&mut game.comment, text),
+                    None => (),
+                }
+                in_movetext = true;
+            }
+            Token::Nag(nag) => line.last_mut().iter_mut().for_each(|x| x.nags.push(nag)),
+            Token::OpenVariation => {
+                lines.push(vec![]);
+                in_movetext = true;
+            }
+            Token::CloseVariation if depth > 1 => close_variation(&mut lines),
+            Token::CloseVariation => (),
+            Token::Result(result) if depth == 1 => {
+                game.result = result;
+                finish(&mut game, &mut lines);
+                in_movetext = false;
+            }
+            // A result within a variation ends the variation only
+            Token::Result(_) => close_variation(&mut lines),
+            Token::San(san) => {
+                line.push(PgnMove::new(&san));
+                in_movetext = true;
+            }
+        }
+    }
+    finish(&mut game, &mut lines);
+    games
+}
+
+// Attach the innermost variation to the move it is played instead of
+fn close_variation(lines: &mut Vec<Vec<PgnMove>>) {
+    let variation = lines.pop().unwrap();
+    if let Some(pgn_move) = lines.last_mut().unwrap().last_mut() {
+        if !variation.is_empty() {
+            pgn_move.variations.push(variation);
+        }
+    }
+}
+
+// Write the movetext of a line whose first move is made at 'first_ply',
+// counting from 0 for white's first move. Black's moves are numbered when they
+// do not directly follow white's.
+fn write_line(line: &[PgnMove], first_ply: usize, words: &mut Vec<String>) {
+    let mut numbered = false;
+    for (i, pgn_move) in line.iter().enumerate() {
+        let ply = first_ply + i;
+        if ply.is_multiple_of(2) {
+            words.push(format!("{}.", ply / 2 + 1));
+        } else if !numbered {
+            words.push(format!("{}...", ply / 2 + 1));
+        }
+        numbered = true;
+
+        words.push(pgn_move.san.clone());
+        words.extend(pgn_move.nags.iter().map(|x| format!("${}", x)));
+        if let Some(comment) = &pgn_move.comment {
+            words.push(format!("{{{}}}", comment.replace('}', "")));
+            numbered = false;
+        }
+        for variation in pgn_move.variations.iter().filter(|x| !x.is_empty()) {
+            let start = words.len();
+            write_line(variation, ply, words);
+            words[start].insert(0, '(');
+            words.last_mut().unwrap().push(')');
+            numbered = false;
+        }
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut write_tag = |name: &str, value: &str| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)
+        };
+        for name in ROSTER.iter() {
+            match *name {
+                "Result" => write_tag(name, result_str(self.result))?,
+                _ => write_tag(name, self.tag(name).unwrap_or("?"))?,
+            }
+        }
+        for (name, value) in self.tags.iter().filter(|x| !ROSTER.contains(&&*x.0)) {
+            write_tag(name, value)?;
+        }
+        writeln!(f)?;
+
+        let mut words = vec![];
+        if let Some(comment) = &self.comment {
+            words.push(format!("{{{}}}", comment.replace('}', "")));
+        }
+        let start = self.start().unwrap_or_default();
+        let first_ply =
+            2 * (start.full_num as usize).saturating_sub(1) + !start.is_white_move as usize;
+        write_line(&self.moves, first_ply, &mut words);
+        words.push(result_str(self.result).to_string());
+
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && line.len() + 1 + word.len() > LINE_LENGTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::str_to_move;
+
+    const GAMES: &str = r#"[Event "Casual game"]
+[White "Anderssen, Adolf"]
+[Black "Kieseritzky, Lionel"]
+[Result "1-0"]
+
+1. e4 e5 2. f4 exf4 {The King's Gambit, accepted} 3. Bc4 Qh4+ 4. Kf1 b5?!
+5. Bxb5 Nf6 6. Nf3 (6. Qf3 Nc6) 6... Qh6 $1 ; A comment to the end of the line
+7. d3 1-0
+
+[Event "Unfinished"]
+[FEN "4k3/8/8/8/8/8/8/4K2R w K - 0 1"]
+
+1.O-O Kd7 *
+"#;
+
+    fn main_line(game: &Game) -> String {
+        let moves: Vec<&str> = game.moves.iter().map(|x| x.san.as_str()).collect();
+        moves.join(" ")
+    }
+
+    #[test]
+    fn parse_games() {
+        let games = parse(GAMES);
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Anderssen, Adolf"));
+        assert_eq!(game.tag("Round"), None);
+        assert_eq!(game.result, Some(GameResult::WhiteWin));
+        assert_eq!(
+            main_line(game),
+            "e4 e5 f4 exf4 Bc4 Qh4+ Kf1 b5 Bxb5 Nf6 Nf3 Qh6 d3"
+        );
+        assert_eq!(
+            game.moves[3].comment.as_deref(),
+            Some("The King's Gambit, accepted")
+        );
+        assert_eq!(game.moves[7].nags, vec![6]);
+        assert_eq!(game.moves[11].nags, vec![1]);
+        assert_eq!(
+            game.moves[11].comment.as_deref(),
+            Some("A comment to the end of the line")
+        );
+        assert_eq!(
+            game.moves[10].variations,
+            vec![vec![PgnMove::new("Qf3"), PgnMove::new("Nc6")]]
+        );
+
+        assert_eq!(main_line(&games[1]), "O-O Kd7");
+        assert_eq!(games[1].result, None);
+    }
+
+    #[test]
+    fn parse_nested_variations() {
+        let games = parse(
+            "{Opening} 1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) (1. c4) 1... c5!! \
+             {Sicilian} $14 2. Nf3",
+        );
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.comment.as_deref(), Some("Opening"));
+        assert_eq!(main_line(game), "e4 c5 Nf3");
+        assert_eq!(game.result, None);
+
+        let variations = &game.moves[0].variations;
+        assert_eq!(variations.len(), 2);
+        assert_eq!(variations[0].len(), 3);
+        assert_eq!(
+            variations[0][1].variations,
+            vec![vec![PgnMove::new("Nf6"), PgnMove::new("c4")]]
+        );
+        assert_eq!(variations[1], vec![PgnMove::new("c4")]);
+        assert_eq!(game.moves[1].nags, vec![3, 14]);
+        assert_eq!(game.moves[1].comment.as_deref(), Some("Sicilian"));
+    }
+
+    #[test]
+    fn parse_tolerates_errors() {
+        let text = "% An escaped line (\n\
+                    [White \"A \\\"quoted\\\" name\"]\n\
+                    [Result \"0-1\"]\n\
+                    1.e4 e5 2.Nf3 (2.f4 exf4 3.Nf3\n\
+                    [White \"B\"]\n\
+                    1. d4 12... Nf6 2.c4 e6 3. Nc3 Bb4 4. Qc2 ) 0-0 5. a3 Bxc3+ 6. Qxc3 b6 ; no result";
+        let games = parse(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A \"quoted\" name"));
+        assert_eq!(main_line(&games[0]), "e4 e5 Nf3");
+        assert_eq!(games[0].moves[2].variations[0].len(), 3);
+        // The result is taken from the tag when the movetext has none
+        assert_eq!(games[0].result, Some(GameResult::BlackWin));
+
+        assert_eq!(
+            main_line(&games[1]),
+            "d4 Nf6 c4 e6 Nc3 Bb4 Qc2 0-0 a3 Bxc3+ Qxc3 b6"
+        );
+        assert_eq!(games[1].replay().unwrap().len(), 12);
+        assert!(parse("").is_empty());
+        assert!(parse("  \n% nothing here\n").is_empty());
+    }
+
+    #[test]
+    fn replay_games() {
+        let games = parse(GAMES);
+        assert_eq!(games[0].replay().unwrap().len(), 13);
+        let mut position = games[1].start().unwrap();
+        for mov in games[1].replay().unwrap() {
+            position.play_move(mov);
+        }
+        assert_eq!(position.to_fen(), "8/3k4/8/8/8/8/8/5RK1 w - - 2 2");
+
+        let game = Game {
+            moves: vec![PgnMove::new("e4"), PgnMove::new("e4")],
+            ..Game::default()
+        };
+        assert!(game.replay().is_err());
+    }
+
+    #[test]
+    fn start_from_fen_tag() {
+        let mut game = Game::default();
+        assert_eq!(game.start(), Ok(Position::new()));
+        game.set_tag("FEN", "4k3/8/8/8/8/8/8/4K2R w K -");
+        assert_eq!(
+            game.start().map(|x| x.to_fen()),
+            Ok(String::from("4k3/8/8/8/8/8/8/4K2R w K - 0 1"))
+        );
+        game.set_tag("FEN", "4k3/8/8/8/8/8/8/4K2R");
+        assert!(game.start().is_err());
+        assert!(game.replay().is_err());
+    }
+
+    #[test]
+    fn write_game() {
+        let mut game = parse(GAMES).remove(0);
+        game.moves[0].comment = Some(String::from("Best by test"));
+        assert_eq!(
+            game.to_string(),
+            r#"[Event "Casual game"]
+[Site "?"]
+[Date "?"]
+[Round "?"]
+[White "Anderssen, Adolf"]
+[Black "Kieseritzky, Lionel"]
+[Result "1-0"]
+
+1. e4 {Best by test} 1... e5 2. f4 exf4 {The King's Gambit, accepted} 3. Bc4
+Qh4+ 4. Kf1 b5 $6 5. Bxb5 Nf6 6. Nf3 (6. Qf3 Nc6) 6... Qh6 $1
+{A comment to the end of the line} 7. d3 1-0
+"#
+        );
+    }
+
+    #[test]
+    fn write_then_read() {
+        let games = parse(GAMES);
+        for game in &games {
+            let mut reread = parse(&game.to_string());
+            assert_eq!(reread.len(), 1);
+            // The roster tags are always written
+            reread[0].tags.retain(|x| x.1 != "?" && x.0 != "Result");
+            let mut expected = game.clone();
+            expected.tags.retain(|x| x.0 != "Result");
+            assert_eq!(reread[0], expected);
+        }
+
+        let nested = parse("1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) c5 $14 2. Nf3 1/2-1/2");
+        let written = nested[0].to_string();
+        assert!(written
+            .ends_with("\n1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) 1... c5 $14 2. Nf3 1/2-1/2\n"));
+        assert_eq!(parse(&written)[0].moves, nested[0].moves);
+    }
+
+    #[test]
+    fn write_engine_game() {
+        let start = Position::from("6k1/1p3ppp/8/8/8/8/5PPP/R5K1 b - - 4 30");
+        let mut game = Game::new(&start);
+        game.set_tag("White", "challenger");
+        game.set_tag("White", "challenger-rs");
+
+        let mut position = start;
+        for (mov, score, seconds) in [("b7b6", -3, 61), ("a1a8", MATE - 1, 3725)] {
+            let mov = str_to_move(mov, position).unwrap();
+            let comment = annotation(score, Duration::from_secs(seconds));
+            game.push(&position, mov, Some(comment));
+            position.play_move(mov);
+        }
+        game.result = Some(GameResult::WhiteWin);
+
+        let written = game.to_string();
+        assert!(written.contains("[White \"challenger-rs\"]\n"));
+        assert!(
+            written.contains("[SetUp \"1\"]\n[FEN \"6k1/1p3ppp/8/8/8/8/5PPP/R5K1 b - - 4 30\"]\n")
+        );
+        assert!(written.ends_with(
+            "\n30... b6 {[%eval -0.03] [%clk 0:01:01]} 31. Ra8# {[%eval #1] [%clk 1:02:05]} 1-0\n"
+        ));
+        assert_eq!(parse(&written)[0].replay().unwrap().len(), 2);
+        assert!(!Game::new(&Position::new()).to_string().contains("FEN"));
+    }
+
+    #[test]
+    fn annotations() {
+        let clock = Duration::from_millis(299_900);
+        assert_eq!(annotation(35, clock), "[%eval 0.35] [%clk 0:04:59]");
+        assert_eq!(annotation(-250, clock), "[%eval -2.50] [%clk 0:04:59]");
+        assert_eq!(annotation(MATE - 3, clock), "[%eval #2] [%clk 0:04:59]");
+        assert_eq!(annotation(-MATE + 4, clock), "[%eval #-2] [%clk 0:04:59]");
+    }
+}
@@ -3,51 +3,60 @@
 // color to move, current piece positions, castling rights, etc. A Position
 // serves as a complete snapshot of a point in time of a chess match.
 
-// FILE constants: bitboards representing their respective files of the board with
-// 1's set in the bit positions for the file, and 0's otherwise.
-
+use crate::attacks;
+use crate::evaluate;
+use crate::movelist::{MoveList, MoveStore, MAX_DROP_MOVES, MAX_MOVES};
+use crate::types::{Bitboard, Color, Piece, PieceType, Square};
+use crate::variant::{Outcome, Variant, CHECKS_TO_WIN};
 use std::fmt;
 
-const A_FILE: u64 = 0x0101010101010101;
-const B_FILE: u64 = 0x0202020202020202;
-const C_FILE: u64 = 0x0404040404040404;
-const D_FILE: u64 = 0x0808080808080808;
-const E_FILE: u64 = 0x1010101010101010;
-const F_FILE: u64 = 0x2020202020202020;
-const G_FILE: u64 = 0x4040404040404040;
-const H_FILE: u64 = 0x8080808080808080;
+// FILE constants: bitboards representing their respective files of the board with
+// 1's set in the bit positions for the file, and 0's otherwise.
+pub const A_FILE: u64 = 0x0101010101010101;
+pub const B_FILE: u64 = 0x0202020202020202;
+pub const C_FILE: u64 = 0x0404040404040404;
+pub const D_FILE: u64 = 0x0808080808080808;
+pub const E_FILE: u64 = 0x1010101010101010;
+pub const F_FILE: u64 = 0x2020202020202020;
+pub const G_FILE: u64 = 0x4040404040404040;
+pub const H_FILE: u64 = 0x8080808080808080;
 
 // RANK constants: bitboards representing their respective ranks of the board with
 // 1's set in the bit positions for the rank, and 0's otherwise.
-const RANK_1: u64 = 0x00000000000000FF;
-const RANK_2: u64 = 0x000000000000FF00;
-const RANK_3: u64 = 0x0000000000FF0000;
-const RANK_4: u64 = 0x00000000FF000000;
-const RANK_5: u64 = 0x000000FF00000000;
-const RANK_6: u64 = 0x0000FF0000000000;
-const RANK_7: u64 = 0x00FF000000000000;
-const RANK_8: u64 = 0xFF00000000000000;
-
-const CORNERS: u64 = (RANK_1 | RANK_8) & (A_FILE | H_FILE);
+pub const RANK_1: u64 = 0x00000000000000FF;
+pub const RANK_2: u64 = 0x000000000000FF00;
+pub const RANK_3: u64 = 0x0000000000FF0000;
+pub const RANK_4: u64 = 0x00000000FF000000;
+pub const RANK_5: u64 = 0x000000FF00000000;
+pub const RANK_6: u64 = 0x0000FF0000000000;
+pub const RANK_7: u64 = 0x00FF000000000000;
+pub const RANK_8: u64 = 0xFF00000000000000;
+
+// Piece values used by static exchange evaluation, indexed like the 'pieces'
+// array. The king can capture, but is never captured.
+const SEE_VALUES: [i32; 6] = [100, 500, 320, 330, 900, 20000];
+
+// The order in which pieces are used to recapture, least valuable first
+const SEE_ORDER: [usize; 6] = [W_PAWN, W_KNIGHT, W_BISHOP, W_ROOK, W_QUEEN, W_KING];
 
 // Piece constants for indexing the 'pieces' field of a position
-const W_PAWN: usize = 0;
-const W_ROOK: usize = 1;
-const W_KNIGHT: usize = 2;
-const W_BISHOP: usize = 3;
-const W_QUEEN: usize = 4;
-const W_KING: usize = 5;
-const W_PIECES: usize = 6;
-
-const B_PAWN: usize = 7;
-const B_ROOK: usize = 8;
-const B_KNIGHT: usize = 9;
-const B_BISHOP: usize = 10;
-const B_QUEEN: usize = 11;
-const B_KING: usize = 12;
-const B_PIECES: usize = 13;
-
-// The representation of a 'Move' is a 16-bit integer.
+pub(crate) const W_PAWN: usize = 0;
+pub(crate) const W_ROOK: usize = 1;
+pub(crate) const W_KNIGHT: usize = 2;
+pub(crate) const W_BISHOP: usize = 3;
+pub(crate) const W_QUEEN: usize = 4;
+pub(crate) const W_KING: usize = 5;
+pub(crate) const W_PIECES: usize = 6;
+
+pub(crate) const B_PAWN: usize = 7;
+pub(crate) const B_ROOK: usize = 8;
+pub(crate) const B_KNIGHT: usize = 9;
+pub(crate) const B_BISHOP: usize = 10;
+pub(crate) const B_QUEEN: usize = 11;
+pub(crate) const B_KING: usize = 12;
+pub(crate) const B_PIECES: usize = 13;
+
+// A 'Move' wraps a 16-bit integer, read through its accessors.
 // This implementation choice is inspired by
 // https://www.chessprogramming.org/Encoding_Moves as well as Stockfish's own move implementation.
 //
@@ -58,8 +67,11 @@ const B_PIECES: usize = 13;
 // bit 14-15: special move flag: promotion (1), en passant (2), castling (3)
 // SPECIAL CASE: To represent pawn double forward moves, the promotion bits will
 // all be set but the special move flag will be 0 (normal move).
+// SPECIAL CASE: Promotions to a king, as allowed in antichess, set all of the
+// promotion bits along with the castling flag.
+// SPECIAL CASE: Crazyhouse drops set the lowest promotion bit along with the
+// castling flag, and hold the type of the dropped piece in the origin bits.
 
-type Move = u16;
 const ORIGIN_SQ_BITS: u16 = 0x3F;
 
 const DEST_BITS_OFFSET: u32 = ORIGIN_SQ_BITS.count_ones();
@@ -75,56 +87,189 @@ const SPECIAL_MOVE_BITS_OFFSET: u32 =
 const SPECIAL_MOVE_BITS: u16 = TWO_BITS << SPECIAL_MOVE_BITS_OFFSET;
 
 // Special move types
-const PROMOTION: Move = 0x1 << SPECIAL_MOVE_BITS_OFFSET;
-const ENPASSANT: Move = 0x2 << SPECIAL_MOVE_BITS_OFFSET;
-const CASTLING: Move = 0x3 << SPECIAL_MOVE_BITS_OFFSET;
-const PAWN_DOUBLE_FWD: Move = 0x3 << PROMOTION_PIECE_BITS_OFFSET;
-
-pub fn str_to_move(move_string: &str, position: Position) -> Move {
-    let mut move_bits: Move = 0;
-    let mut move_chars = move_string.chars();
-
-    let start_sq_num = sq_num(move_chars.next().unwrap(), move_chars.next().unwrap());
-    let dest_sq_num = sq_num(move_chars.next().unwrap(), move_chars.next().unwrap());
-    let promotion = move_chars.next();
-    let is_king_move =
-        (position.pieces[W_KING] & position.pieces[B_KING]) & (1u64 << start_sq_num) != 0;
-    let sq_diff = start_sq_num as isize - dest_sq_num as isize;
-
-    move_bits |= start_sq_num as u16;
-    move_bits |= (dest_sq_num as u16) << DEST_BITS_OFFSET;
-
-    if promotion.is_some() {
-        match promotion.unwrap() {
-            'Q' | 'q' => move_bits |= 3 << 12,
-            'R' | 'r' => move_bits |= 2 << 12,
-            'B' | 'b' => move_bits |= 1 << 12,
-            _ => (), // Since knights are 0's, no need to do anything
-        }
-        move_bits |= PROMOTION;
-    } else if 1u64 << dest_sq_num == position.passant_sq {
-        move_bits |= ENPASSANT;
-    } else if is_king_move && sq_diff == 2 {
-        move_bits |= CASTLING;
-    }
-
-    return move_bits;
+const PROMOTION: u16 = 0x1 << SPECIAL_MOVE_BITS_OFFSET;
+const ENPASSANT: u16 = 0x2 << SPECIAL_MOVE_BITS_OFFSET;
+const CASTLING: u16 = 0x3 << SPECIAL_MOVE_BITS_OFFSET;
+const PAWN_DOUBLE_FWD: u16 = 0x3 << PROMOTION_PIECE_BITS_OFFSET;
+const KING_PROMOTION: u16 = CASTLING | PAWN_DOUBLE_FWD;
+const DROP: u16 = CASTLING | 0x1 << PROMOTION_PIECE_BITS_OFFSET;
+
+// The pieces a pawn can promote to, in the order of their promotion bits
+const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Move(u16);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MoveKind {
+    Normal,
+    DoublePawnPush,
+    Promotion(PieceType),
+    EnPassant,
+    Castling,
+    Drop(PieceType),
+}
+
+impl Move {
+    // A move from a1 to a1, standing for no move in tables of moves
+    pub const NONE: Move = Move(0);
+
+    // Panics if a promotion is to a pawn. The origin square of a drop is
+    // ignored.
+    pub fn new(from: Square, to: Square, kind: MoveKind) -> Move {
+        let from = match kind {
+            MoveKind::Drop(piece) => piece.index() as u16,
+            _ => from.index() as u16,
+        };
+        let squares = from | (to.index() as u16) << DEST_BITS_OFFSET;
+        let flags = match kind {
+            MoveKind::Normal => 0,
+            MoveKind::DoublePawnPush => PAWN_DOUBLE_FWD,
+            MoveKind::Promotion(PieceType::King) => KING_PROMOTION,
+            MoveKind::Promotion(piece) => {
+                let index = PROMOTION_PIECES.iter().position(|&x| x == piece);
+                PROMOTION
+                    | (index.expect("Invalid promotion piece") as u16)
+                        << PROMOTION_PIECE_BITS_OFFSET
+            }
+            MoveKind::EnPassant => ENPASSANT,
+            MoveKind::Castling => CASTLING,
+            MoveKind::Drop(_) => DROP,
+        };
+        Move(squares | flags)
+    }
+
+    // The 16 bit encoding described above, for compact storage
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub(crate) const fn from_bits(bits: u16) -> Move {
+        Move(bits)
+    }
+
+    // The origin square, which is meaningless for a drop
+    pub fn from(self) -> Square {
+        Square::from_index((self.0 & ORIGIN_SQ_BITS) as usize)
+    }
+
+    // The piece type dropped, for a Crazyhouse drop
+    pub fn dropped(self) -> Option<PieceType> {
+        match self.kind() {
+            MoveKind::Drop(piece) => Some(piece),
+            _ => None,
+        }
+    }
+
+    pub fn to(self) -> Square {
+        Square::from_index(((self.0 & DEST_SQ_BITS) >> DEST_BITS_OFFSET) as usize)
+    }
+
+    pub fn promotion(self) -> Option<PieceType> {
+        match self.kind() {
+            MoveKind::Promotion(piece) => Some(piece),
+            _ => None,
+        }
+    }
+
+    pub fn kind(self) -> MoveKind {
+        match self.0 & SPECIAL_MOVE_BITS {
+            PROMOTION => {
+                let index = (self.0 & PROMOTION_PIECE_BITS) >> PROMOTION_PIECE_BITS_OFFSET;
+                MoveKind::Promotion(PROMOTION_PIECES[index as usize])
+            }
+            ENPASSANT => MoveKind::EnPassant,
+            CASTLING if self.0 & PROMOTION_PIECE_BITS == PAWN_DOUBLE_FWD => {
+                MoveKind::Promotion(PieceType::King)
+            }
+            CASTLING if self.0 & PROMOTION_PIECE_BITS != 0 => {
+                let piece = PieceType::from_index((self.0 & ORIGIN_SQ_BITS) as usize);
+                MoveKind::Drop(piece.expect("Invalid drop piece"))
+            }
+            CASTLING => MoveKind::Castling,
+            _ if self.0 & PROMOTION_PIECE_BITS == PAWN_DOUBLE_FWD => MoveKind::DoublePawnPush,
+            _ => MoveKind::Normal,
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", move_to_str(*self))
+    }
+}
+
+// Piece letters of Standard Algebraic Notation, indexed like the white pieces
+// of the 'pieces' array
+const SAN_PIECES: [char; 6] = ['P', 'R', 'N', 'B', 'Q', 'K'];
+
+// Find the legal move written in long algebraic notation, if there is one.
+// Castling may be written either as the king moving two squares or as the
+// king capturing its own rook; a king move to the same square comes first.
+pub fn str_to_move(move_string: &str, position: Position) -> Option<Move> {
+    let moves = position.moves();
+    let find = |chess960| {
+        (moves.iter().copied())
+            .find(|&x| move_to_uci(x, chess960).eq_ignore_ascii_case(move_string))
+    };
+    find(true).or_else(|| find(false))
+}
+
+// Convert a move to its long algebraic notation, as used by UCI (e.g. 'e7e8q')
+pub fn move_to_str(mov: Move) -> String {
+    move_to_uci(mov, false)
+}
+
+// Long algebraic notation of a move, with castling written as the king moving
+// two squares or, for Chess960, as the king capturing its own rook. Drops are
+// written as the piece, '@' and the square (e.g. 'P@e4').
+pub fn move_to_uci(mov: Move, chess960: bool) -> String {
+    if let Some(piece) = mov.dropped() {
+        return format!("{}@{}", piece.to_char(), mov.to());
+    }
+    let to = match mov.kind() {
+        MoveKind::Castling if !chess960 => {
+            let from = mov.from().index();
+            let home = from - from % 8;
+            let king_side = mov.to().index() > from;
+            Square::from_index(if king_side { home + 6 } else { home + 2 })
+        }
+        _ => mov.to(),
+    };
+    let mut move_string = format!("{}{}", mov.from(), to);
+    if let Some(piece) = mov.promotion() {
+        move_string.push(piece.to_char().to_ascii_lowercase());
+    }
+    move_string
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Position {
-    pieces: [u64; 14], // Bitboards
-    passant_sq: u64,   // En Passant square
-
-    // Castling rights
-    w_king_castle: bool,
-    w_queen_castle: bool,
-    b_king_castle: bool,
-    b_queen_castle: bool,
-
-    is_white_move: bool, // Side to move
-    hlf_clock: u8,       // Halfmove clock
-    full_num: u8,        // Fullmove number
+    pub(crate) pieces: [u64; 14], // Bitboards
+    pub(crate) passant_sq: u64,   // En Passant square
+
+    // Castling rights, as the squares of the rooks that can still castle. A
+    // rook on the king side of its king castles king side. Storing the rooks
+    // rather than the sides supports Chess960, where they start on any file.
+    pub(crate) castling_rooks: u64,
+
+    pub(crate) is_white_move: bool, // Side to move
+    pub(crate) hlf_clock: u8,       // Halfmove clock
+    pub(crate) full_num: u16,       // Fullmove number
+
+    pub(crate) variant: Variant,
+    // Checks given by white and by black, counted in Three-check
+    pub(crate) checks: [u8; 2],
+    // Pieces in hand of white and of black, by piece type from pawn to queen,
+    // and the squares of promoted pieces, which return to hand as pawns. Both
+    // are only used in Crazyhouse.
+    pub(crate) hands: [[u8; 5]; 2],
+    pub(crate) promoted: u64,
 }
 
 impl fmt::Display for Position {
@@ -138,32 +283,55 @@ impl fmt::Display for Position {
         }
         write!(f, "], ")?;
         write!(f, "passant: {}, ", self.passant_sq)?;
-        write!(f, "w_king_castle {}, ", self.w_king_castle)?;
-        write!(f, "w_queen_castle {}, ", self.w_queen_castle)?;
-        write!(f, "b_king_castle {}, ", self.b_king_castle)?;
-        write!(f, "b_queen_castle {}, ", self.b_queen_castle)?;
+        write!(f, "castling_rooks {}, ", self.castling_rooks)?;
         write!(f, "is_white_move {}, ", self.is_white_move)?;
         write!(f, "hlf_clock {}, ", self.hlf_clock)?;
-        write!(f, "full_num {}", self.full_num)
+        write!(f, "full_num {}, ", self.full_num)?;
+        write!(f, "variant {}, ", self.variant.name())?;
+        write!(f, "checks {:?}, ", self.checks)?;
+        write!(f, "hands {:?}, ", self.hands)?;
+        write!(f, "promoted {}", self.promoted)
     }
 }
 
 impl Position {
+    // Panics if the FEN is invalid. Use try_from() for FEN strings that are
+    // not known to be valid.
     pub fn from(fen: &str) -> Position {
+        Position::try_from(fen).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Parse a FEN string. The halfmove clock and fullmove number may be left
+    // out, and default to 0 and 1.
+    pub fn try_from(fen: &str) -> Result<Position, String> {
+        let invalid = |field: &str| format!("Invalid {} in FEN: {}", field, fen);
         let mut fen_tokens = fen.split_whitespace();
 
-        // Fen string: Piece positions
-        let piece_string = fen_tokens
-            .next()
-            .unwrap()
-            .split('/')
-            .flat_map(|x| x.chars().rev());
+        // Fen string: Piece positions. Crazyhouse FEN adds the pieces in hand,
+        // either in brackets (e.g. '[Qp]') or as a ninth rank, and marks
+        // promoted pieces with a '~'.
+        let placement = fen_tokens.next().ok_or_else(|| invalid("placement"))?;
+        let (board, holdings) = match placement.split_once('[') {
+            Some((board, holdings)) => (board, holdings.trim_end_matches(']')),
+            None if placement.matches('/').count() == 8 => placement.rsplit_once('/').unwrap(),
+            None => (placement, ""),
+        };
+        if !is_valid_board(board) || !holdings.chars().all(|x| "PNBRQpnbrq".contains(x)) {
+            return Err(invalid("placement"));
+        }
+        let piece_string = board.split('/').flat_map(|x| x.chars().rev());
 
         let mut square_num: isize = 63;
         let mut pieces = [0; 14];
+        let mut promoted = 0;
 
         for piece in piece_string {
             match piece {
+                // Follows the promoted piece, so comes first in reverse
+                '~' => {
+                    promoted |= 1u64 << square_num;
+                    continue;
+                }
                 'P' => pieces[W_PAWN] |= 1u64 << square_num,
                 'R' => pieces[W_ROOK] |= 1u64 << square_num,
                 'N' => pieces[W_KNIGHT] |= 1u64 << square_num,
@@ -192,45 +360,148 @@ impl Position {
             pieces[B_PIECES] |= pieces[i + 7];
         }
 
-        // Fen string: Active color
-        let is_white_move = fen_tokens.next().unwrap() == "w";
-
-        // Fen string: Castling availability
-        let castle_rights = fen_tokens.next().unwrap();
+        let mut hands = [[0; 5]; 2];
+        for c in holdings.chars() {
+            let piece = PieceType::ALL[..5]
+                .iter()
+                .find(|x| x.to_char() == c.to_ascii_uppercase());
+            if let Some(piece) = piece {
+                hands[c.is_ascii_lowercase() as usize][piece.index()] += 1;
+            }
+        }
 
-        // Fen string: En passant target square
-        let passant_sq_str = fen_tokens.next().unwrap();
+        // Fen string: Active color
+        let is_white_move = match fen_tokens.next() {
+            Some("w") => true,
+            Some("b") => false,
+            _ => return Err(invalid("active color")),
+        };
 
-        // Default to no passant sq
-        let mut passant_sq: u64 = 0;
-        if passant_sq_str.len() != 1 {
-            let mut chars = passant_sq_str.chars();
-            passant_sq = sq_to_bitboard(chars.next().unwrap(), chars.next().unwrap());
+        // Fen string: Castling availability. Besides the usual 'KQkq', files
+        // of castling rooks are accepted as in X-FEN and Shredder-FEN.
+        let castling = fen_tokens.next().ok_or_else(|| invalid("castling"))?;
+        if castling != "-" && !castling.chars().all(|x| "KQkqABCDEFGHabcdefgh".contains(x)) {
+            return Err(invalid("castling"));
         }
+        let castling_rooks = castling_rooks(&pieces, castling);
+
+        // Fen string: En passant target square, or '-' for none
+        let passant_sq = match fen_tokens.next() {
+            Some("-") => 0,
+            Some(square) => {
+                let square = square
+                    .parse::<Square>()
+                    .map_err(|_| invalid("en passant"))?;
+                square.bitboard().0
+            }
+            None => return Err(invalid("en passant")),
+        };
 
-        // Fen string: Halfmove clock
-        let hlf_clock = fen_tokens.next().unwrap().parse().unwrap();
+        // Three-check FEN adds the checks each side has left (e.g. '3+3')
+        // here, or the checks each side has given (e.g. '+0+0') at the end
+        let mut fen_tokens = fen_tokens.peekable();
+        let mut checks = [0; 2];
+        if let Some(remaining) = fen_tokens.next_if(|x| x.contains('+') && !x.starts_with('+')) {
+            checks = parse_checks(remaining)
+                .ok_or_else(|| invalid("checks"))?
+                .map(|x| CHECKS_TO_WIN.saturating_sub(x));
+        }
 
-        // Fen string: Fullmove number
-        let full_num = fen_tokens.next().unwrap().parse().unwrap();
+        // Fen string: Halfmove clock and fullmove number
+        let mut counter = || fen_tokens.next_if(|x| !x.starts_with('+'));
+        let hlf_clock = counter()
+            .map_or(Ok(0), str::parse)
+            .map_err(|_| invalid("halfmove clock"))?;
+        let full_num = counter()
+            .map_or(Ok(1), str::parse)
+            .map_err(|_| invalid("fullmove number"))?;
+
+        if let Some(given) = fen_tokens.next() {
+            checks = parse_checks(given).ok_or_else(|| invalid("checks"))?;
+        }
 
-        Position {
+        Ok(Position {
             pieces,
             passant_sq,
-            w_king_castle: castle_rights.contains('K'),
-            w_queen_castle: castle_rights.contains('Q'),
-            b_king_castle: castle_rights.contains('k'),
-            b_queen_castle: castle_rights.contains('q'),
+            castling_rooks,
             is_white_move,
             hlf_clock,
             full_num,
+            variant: Variant::Standard,
+            checks,
+            hands,
+            promoted,
+        })
+    }
+
+    // A position of the given variant. Antichess has no castling.
+    pub fn from_variant(fen: &str, variant: Variant) -> Position {
+        Position::try_from_variant(fen, variant).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Parse a FEN string of a position played under the rules of 'variant'
+    pub fn try_from_variant(fen: &str, variant: Variant) -> Result<Position, String> {
+        let mut position = Position::try_from(fen)?;
+        position.variant = variant;
+        if !variant.has_royal_king() {
+            position.castling_rooks = 0;
         }
+        Ok(position)
     }
 
     pub fn new() -> Position {
         Position::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
     }
 
+    // The Chess960 start position with the given Scharnagl number, from 0 to
+    // 959. Number 518 is the standard start position.
+    pub fn chess960(number: u16) -> Position {
+        // Squares of the two knights among the five left after placing the
+        // bishops and queen
+        const KNIGHTS: [(usize, usize); 10] = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+        let mut n = number as usize % 960;
+        let mut rank = [' '; 8];
+
+        rank[n % 4 * 2 + 1] = 'b';
+        n /= 4;
+        rank[n % 4 * 2] = 'b';
+        n /= 4;
+
+        let empty =
+            |rank: &[char; 8]| -> Vec<usize> { (0..8).filter(|&x| rank[x] == ' ').collect() };
+        let queen = empty(&rank)[n % 6];
+        rank[queen] = 'q';
+        n /= 6;
+
+        let squares = empty(&rank);
+        let (first, second) = KNIGHTS[n];
+        rank[squares[first]] = 'n';
+        rank[squares[second]] = 'n';
+
+        // The king stands between the two rooks on the remaining squares
+        for (sq, piece) in empty(&rank).into_iter().zip(['r', 'k', 'r']) {
+            rank[sq] = piece;
+        }
+
+        let black: String = rank.iter().collect();
+        let white = black.to_ascii_uppercase();
+        Position::from(&format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            black, white
+        ))
+    }
+
     // The play_move() function attempts to play the requested move and apply the rules
     // of chess to the board. It will not consider the legality of the move it is given,
     // and will instead just apply regular chess logic to that move. For example, a king
@@ -241,26 +512,65 @@ impl Position {
     // The focus of the play_move function is speed instead of legality, as challenger
     // has a strictly legal move generator. Moves from stdin could still supply the
     // engine with illegal moves, in which case the engine will gladly play them.
-    pub fn play_move(&mut self, move_bits: Move) {
+    pub fn play_move(&mut self, mov: Move) {
+        self.play_move_observed(mov, &mut NoObserver);
+    }
+
+    // Play a move as play_move() does, reporting every piece that is added to
+    // or removed from the board to the given observer. This allows state that
+    // depends on piece placement (such as NNUE accumulators) to be updated
+    // incrementally instead of being recomputed from scratch.
+    pub fn play_move_observed<O: PieceObserver>(&mut self, mov: Move, observer: &mut O) {
+        self.move_pieces(mov, observer);
+
+        // Count the checks given by the side that just moved
+        if self.variant == Variant::ThreeCheck && self.is_in_check() {
+            self.checks[self.is_white_move as usize] += 1;
+        }
+    }
+
+    // Update the board, castling rights, en passant square, clocks and side to
+    // move for a move
+    fn move_pieces<O: PieceObserver>(&mut self, mov: Move, observer: &mut O) {
         // Increment halfmove clock early. Resets will happen based on move played
         self.hlf_clock += 1;
-        self.full_num += !self.is_white_move as u8;
+        self.full_num += !self.is_white_move as u16;
 
         let self_offset: usize = (!self.is_white_move as usize) * 7;
         self.is_white_move = !self.is_white_move;
 
-        let start_sq_num = move_bits & 0x3F;
-        let dest_sq_num = (move_bits >> 6) & 0x3F;
+        let start_sq_num = mov.from().index();
+        let dest_sq_num = mov.to().index();
         let start_square = 1u64 << start_sq_num;
         let dest_square = 1u64 << dest_sq_num;
         let sq_diff = start_sq_num as isize - dest_sq_num as isize;
 
-        let promotion_piece = (move_bits >> 12) & 3;
-
         let moving_bits = start_square | dest_square;
 
+        if let Some(piece) = mov.dropped() {
+            self.play_drop(piece, dest_sq_num, observer);
+            return;
+        }
+
+        // Castling is encoded as the king capturing its own rook
+        if mov.kind() == MoveKind::Castling {
+            self.play_castling(start_sq_num, dest_sq_num, observer);
+            return;
+        }
+
+        // Castling rights are lost when the rook moves or is captured
+        self.castling_rooks &= !moving_bits;
+
         // If a capture is taking place, zero out the destination square
         if (self.pieces[W_PIECES] | self.pieces[B_PIECES]) & dest_square != 0 {
+            // Color bitboards sort after the pieces they contain, so the first
+            // match is always the captured piece itself.
+            let captured = self.pieces.iter().position(|&x| x & dest_square != 0);
+            observer.remove_piece(captured.unwrap(), dest_sq_num);
+            if self.variant == Variant::Crazyhouse {
+                self.capture_to_hand(captured.unwrap(), dest_square);
+            }
+
             let dest_zero_mask = !dest_square;
             for piece in &mut self.pieces {
                 *piece &= dest_zero_mask;
@@ -278,14 +588,21 @@ impl Position {
         let passant_prev = self.passant_sq;
         self.passant_sq = 0;
 
+        observer.remove_piece(moving_piece, start_sq_num);
+        let mut placed_piece = moving_piece;
+
         match moving_piece {
             W_PAWN | B_PAWN => {
                 if dest_square & passant_prev != 0 {
-                    let dest_zero = if moving_piece == W_PAWN {
-                        !(dest_square >> 8)
+                    let (dest_zero, captured_pawn) = if moving_piece == W_PAWN {
+                        (!(dest_square >> 8), B_PAWN)
                     } else {
-                        !(dest_square << 8)
+                        (!(dest_square << 8), W_PAWN)
                     };
+                    observer.remove_piece(captured_pawn, (!dest_zero).trailing_zeros() as usize);
+                    if self.variant == Variant::Crazyhouse {
+                        self.capture_to_hand(captured_pawn, !dest_zero);
+                    }
                     self.pieces[W_PIECES] &= dest_zero;
                     self.pieces[B_PIECES] &= dest_zero;
                     self.pieces[W_PAWN] &= dest_zero;
@@ -298,49 +615,17 @@ impl Position {
                     self.pieces[moving_piece] |= dest_square;
 
                     // Set the promoted piece
-                    match promotion_piece {
-                        3 => self.pieces[W_QUEEN + self_offset] |= dest_square,
-                        2 => self.pieces[W_ROOK + self_offset] |= dest_square,
-                        1 => self.pieces[W_BISHOP + self_offset] |= dest_square,
-                        0 => self.pieces[W_KNIGHT + self_offset] |= dest_square,
-                        _ => (),
+                    let promoted = mov.promotion().map_or(W_KNIGHT, |x| x.index());
+                    placed_piece = self_offset + promoted;
+                    self.pieces[placed_piece] |= dest_square;
+                    if self.variant == Variant::Crazyhouse {
+                        self.promoted |= dest_square;
                     }
                 }
                 self.hlf_clock = 0;
             }
-            W_KING => {
-                self.w_king_castle = false;
-                self.w_queen_castle = false;
-                if sq_diff == 2 {
-                    // Queenside Castling
-                    self.pieces[W_ROOK] ^= 0x0000000000000009;
-                    self.pieces[W_PIECES] ^= 0x0000000000000009;
-                } else if sq_diff == -2 {
-                    // Kingside Castling
-                    self.pieces[W_ROOK] ^= 0x00000000000000A0;
-                    self.pieces[W_PIECES] ^= 0x00000000000000A0;
-                }
-            }
-            B_KING => {
-                self.b_king_castle = false;
-                self.b_queen_castle = false;
-                if sq_diff == 2 {
-                    // Queenside Castling
-                    self.pieces[B_ROOK] ^= 0x0900000000000000;
-                    self.pieces[B_PIECES] ^= 0x0900000000000000;
-                } else if sq_diff == -2 {
-                    // Kingside Castling
-                    self.pieces[B_ROOK] ^= 0xA000000000000000;
-                    self.pieces[B_PIECES] ^= 0xA000000000000000;
-                }
-            }
-            W_ROOK | B_ROOK if start_square & CORNERS != 0 => match start_sq_num {
-                1 => self.w_queen_castle = false,
-                7 => self.w_king_castle = false,
-                56 => self.b_queen_castle = false,
-                63 => self.b_king_castle = false,
-                _ => (),
-            },
+            W_KING => self.castling_rooks &= !RANK_1,
+            B_KING => self.castling_rooks &= !RANK_8,
             _ => (),
         }
 
@@ -350,42 +635,376 @@ impl Position {
         } else {
             self.pieces[B_PIECES] ^= moving_bits;
         }
+        if self.promoted & start_square != 0 {
+            self.promoted ^= moving_bits;
+        }
+        observer.add_piece(placed_piece, dest_sq_num);
+    }
+
+    // Place a piece from the hand of the side that just moved on an empty
+    // square. Like a pawn move, a pawn drop cannot be undone.
+    fn play_drop<O: PieceObserver>(&mut self, piece: PieceType, sq: usize, observer: &mut O) {
+        let own = self.is_white_move as usize;
+        let placed = own * 7 + piece.index();
+        self.hands[own][piece.index()] -= 1;
+        self.pieces[placed] |= 1u64 << sq;
+        self.pieces[W_PIECES + own * 7] |= 1u64 << sq;
+        observer.add_piece(placed, sq);
+
+        if piece == PieceType::Pawn {
+            self.hlf_clock = 0;
+        }
+        self.passant_sq = 0;
+    }
+
+    // Add a captured piece to the hand of the side capturing it, demoting it
+    // to a pawn if it was promoted
+    fn capture_to_hand(&mut self, captured: usize, square: u64) {
+        let kind = if self.promoted & square != 0 {
+            W_PAWN
+        } else {
+            captured % 7
+        };
+        self.promoted &= !square;
+        if kind < W_KING {
+            self.hands[(captured < 7) as usize][kind] += 1;
+        }
+    }
+
+    // Move the king and rook to their castled squares: the g and f files when
+    // castling king side, the c and d files when castling queen side. Either
+    // may already stand on its destination in Chess960.
+    fn play_castling<O: PieceObserver>(
+        &mut self,
+        king_sq: usize,
+        rook_sq: usize,
+        observer: &mut O,
+    ) {
+        let own = if king_sq < 8 { 0 } else { 7 };
+        let home = king_sq - king_sq % 8;
+        let (king_to, rook_to) = if rook_sq > king_sq {
+            (home + 6, home + 5)
+        } else {
+            (home + 2, home + 3)
+        };
+
+        observer.remove_piece(W_KING + own, king_sq);
+        observer.remove_piece(W_ROOK + own, rook_sq);
+        let from = 1u64 << king_sq | 1u64 << rook_sq;
+        let to = 1u64 << king_to | 1u64 << rook_to;
+        self.pieces[W_KING + own] = 1u64 << king_to;
+        self.pieces[W_ROOK + own] = self.pieces[W_ROOK + own] & !from | 1u64 << rook_to;
+        self.pieces[W_PIECES + own] = self.pieces[W_PIECES + own] & !from | to;
+        observer.add_piece(W_ROOK + own, rook_to);
+        observer.add_piece(W_KING + own, king_to);
+
+        self.castling_rooks &= if own == 0 { !RANK_1 } else { !RANK_8 };
+        self.passant_sq = 0;
     }
 
+    // Evaluate the position from white's point of view. A missing royal king
+    // is treated as a decisive result for the other side.
     pub fn evaluate(self) -> isize {
-        if self.pieces[W_KING] == 0 {
+        let royal = self.variant.has_royal_king();
+        if royal && self.pieces[W_KING] == 0 {
             return isize::MIN;
         }
-        if self.pieces[B_KING] == 0 {
+        if royal && self.pieces[B_KING] == 0 {
             return isize::MAX;
         }
 
-        let mut white_evaluation = 0;
-        let mut black_evaluation = 0;
+        evaluate::evaluate(&self, &mut evaluate::NoTrace) as isize
+    }
+
+    // Generate the legal moves that can be performed from the current
+    // position, in a list large enough for the drops of any variant
+    pub fn moves(self) -> MoveList<MAX_DROP_MOVES> {
+        let mut moves = MoveList::new();
+        self.generate_moves(&mut moves);
+        moves
+    }
+
+    // Replace the contents of 'moves' with the legal moves of the position.
+    // There are none once a rule of the variant has ended the game.
+    pub fn generate_moves<L: MoveStore>(self, moves: &mut L) {
+        self.generate(moves, false);
+    }
+
+    // Replace the contents of 'moves' with the legal captures and promotions
+    // of the position, without paying for the legality check of quiet moves
+    pub fn generate_tactical_moves<L: MoveStore>(self, moves: &mut L) {
+        self.generate(moves, true);
+    }
+
+    fn generate<L: MoveStore>(self, moves: &mut L, tactical_only: bool) {
+        moves.clear();
+        if self.variant_outcome().is_some() {
+            return;
+        }
+        self.generate_knight_moves(moves);
+        self.generate_pawn_moves(moves);
+        self.generate_slider_moves(moves);
+        self.generate_king_moves(moves);
+        if self.variant.has_drops() {
+            self.generate_drop_moves(moves);
+        }
+        if tactical_only {
+            moves.retain(|&x| self.is_tactical(x));
+        }
+
+        if self.variant.has_royal_king() {
+            moves.retain(|&x| self.is_legal(x));
+        }
+        if self.variant.forces_captures() {
+            let enemies = self.pieces[if self.is_white_move {
+                B_PIECES
+            } else {
+                W_PIECES
+            }];
+            let is_capture =
+                |x: &Move| enemies & x.to().bitboard().0 != 0 || x.kind() == MoveKind::EnPassant;
+            if moves.as_slice().iter().any(is_capture) {
+                moves.retain(|x| is_capture(x));
+            }
+        }
+    }
+
+    pub fn variant(self) -> Variant {
+        self.variant
+    }
+
+    // Checks given by the given side, counted in Three-check
+    pub fn checks_given(self, color: Color) -> u8 {
+        self.checks[color.index()]
+    }
+
+    // The number of pieces of the given type in the hand of the given side,
+    // in Crazyhouse
+    pub fn in_hand(self, color: Color, piece: PieceType) -> u8 {
+        self.hands[color.index()]
+            .get(piece.index())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // The result of a game ended by a rule of the variant, such as a king
+    // reaching the center in King of the Hill
+    pub fn variant_outcome(self) -> Option<Outcome> {
+        self.variant.outcome(&self)
+    }
+
+    // The result of the game when the side to move has no legal moves, either
+    // because a variant rule ended it or by checkmate or stalemate
+    pub fn no_moves_outcome(self) -> Outcome {
+        self.variant_outcome()
+            .unwrap_or_else(|| self.variant.no_moves_outcome(self.is_in_check()))
+    }
+
+    // Count the leaf nodes of the legal move tree to the given depth. Used to
+    // verify move generation against known results.
+    pub fn perft(self, depth: u32) -> u64 {
+        if self.variant.has_drops() {
+            self.perft_with::<MAX_DROP_MOVES>(depth)
+        } else {
+            self.perft_with::<MAX_MOVES>(depth)
+        }
+    }
+
+    // Perft with move lists of capacity N, which must fit the variant
+    fn perft_with<const N: usize>(self, depth: u32) -> u64 {
+        let mut moves = MoveList::<N>::new();
+        self.generate_moves(&mut moves);
+        if depth <= 1 {
+            return if depth == 0 { 1 } else { moves.len() as u64 };
+        }
+
+        moves
+            .iter()
+            .map(|&x| {
+                let mut next = self;
+                next.play_move(x);
+                next.perft_with::<N>(depth - 1)
+            })
+            .sum()
+    }
+
+    // Check that a pseudo-legal move does not leave the moving side's king
+    // attacked.
+    fn is_legal(self, mov: Move) -> bool {
+        let mut next = self;
+        next.play_move(mov);
 
-        white_evaluation += self.pieces[W_PAWN].count_ones() * 100;
-        white_evaluation += self.pieces[W_ROOK].count_ones() * 350;
-        white_evaluation += self.pieces[W_KNIGHT].count_ones() * 350;
-        white_evaluation += self.pieces[W_BISHOP].count_ones() * 525;
-        white_evaluation += self.pieces[W_QUEEN].count_ones() * 1000;
+        let king = next.pieces[if self.is_white_move { W_KING } else { B_KING }];
+        king == 0 || !next.attacked(king.trailing_zeros() as usize, next.is_white_move)
+    }
 
-        black_evaluation += self.pieces[B_PAWN].count_ones() * 100;
-        black_evaluation += self.pieces[B_ROOK].count_ones() * 350;
-        black_evaluation += self.pieces[B_KNIGHT].count_ones() * 350;
-        black_evaluation += self.pieces[B_BISHOP].count_ones() * 525;
-        black_evaluation += self.pieces[B_QUEEN].count_ones() * 1000;
+    // Is the side to move in check? Kings that are not royal are never in
+    // check.
+    pub fn is_in_check(self) -> bool {
+        let king = self.pieces[if self.is_white_move { W_KING } else { B_KING }];
+        king != 0
+            && self.variant.has_royal_king()
+            && self.attacked(king.trailing_zeros() as usize, !self.is_white_move)
+    }
 
-        white_evaluation as isize - black_evaluation as isize
+    // Is the square attacked by any piece of the given color?
+    pub fn is_attacked(self, sq: Square, by: Color) -> bool {
+        self.attacked(sq.index(), by.is_white())
     }
 
-    // Generate moves that can be performed from the current position
-    pub fn moves(self) -> Vec<Move> {
-        return self.generate_knight_moves();
+    fn attacked(self, sq: usize, by_white: bool) -> bool {
+        let enemy = (!by_white as usize) * 7;
+        let occupied = self.pieces[W_PIECES] | self.pieces[B_PIECES];
+        let queens = self.pieces[W_QUEEN + enemy];
+
+        attacks::pawn_attacks(!by_white, sq) & self.pieces[W_PAWN + enemy] != 0
+            || attacks::knight_attacks(sq) & self.pieces[W_KNIGHT + enemy] != 0
+            || attacks::king_attacks(sq) & self.pieces[W_KING + enemy] != 0
+            || attacks::bishop_attacks(sq, occupied) & (self.pieces[W_BISHOP + enemy] | queens) != 0
+            || attacks::rook_attacks(sq, occupied) & (self.pieces[W_ROOK + enemy] | queens) != 0
     }
 
-    fn generate_knight_moves(self) -> Vec<Move> {
-        let mut moves: Vec<Move> = Vec::new();
+    fn generate_pawn_moves<L: MoveStore>(self, moves: &mut L) {
+        let own = (!self.is_white_move as usize) * 7;
+        let pawns = self.pieces[W_PAWN + own];
+        let empty = !(self.pieces[W_PIECES] | self.pieces[B_PIECES]);
+        let enemies = self.pieces[B_PIECES - own] | self.passant_sq;
+
+        // Destination squares of each kind of pawn move, paired with the
+        // distance from the origin square to the destination square.
+        let targets: [(u64, isize); 4] = if self.is_white_move {
+            let single = (pawns << 8) & empty;
+            [
+                (single, 8),
+                (((single & RANK_3) << 8) & empty, 16),
+                (((pawns & !A_FILE) << 7) & enemies, 7),
+                (((pawns & !H_FILE) << 9) & enemies, 9),
+            ]
+        } else {
+            let single = (pawns >> 8) & empty;
+            [
+                (single, -8),
+                (((single & RANK_6) >> 8) & empty, -16),
+                (((pawns & !H_FILE) >> 7) & enemies, -7),
+                (((pawns & !A_FILE) >> 9) & enemies, -9),
+            ]
+        };
+
+        for (mut dest_squares, distance) in targets {
+            while dest_squares != 0 {
+                let dest_sq = dest_squares.trailing_zeros() as u16;
+                let start_sq = (dest_sq as isize - distance) as u16;
+                let move_bits = start_sq | dest_sq << DEST_BITS_OFFSET;
 
+                if 1u64 << dest_sq & (RANK_1 | RANK_8) != 0 {
+                    if self.variant.promotes_to_king() {
+                        moves.push(Move::from_bits(move_bits | KING_PROMOTION));
+                    }
+                    for piece in (0..4).rev() {
+                        let promotion = PROMOTION | piece << PROMOTION_PIECE_BITS_OFFSET;
+                        moves.push(Move::from_bits(move_bits | promotion));
+                    }
+                } else if 1u64 << dest_sq == self.passant_sq {
+                    moves.push(Move::from_bits(move_bits | ENPASSANT));
+                } else if distance.abs() == 16 {
+                    moves.push(Move::from_bits(move_bits | PAWN_DOUBLE_FWD));
+                } else {
+                    moves.push(Move::from_bits(move_bits));
+                }
+                dest_squares &= dest_squares - 1;
+            }
+        }
+    }
+
+    fn generate_slider_moves<L: MoveStore>(self, moves: &mut L) {
+        let own = (!self.is_white_move as usize) * 7;
+        let occupied = self.pieces[W_PIECES] | self.pieces[B_PIECES];
+        let friendly_pieces = self.pieces[W_PIECES + own];
+
+        for piece in [W_ROOK, W_BISHOP, W_QUEEN] {
+            let mut pieces = self.pieces[piece + own];
+            while pieces != 0 {
+                let start_sq = pieces.trailing_zeros() as usize;
+                let attacked = match piece {
+                    W_ROOK => attacks::rook_attacks(start_sq, occupied),
+                    W_BISHOP => attacks::bishop_attacks(start_sq, occupied),
+                    _ => attacks::queen_attacks(start_sq, occupied),
+                };
+                push_moves(moves, start_sq, attacked & !friendly_pieces);
+                pieces &= pieces - 1;
+            }
+        }
+    }
+
+    fn generate_king_moves<L: MoveStore>(self, moves: &mut L) {
+        let own = (!self.is_white_move as usize) * 7;
+        let king = self.pieces[W_KING + own];
+        if king == 0 {
+            return;
+        }
+        let king_sq = king.trailing_zeros() as usize;
+        let friendly_pieces = self.pieces[W_PIECES + own];
+
+        push_moves(
+            moves,
+            king_sq,
+            attacks::king_attacks(king_sq) & !friendly_pieces,
+        );
+
+        // Castling requires the king and rook on their original squares, the
+        // squares they cross and land on to be empty but for themselves, and
+        // the king not to pass through an attacked square. Whether the king
+        // lands in check is left to the legality test of all moves.
+        let mut rooks = self.castling_rooks & self.pieces[W_ROOK + own];
+        if rooks == 0 || self.is_in_check() {
+            return;
+        }
+        let occupied = self.pieces[W_PIECES] | self.pieces[B_PIECES];
+        let home = king_sq - king_sq % 8;
+        while rooks != 0 {
+            let rook_sq = rooks.trailing_zeros() as usize;
+            let (king_to, rook_to) = if rook_sq > king_sq {
+                (home + 6, home + 5)
+            } else {
+                (home + 2, home + 3)
+            };
+            let king_path = attacks::between(king_sq, king_to) | 1u64 << king_to;
+            let rook_path = attacks::between(rook_sq, rook_to) | 1u64 << rook_to;
+            let others = occupied & !(1u64 << king_sq | 1u64 << rook_sq);
+
+            if (king_path | rook_path) & others == 0
+                && !Bitboard(attacks::between(king_sq, king_to))
+                    .any(|sq| self.attacked(sq.index(), !self.is_white_move))
+            {
+                moves.push(Move::new(
+                    Square::from_index(king_sq),
+                    Square::from_index(rook_sq),
+                    MoveKind::Castling,
+                ));
+            }
+            rooks &= rooks - 1;
+        }
+    }
+
+    // Drops of each piece in hand to the empty squares, except for pawns on
+    // the first and last ranks
+    fn generate_drop_moves<L: MoveStore>(self, moves: &mut L) {
+        let hand = self.hands[!self.is_white_move as usize];
+        let empty = !(self.pieces[W_PIECES] | self.pieces[B_PIECES]);
+        for piece in PieceType::ALL[..5].iter().filter(|x| hand[x.index()] != 0) {
+            let mut squares = match piece {
+                PieceType::Pawn => empty & !(RANK_1 | RANK_8),
+                _ => empty,
+            };
+            while squares != 0 {
+                let sq = Square::from_index(squares.trailing_zeros() as usize);
+                moves.push(Move::new(sq, sq, MoveKind::Drop(*piece)));
+                squares &= squares - 1;
+            }
+        }
+    }
+
+    fn generate_knight_moves<L: MoveStore>(self, moves: &mut L) {
         let mut knights;
         let friendly_pieces;
         if self.is_white_move {
@@ -396,167 +1015,714 @@ impl Position {
             friendly_pieces = self.pieces[B_PIECES];
         };
 
-        // Add knight moves
+        // A pinned knight can never move without exposing its king
+        if self.variant.has_royal_king() {
+            knights &= !self.pinned_pieces();
+        }
+
         while knights != 0 {
-            let index = knights.trailing_zeros();
-            moves.extend(KNIGHT_MOVES[index as usize].iter());
-            knights ^= 1 << index;
+            let start_sq = knights.trailing_zeros() as usize;
+            let dest_squares = attacks::knight_attacks(start_sq) & !friendly_pieces;
+            push_moves(moves, start_sq, dest_squares);
+            knights &= knights - 1;
         }
+    }
 
-        moves = moves
-            .into_iter()
-            .filter(|&x| {
-                let dest_sq_index = (x & DEST_SQ_BITS) >> DEST_BITS_OFFSET;
-                let dest_sq = 1u64 << dest_sq_index;
-                dest_sq & friendly_pieces == 0
-            })
-            .collect();
+    // Friendly pieces of the side to move which are pinned to their own king
+    // by an enemy rook, bishop or queen.
+    fn pinned_pieces(self) -> u64 {
+        let own = (!self.is_white_move as usize) * 7;
+        let enemy = (self.is_white_move as usize) * 7;
+
+        let king = self.pieces[W_KING + own];
+        if king == 0 {
+            return 0;
+        }
+        let king_sq = king.trailing_zeros() as usize;
+        let occupied = self.pieces[W_PIECES] | self.pieces[B_PIECES];
+        let queens = self.pieces[W_QUEEN + enemy];
+        let own_pieces = self.pieces[W_PIECES + own];
+
+        let rook_pinned = pinned_by_sliders(
+            attacks::rook_attacks,
+            king_sq,
+            occupied,
+            own_pieces,
+            self.pieces[W_ROOK + enemy] | queens,
+        );
+        let bishop_pinned = pinned_by_sliders(
+            attacks::bishop_attacks,
+            king_sq,
+            occupied,
+            own_pieces,
+            self.pieces[W_BISHOP + enemy] | queens,
+        );
+        rook_pinned | bishop_pinned
+    }
+
+    // Does the move put the opponent in check?
+    pub fn gives_check(self, mov: Move) -> bool {
+        let mut next = self;
+        next.play_move(mov);
+        next.is_in_check()
+    }
+
+    // Does the move take a pawn to its seventh rank? Such a pawn is always
+    // passed, as there is no room for enemy pawns in front of it.
+    pub fn is_push_to_seventh(self, mov: Move) -> bool {
+        let start_sq = mov.from().index();
+        let dest_sq = mov.to().index();
+        let (pawns, seventh_rank) = if self.is_white_move {
+            (self.pieces[W_PAWN], 6)
+        } else {
+            (self.pieces[B_PAWN], 1)
+        };
+        pawns & (1u64 << start_sq) != 0 && dest_sq / 8 == seventh_rank
+    }
+
+    // Is the move a capture (including en passant) or a promotion?
+    pub fn is_tactical(self, mov: Move) -> bool {
+        let dest_sq = mov.to().bitboard().0;
+        let enemies = self.pieces[if self.is_white_move {
+            B_PIECES
+        } else {
+            W_PIECES
+        }];
+        let kind = mov.kind();
+        dest_sq & enemies != 0 || kind == MoveKind::EnPassant || mov.promotion().is_some()
+    }
+
+    // Pieces of both colors attacking a square, given the occupied squares
+    fn attackers_to(self, sq: usize, occupied: u64) -> u64 {
+        let p = &self.pieces;
+        let bishops = p[W_BISHOP] | p[B_BISHOP] | p[W_QUEEN] | p[B_QUEEN];
+        let rooks = p[W_ROOK] | p[B_ROOK] | p[W_QUEEN] | p[B_QUEEN];
+
+        (attacks::pawn_attacks(false, sq) & p[W_PAWN])
+            | (attacks::pawn_attacks(true, sq) & p[B_PAWN])
+            | (attacks::knight_attacks(sq) & (p[W_KNIGHT] | p[B_KNIGHT]))
+            | (attacks::king_attacks(sq) & (p[W_KING] | p[B_KING]))
+            | (attacks::bishop_attacks(sq, occupied) & bishops)
+            | (attacks::rook_attacks(sq, occupied) & rooks)
+    }
+
+    // Static exchange evaluation: the material won or lost by the side to move
+    // if both sides keep recapturing on the destination square of the move,
+    // always with their least valuable piece, and stop when it no longer pays.
+    // Sliders lined up behind a capturing piece join in as it leaves (x-rays).
+    // Pins are not taken into account.
+    pub fn see(self, mov: Move) -> i32 {
+        let from = mov.from().index();
+        let to = mov.to().index();
+        if let MoveKind::Castling | MoveKind::Drop(_) = mov.kind() {
+            return 0;
+        }
+
+        let mut occupied = (self.pieces[W_PIECES] | self.pieces[B_PIECES]) ^ (1u64 << from);
+        let mut gain = [0; 32];
+        gain[0] = self.piece_index(to).map_or(0, |x| SEE_VALUES[x % 7]);
+        let mut on_square = SEE_VALUES[self.piece_index(from).unwrap() % 7];
+
+        match mov.kind() {
+            MoveKind::EnPassant => {
+                let victim = if self.is_white_move { to - 8 } else { to + 8 };
+                occupied ^= 1u64 << victim;
+                gain[0] = SEE_VALUES[W_PAWN];
+            }
+            MoveKind::Promotion(piece) => {
+                gain[0] += SEE_VALUES[piece.index()] - SEE_VALUES[W_PAWN];
+                on_square = SEE_VALUES[piece.index()];
+            }
+            _ => (),
+        }
+
+        let bishops = self.pieces[W_BISHOP] | self.pieces[B_BISHOP];
+        let rooks = self.pieces[W_ROOK] | self.pieces[B_ROOK];
+        let queens = self.pieces[W_QUEEN] | self.pieces[B_QUEEN];
+        let mut attackers = self.attackers_to(to, occupied) & occupied;
+        let mut is_white = !self.is_white_move;
+        let mut depth = 0;
+
+        loop {
+            let offset = (!is_white as usize) * 7;
+            let own = attackers & self.pieces[W_PIECES + offset];
+            let kind = match SEE_ORDER
+                .iter()
+                .find(|&&x| own & self.pieces[x + offset] != 0)
+            {
+                Some(&kind) => kind,
+                None => break,
+            };
+            // The king cannot capture onto a square that is still defended
+            if kind == W_KING && attackers & !own != 0 {
+                break;
+            }
+
+            // The score if this is the last capture of the exchange
+            depth += 1;
+            gain[depth] = on_square - gain[depth - 1];
+
+            let attacker = own & self.pieces[kind + offset];
+            occupied ^= attacker & attacker.wrapping_neg();
+            if let W_PAWN | W_BISHOP | W_QUEEN = kind {
+                attackers |= attacks::bishop_attacks(to, occupied) & (bishops | queens);
+            }
+            if let W_ROOK | W_QUEEN = kind {
+                attackers |= attacks::rook_attacks(to, occupied) & (rooks | queens);
+            }
+            attackers &= occupied;
+            on_square = SEE_VALUES[kind];
+            is_white = !is_white;
+        }
+
+        // Each side may stop capturing when continuing would lose material
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    // Does the move win at least 'threshold' material by static exchange?
+    pub fn see_ge(self, mov: Move, threshold: i32) -> bool {
+        self.see(mov) >= threshold
+    }
+
+    pub fn side_to_move(self) -> Color {
+        Color::from_is_white(self.is_white_move)
+    }
+
+    // The squares holding the given piece
+    pub fn bitboard(self, piece: Piece) -> Bitboard {
+        Bitboard(self.pieces[piece.index()])
+    }
+
+    // The squares holding pieces of the given color
+    pub fn occupied(self, color: Color) -> Bitboard {
+        Bitboard(self.pieces[W_PIECES + 7 * color.index()])
+    }
+
+    // The rook that can still castle on the given side of its king, if any
+    pub fn castling_rook(self, color: Color, king_side: bool) -> Option<Square> {
+        let rank = RANK_1 << (56 * color.index());
+        let king = self.pieces[W_KING + 7 * color.index()] & rank;
+        if king == 0 {
+            return None;
+        }
+        let rooks = self.castling_rooks & rank;
+        let side = if king_side {
+            rooks & !(king | (king - 1))
+        } else {
+            rooks & (king - 1)
+        };
+        Bitboard(side).first()
+    }
+
+    // The castling rooks of white king side, white queen side, black king side
+    // and black queen side, in the order of FEN
+    pub(crate) fn castling_rights(self) -> [Option<Square>; 4] {
+        [
+            self.castling_rook(Color::White, true),
+            self.castling_rook(Color::White, false),
+            self.castling_rook(Color::Black, true),
+            self.castling_rook(Color::Black, false),
+        ]
+    }
+
+    // The piece on a square, if any
+    pub fn piece_on(self, sq: Square) -> Option<Piece> {
+        self.piece_index(sq.index()).and_then(Piece::from_index)
+    }
+
+    // The index in the 'pieces' array of the piece on a square, if any
+    pub(crate) fn piece_index(self, sq: usize) -> Option<usize> {
+        (W_PAWN..B_PIECES)
+            .filter(|&x| x != W_PIECES)
+            .find(|&x| self.pieces[x] & (1u64 << sq) != 0)
+    }
+
+    // Neither side has enough material left to deliver checkmate, in a
+    // variant where that is a draw
+    pub fn is_insufficient_material(self) -> bool {
+        if !self.variant.draws_by_insufficient_material() {
+            return false;
+        }
+        let heavy = [W_PAWN, W_ROOK, W_QUEEN, B_PAWN, B_ROOK, B_QUEEN];
+        if heavy.iter().any(|&x| self.pieces[x] != 0) {
+            return false;
+        }
+        let minors = self.pieces[W_KNIGHT]
+            | self.pieces[W_BISHOP]
+            | self.pieces[B_KNIGHT]
+            | self.pieces[B_BISHOP];
+        minors.count_ones() <= 1
+    }
+
+    // Does the side to move have any pieces other than pawns and its king?
+    // Without them, passing is often the best move and null moves are unsafe.
+    pub fn has_non_pawn_material(self) -> bool {
+        let offset = (!self.is_white_move as usize) * 7;
+        let pawns_and_king = self.pieces[W_PAWN + offset] | self.pieces[W_KING + offset];
+        self.pieces[W_PIECES + offset] & !pawns_and_king != 0
+    }
+
+    // Pass the turn to the opponent without moving a piece
+    pub fn play_null_move(&mut self) {
+        self.hlf_clock += 1;
+        self.full_num += !self.is_white_move as u16;
+        self.is_white_move = !self.is_white_move;
+        self.passant_sq = 0;
+    }
+
+    // Zobrist hash of the position, used to detect repetitions
+    pub fn hash(self) -> u64 {
+        let mut hash = 0;
+        for (piece, &bitboard) in self.pieces.iter().enumerate() {
+            if piece == W_PIECES || piece == B_PIECES {
+                continue;
+            }
+            let mut bitboard = bitboard;
+            while bitboard != 0 {
+                hash ^= ZOBRIST.pieces[piece][bitboard.trailing_zeros() as usize];
+                bitboard &= bitboard - 1;
+            }
+        }
+
+        for (i, _) in self
+            .castling_rights()
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.is_some())
+        {
+            hash ^= ZOBRIST.castling[i];
+        }
+        if self.passant_sq != 0 {
+            hash ^= ZOBRIST.passant[self.passant_sq.trailing_zeros() as usize % 8];
+        }
+        if !self.is_white_move {
+            hash ^= ZOBRIST.black_move;
+        }
+        for (side, &count) in self.checks.iter().enumerate().filter(|x| *x.1 != 0) {
+            hash ^= ZOBRIST.checks[side][count.min(CHECKS_TO_WIN) as usize];
+        }
+        for (side, hand) in self.hands.iter().enumerate() {
+            for (piece, &count) in hand.iter().enumerate().filter(|x| *x.1 != 0) {
+                hash ^= ZOBRIST.hands[side][piece][(count as usize).min(MAX_IN_HAND)];
+            }
+        }
+        let mut promoted = self.promoted;
+        while promoted != 0 {
+            hash ^= ZOBRIST.promoted[promoted.trailing_zeros() as usize];
+            promoted &= promoted - 1;
+        }
+        hash
+    }
+
+    // Forsyth-Edwards Notation of the position. Castling rights are written
+    // as in X-FEN: 'K' and 'Q' for the outermost rook on each side of the
+    // king, which covers every standard chess position, and the rook's file
+    // for an inner rook in Chess960.
+    pub fn to_fen(self) -> String {
+        self.fen(false)
+    }
+
+    // FEN with castling rights written as the files of the castling rooks, as
+    // in Shredder-FEN (e.g. 'HAha' for the standard start position)
+    pub fn to_shredder_fen(self) -> String {
+        self.fen(true)
+    }
+
+    fn fen(self, shredder: bool) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_on(Square::from_index(rank * 8 + file)) {
+                    Some(piece) => {
+                        if empty != 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.to_char());
+                        if self.promoted & 1u64 << (rank * 8 + file) != 0 {
+                            fen.push('~');
+                        }
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty != 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank != 0 {
+                fen.push('/');
+            }
+        }
+
+        if self.variant == Variant::Crazyhouse {
+            fen.push('[');
+            for (side, hand) in self.hands.iter().enumerate() {
+                for piece in [W_QUEEN, W_ROOK, W_BISHOP, W_KNIGHT, W_PAWN] {
+                    let letter = Piece::from_index(piece + side * 7).unwrap().to_char();
+                    (0..hand[piece]).for_each(|_| fen.push(letter));
+                }
+            }
+            fen.push(']');
+        }
+
+        fen.push_str(if self.is_white_move { " w " } else { " b " });
+        let mut castling = String::new();
+        for (i, rook) in self.castling_rights().iter().enumerate() {
+            if let Some(rook) = rook {
+                let own = (i / 2) * 7;
+                let king_side = i % 2 == 0;
+                let rooks = self.pieces[W_ROOK + own] & RANK_1 << (rook.index() / 8 * 8);
+                let below = rook.bitboard().0 - 1;
+                let outside = if king_side { !below << 1 } else { below };
+                let letter = if shredder || rooks & outside != 0 {
+                    rook.file().to_char().to_ascii_uppercase()
+                } else if king_side {
+                    'K'
+                } else {
+                    'Q'
+                };
+                castling.push(if own == 0 {
+                    letter
+                } else {
+                    letter.to_ascii_lowercase()
+                });
+            }
+        }
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
 
-        return moves;
+        if self.passant_sq == 0 {
+            fen.push_str(" -");
+        } else {
+            let sq = Square::from_index(self.passant_sq.trailing_zeros() as usize);
+            fen.push_str(&format!(" {}", sq));
+        }
+        if self.variant == Variant::ThreeCheck {
+            let remaining = self.checks.map(|x| CHECKS_TO_WIN.saturating_sub(x));
+            fen.push_str(&format!(" {}+{}", remaining[0], remaining[1]));
+        }
+        format!("{} {} {}", fen, self.hlf_clock, self.full_num)
     }
+
+    // Standard Algebraic Notation of a legal move, such as 'Nbd7', 'exd6',
+    // 'e8=Q+' or 'O-O'
+    pub fn move_to_san(self, mov: Move) -> String {
+        let from = mov.from().index();
+        let to = mov.to().index();
+        let square = |sq: usize| Square::from_index(sq).to_string();
+
+        let mut san = if mov.kind() == MoveKind::Castling {
+            String::from(if to > from { "O-O" } else { "O-O-O" })
+        } else if let Some(piece) = mov.dropped() {
+            format!("{}@{}", piece.to_char(), square(to))
+        } else {
+            let piece = self.piece_index(from);
+            let kind = piece.map_or(W_PAWN, |x| x % B_PAWN);
+            let is_capture = self.piece_index(to).is_some() || mov.kind() == MoveKind::EnPassant;
+            let mut san = String::new();
+            if kind == W_PAWN {
+                if is_capture {
+                    san.push_str(&square(from)[..1]);
+                }
+            } else {
+                san.push(SAN_PIECES[kind]);
+                // Name the origin file, rank or both when another piece of the
+                // same kind can move to the same square
+                let others: Vec<usize> = self
+                    .moves()
+                    .into_iter()
+                    .filter(|x| x.to().index() == to && x.dropped().is_none())
+                    .map(|x| x.from().index())
+                    .filter(|&x| x != from && self.piece_index(x) == piece)
+                    .collect();
+                if others.iter().any(|x| x % 8 == from % 8) {
+                    if others.iter().any(|x| x / 8 == from / 8) {
+                        san.push_str(&square(from));
+                    } else {
+                        san.push_str(&square(from)[1..]);
+                    }
+                } else if !others.is_empty() {
+                    san.push_str(&square(from)[..1]);
+                }
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&square(to));
+            if let Some(piece) = mov.promotion() {
+                san.push('=');
+                san.push(piece.to_char());
+            }
+            san
+        };
+
+        let mut after = self;
+        after.play_move(mov);
+        if after.is_in_check() {
+            san.push(if after.moves().is_empty() { '#' } else { '+' });
+        }
+        san
+    }
+
+    // Find the legal move written in Standard Algebraic Notation. Check and
+    // annotation suffixes are optional, castling may be written with zeros
+    // and a pawn drop without its letter.
+    pub fn parse_san(self, san: &str) -> Result<Move, String> {
+        let invalid = || format!("Invalid move: {}", san);
+        let text = san.trim_end_matches(|x| "+#!?".contains(x));
+        let moves = self.moves();
+
+        let castle = match text {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        let found: Vec<Move> = if let Some((piece, square)) = text.split_once('@') {
+            let piece = match piece {
+                "" => Some(PieceType::Pawn),
+                _ => PieceType::ALL[..5]
+                    .iter()
+                    .find(|x| x.to_char().to_string() == piece)
+                    .copied(),
+            };
+            let to = square.parse::<Square>().map_err(|_| invalid())?;
+            moves
+                .into_iter()
+                .filter(|&x| piece.is_some() && x.dropped() == piece && x.to() == to)
+                .collect()
+        } else if let Some(king_side) = castle {
+            moves
+                .into_iter()
+                .filter(|&x| {
+                    x.kind() == MoveKind::Castling
+                        && (x.to().index() > x.from().index()) == king_side
+                })
+                .collect()
+        } else {
+            // Split off the promotion piece, with or without '='
+            let (text, promotion) = match text.char_indices().last().ok_or_else(invalid)? {
+                (i, x) if "NBRQK".contains(x) && i >= 2 => {
+                    let piece = PieceType::ALL.iter().find(|p| p.to_char() == x);
+                    (text[..i].trim_end_matches('='), piece.copied())
+                }
+                _ => (text, None),
+            };
+            let (kind, rest) = match SAN_PIECES.iter().position(|&x| text.starts_with(x)) {
+                Some(kind) if kind != W_PAWN => (kind, &text[1..]),
+                _ => (W_PAWN, text),
+            };
+            let rest = rest.replace('x', "");
+            if rest.len() < 2 || !rest.is_ascii() {
+                return Err(invalid());
+            }
+            let (qualifier, square) = rest.split_at(rest.len() - 2);
+            let mut square = square.chars();
+            let (file, rank) = (square.next().unwrap(), square.next().unwrap());
+            if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+                return Err(invalid());
+            }
+            let to = sq_num(file, rank) as usize;
+
+            moves
+                .into_iter()
+                .filter(|&x| {
+                    let from = x.from().index();
+                    x.to().index() == to
+                        && x.dropped().is_none()
+                        && self.piece_index(from).map(|p| p % B_PAWN) == Some(kind)
+                        && promotion == x.promotion()
+                        && qualifier.bytes().all(|q| match q {
+                            b'a'..=b'h' => from % 8 == (q - b'a') as usize,
+                            b'1'..=b'8' => from / 8 == (q - b'1') as usize,
+                            _ => false,
+                        })
+                })
+                .collect()
+        };
+
+        match found[..] {
+            [mov] => Ok(mov),
+            [] => Err(format!("Illegal move: {}", san)),
+            _ => Err(format!("Ambiguous move: {}", san)),
+        }
+    }
+}
+
+struct Zobrist {
+    pieces: [[u64; 64]; 13],
+    castling: [u64; 4],
+    passant: [u64; 8],
+    black_move: u64,
+    checks: [[u64; CHECKS_TO_WIN as usize + 1]; 2],
+    hands: [[[u64; MAX_IN_HAND + 1]; 5]; 2],
+    promoted: [u64; 64],
 }
 
+// The most pieces of one type a side can hold, as all 16 pawns
+const MAX_IN_HAND: usize = 16;
+
 lazy_static! {
-    static ref KNIGHT_MOVES: Vec<Vec<Move>> = vec![
-        vec![640, 1088,],
-        vec![705, 1025, 1153,],
-        vec![514, 770, 1090, 1218,],
-        vec![579, 835, 1155, 1283,],
-        vec![644, 900, 1220, 1348,],
-        vec![709, 965, 1285, 1413,],
-        vec![774, 1350, 1478,],
-        vec![839, 1415,],
-        vec![136, 1160, 1608,],
-        vec![201, 1225, 1545, 1673,],
-        vec![10, 266, 1034, 1290, 1610, 1738,],
-        vec![75, 331, 1099, 1355, 1675, 1803,],
-        vec![140, 396, 1164, 1420, 1740, 1868,],
-        vec![205, 461, 1229, 1485, 1805, 1933,],
-        vec![270, 1294, 1870, 1998,],
-        vec![335, 1359, 1935,],
-        vec![80, 656, 1680, 2128,],
-        vec![17, 145, 721, 1745, 2065, 2193,],
-        vec![82, 210, 530, 786, 1554, 1810, 2130, 2258,],
-        vec![147, 275, 595, 851, 1619, 1875, 2195, 2323,],
-        vec![212, 340, 660, 916, 1684, 1940, 2260, 2388,],
-        vec![277, 405, 725, 981, 1749, 2005, 2325, 2453,],
-        vec![342, 470, 790, 1814, 2390, 2518,],
-        vec![407, 855, 1879, 2455,],
-        vec![600, 1176, 2200, 2648,],
-        vec![537, 665, 1241, 2265, 2585, 2713,],
-        vec![602, 730, 1050, 1306, 2074, 2330, 2650, 2778,],
-        vec![667, 795, 1115, 1371, 2139, 2395, 2715, 2843,],
-        vec![732, 860, 1180, 1436, 2204, 2460, 2780, 2908,],
-        vec![797, 925, 1245, 1501, 2269, 2525, 2845, 2973,],
-        vec![862, 990, 1310, 2334, 2910, 3038,],
-        vec![927, 1375, 2399, 2911,],
-        vec![1120, 1696, 2720, 3168,],
-        vec![1057, 1185, 1761, 2785, 3105, 3233,],
-        vec![1122, 1250, 1570, 1826, 2594, 2850, 3170, 3298,],
-        vec![1187, 1315, 1635, 1891, 2659, 2915, 3235, 3363,],
-        vec![1252, 1380, 1700, 1956, 2724, 2980, 3300, 3428,],
-        vec![1317, 1445, 1765, 2021, 2789, 3045, 3365, 3493,],
-        vec![1382, 1510, 1830, 2854, 3430, 3558,],
-        vec![1447, 1895, 2919, 3495,],
-        vec![1640, 2216, 3240, 3688,],
-        vec![1577, 1705, 2281, 3305, 3625, 3753,],
-        vec![1642, 1770, 2090, 2346, 3114, 3370, 3690, 3818,],
-        vec![1707, 1835, 2155, 2411, 3179, 3435, 3755, 3883,],
-        vec![1772, 1900, 2220, 2476, 3244, 3500, 3820, 3948,],
-        vec![1837, 1965, 2285, 2541, 3309, 3565, 3885, 4013,],
-        vec![1902, 2030, 2350, 3374, 3950, 4078,],
-        vec![1967, 2415, 3439, 4015,],
-        vec![2160, 2736, 3760,],
-        vec![2097, 2225, 2801, 3825,],
-        vec![2162, 2290, 2610, 2866, 3634, 3890,],
-        vec![2227, 2355, 2675, 2931, 3699, 3955,],
-        vec![2292, 2420, 2740, 2996, 3764, 4020,],
-        vec![2357, 2485, 2805, 3061, 3829, 4085,],
-        vec![2422, 2550, 2870, 3894,],
-        vec![2487, 2935, 3959,],
-        vec![2680, 3256,],
-        vec![2617, 2745, 3321,],
-        vec![2682, 2810, 3130, 3386,],
-        vec![2747, 2875, 3195, 3451,],
-        vec![2812, 2940, 3260, 3516,],
-        vec![2877, 3005, 3325, 3581,],
-        vec![2942, 3070, 3390,],
-        vec![2943, 3455,]
-    ];
-    static ref KING_MOVES: Vec<Vec<Move>> = vec![
-        vec![64, 512, 576,],
-        vec![1, 129, 513, 577, 641,],
-        vec![66, 194, 578, 642, 706,],
-        vec![131, 259, 643, 707, 771,],
-        vec![196, 324, 708, 772, 836,],
-        vec![261, 389, 773, 837, 901,],
-        vec![326, 454, 838, 902, 966,],
-        vec![327, 903, 967,],
-        vec![8, 72, 584, 1032, 1096,],
-        vec![9, 73, 137, 521, 649, 1033, 1097, 1161,],
-        vec![74, 138, 202, 586, 714, 1098, 1162, 1226,],
-        vec![139, 203, 267, 651, 779, 1163, 1227, 1291,],
-        vec![204, 268, 332, 716, 844, 1228, 1292, 1356,],
-        vec![269, 333, 397, 781, 909, 1293, 1357, 1421,],
-        vec![334, 398, 462, 846, 974, 1358, 1422, 1486,],
-        vec![335, 463, 911, 1423, 1487,],
-        vec![528, 592, 1104, 1552, 1616,],
-        vec![529, 593, 657, 1041, 1169, 1553, 1617, 1681,],
-        vec![594, 658, 722, 1106, 1234, 1618, 1682, 1746,],
-        vec![659, 723, 787, 1171, 1299, 1683, 1747, 1811,],
-        vec![724, 788, 852, 1236, 1364, 1748, 1812, 1876,],
-        vec![789, 853, 917, 1301, 1429, 1813, 1877, 1941,],
-        vec![854, 918, 982, 1366, 1494, 1878, 1942, 2006,],
-        vec![919, 983, 1431, 1943, 2007,],
-        vec![1048, 1112, 1624, 2072, 2136,],
-        vec![1049, 1113, 1177, 1561, 1689, 2073, 2137, 2201,],
-        vec![1114, 1178, 1242, 1626, 1754, 2138, 2202, 2266,],
-        vec![1179, 1243, 1307, 1691, 1819, 2203, 2267, 2331,],
-        vec![1244, 1308, 1372, 1756, 1884, 2268, 2332, 2396,],
-        vec![1309, 1373, 1437, 1821, 1949, 2333, 2397, 2461,],
-        vec![1374, 1438, 1502, 1886, 2014, 2398, 2462, 2526,],
-        vec![1439, 1503, 1951, 2463, 2527,],
-        vec![1568, 1632, 2144, 2592, 2656,],
-        vec![1569, 1633, 1697, 2081, 2209, 2593, 2657, 2721,],
-        vec![1634, 1698, 1762, 2146, 2274, 2658, 2722, 2786,],
-        vec![1699, 1763, 1827, 2211, 2339, 2723, 2787, 2851,],
-        vec![1764, 1828, 1892, 2276, 2404, 2788, 2852, 2916,],
-        vec![1829, 1893, 1957, 2341, 2469, 2853, 2917, 2981,],
-        vec![1894, 1958, 2022, 2406, 2534, 2918, 2982, 3046,],
-        vec![1959, 2023, 2471, 2983, 3047,],
-        vec![2088, 2152, 2664, 3112, 3176,],
-        vec![2089, 2153, 2217, 2601, 2729, 3113, 3177, 3241,],
-        vec![2154, 2218, 2282, 2666, 2794, 3178, 3242, 3306,],
-        vec![2219, 2283, 2347, 2731, 2859, 3243, 3307, 3371,],
-        vec![2284, 2348, 2412, 2796, 2924, 3308, 3372, 3436,],
-        vec![2349, 2413, 2477, 2861, 2989, 3373, 3437, 3501,],
-        vec![2414, 2478, 2542, 2926, 3054, 3438, 3502, 3566,],
-        vec![2479, 2543, 2927, 3503, 3567,],
-        vec![2608, 2672, 3184, 3632, 3696,],
-        vec![2609, 2673, 2737, 3121, 3249, 3633, 3697, 3761,],
-        vec![2674, 2738, 2802, 3186, 3314, 3698, 3762, 3826,],
-        vec![2739, 2803, 2867, 3251, 3379, 3763, 3827, 3891,],
-        vec![2804, 2868, 2932, 3316, 3444, 3828, 3892, 3956,],
-        vec![2869, 2933, 2997, 3381, 3509, 3893, 3957, 4021,],
-        vec![2934, 2998, 3062, 3446, 3574, 3958, 4022, 4086,],
-        vec![2999, 3063, 3511, 4023, 4087,],
-        vec![3128, 3192, 3704,],
-        vec![3129, 3193, 3257, 3641, 3769,],
-        vec![3194, 3258, 3322, 3706, 3834,],
-        vec![3259, 3323, 3387, 3771, 3899,],
-        vec![3324, 3388, 3452, 3836, 3964,],
-        vec![3389, 3453, 3517, 3901, 4029,],
-        vec![3454, 3518, 3582, 3966, 4094,],
-        vec![3519, 3583, 4031,],
-    ];
+    static ref ZOBRIST: Zobrist = {
+        // xorshift64 with a fixed seed, so hashes are stable between runs
+        let mut state: u64 = 0x2545F4914F6CDD1D;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut zobrist = Zobrist {
+            pieces: [[0; 64]; 13],
+            castling: [0; 4],
+            passant: [0; 8],
+            black_move: 0,
+            checks: [[0; CHECKS_TO_WIN as usize + 1]; 2],
+            hands: [[[0; MAX_IN_HAND + 1]; 5]; 2],
+            promoted: [0; 64],
+        };
+        zobrist.pieces.iter_mut().flatten().for_each(|x| *x = next());
+        zobrist.castling.iter_mut().for_each(|x| *x = next());
+        zobrist.passant.iter_mut().for_each(|x| *x = next());
+        zobrist.black_move = next();
+        zobrist.checks.iter_mut().flatten().for_each(|x| *x = next());
+        zobrist.hands.iter_mut().flatten().flatten().for_each(|x| *x = next());
+        zobrist.promoted.iter_mut().for_each(|x| *x = next());
+        zobrist
+    };
 }
 
-pub fn sq_num(file: char, rank: char) -> u32 {
-    (file as u32 - 'a' as u32) + ((rank as u32 - '1' as u32) * 8)
+// A PieceObserver is told about every piece placed on or taken off the board
+// while a move is played. Pieces are indexed like the 'pieces' array.
+pub trait PieceObserver {
+    fn add_piece(&mut self, _piece: usize, _sq: usize) {}
+    fn remove_piece(&mut self, _piece: usize, _sq: usize) {}
 }
 
-pub fn sq_to_bitboard(file: char, rank: char) -> u64 {
-    1u64 << sq_num(file, rank)
+// NoObserver ignores all piece changes. It is used by play_move().
+pub struct NoObserver;
+
+impl PieceObserver for NoObserver {}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new()
+    }
+}
+
+// Find the pieces in 'own_pieces' that are the only blocker between the king
+// and an enemy slider moving along the lines given by 'slider_attacks'.
+fn pinned_by_sliders(
+    slider_attacks: fn(usize, u64) -> u64,
+    king_sq: usize,
+    occupied: u64,
+    own_pieces: u64,
+    enemy_sliders: u64,
+) -> u64 {
+    let king_rays = slider_attacks(king_sq, occupied);
+    let blockers = king_rays & own_pieces;
+
+    // Enemy sliders that would attack the king if the blockers were removed
+    let mut pinners = slider_attacks(king_sq, occupied ^ blockers) & enemy_sliders;
+    let mut pinned = 0;
+    while pinners != 0 {
+        let pinner_sq = pinners.trailing_zeros() as usize;
+        let between = attacks::between(king_sq, pinner_sq);
+        // With two or more blockers in the way, none of them is pinned
+        if (between & occupied).count_ones() == 1 {
+            pinned |= between & blockers;
+        }
+        pinners &= pinners - 1;
+    }
+    pinned
+}
+
+// The castling rooks named by the castling field of a FEN string. 'K' and 'Q'
+// stand for the outermost rook on that side of the king, and a file letter
+// (upper case for white) for the rook on that file. Rights without a king and
+// rook on their first rank are ignored.
+fn castling_rooks(pieces: &[u64; 14], field: &str) -> u64 {
+    let mut castling_rooks = 0;
+    for c in field.chars().filter(|x| x.is_ascii_alphabetic()) {
+        let (own, home) = if c.is_ascii_uppercase() {
+            (0, 0)
+        } else {
+            (7, 56)
+        };
+        let rooks = pieces[W_ROOK + own] & RANK_1 << home;
+        let king = pieces[W_KING + own] & RANK_1 << home;
+        if king == 0 {
+            continue;
+        }
+        castling_rooks |= match c.to_ascii_lowercase() {
+            'k' => {
+                let side = rooks & !(king | (king - 1));
+                match side {
+                    0 => 0,
+                    _ => 1u64 << (63 - side.leading_zeros()),
+                }
+            }
+            'q' => {
+                let side = rooks & (king - 1);
+                side & side.wrapping_neg()
+            }
+            file @ 'a'..='h' => rooks & 1u64 << (home + (file as usize - 'a' as usize)),
+            _ => 0,
+        };
+    }
+    castling_rooks
+}
+
+// The two counts of a Three-check FEN field, such as '3+3' or '+1+0'
+fn parse_checks(field: &str) -> Option<[u8; 2]> {
+    let mut counts = field.split('+').filter(|x| !x.is_empty()).map(str::parse);
+    match (counts.next(), counts.next(), counts.next()) {
+        (Some(Ok(white)), Some(Ok(black)), None) => Some([white, black]),
+        _ => None,
+    }
+}
+
+// Does the placement field of a FEN string hold eight ranks of eight squares?
+// A '~' may follow a piece to mark it as promoted.
+fn is_valid_board(board: &str) -> bool {
+    let ranks: Vec<&str> = board.split('/').collect();
+    ranks.len() == 8
+        && ranks.iter().all(|rank| {
+            let mut squares = 0;
+            let mut after_piece = false;
+            for c in rank.chars() {
+                match c {
+                    '1'..='8' => squares += c as u32 - '0' as u32,
+                    'P' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'p' | 'n' | 'b' | 'r' | 'q' | 'k' => {
+                        squares += 1
+                    }
+                    '~' if after_piece => (),
+                    _ => return false,
+                }
+                after_piece = c.is_ascii_alphabetic();
+            }
+            squares == 8
+        })
+}
+
+// Add a normal move from one square to each of the destination squares
+fn push_moves<L: MoveStore>(moves: &mut L, start_sq: usize, mut dest_squares: u64) {
+    while dest_squares != 0 {
+        let dest_sq = dest_squares.trailing_zeros() as u16;
+        moves.push(Move::from_bits(
+            start_sq as u16 | dest_sq << DEST_BITS_OFFSET,
+        ));
+        dest_squares &= dest_squares - 1;
+    }
+}
+
+// The index of a square given by its file ('a' to 'h') and rank ('1' to '8')
+pub(crate) fn sq_num(file: char, rank: char) -> u32 {
+    (file as u32 - 'a' as u32) + ((rank as u32 - '1' as u32) * 8)
 }
 
 #[cfg(test)]
@@ -736,107 +1902,108 @@ mod tests {
 
     // Test castling availability of Position construction
     macro_rules! test_castle {
-        ($test_name:ident, $castle_rights:expr, $position_member:ident, $expected:literal) => {
+        ($test_name:ident, $castle_rights:expr, $color:expr, $king_side:expr, $expected:literal) => {
             #[test]
             fn $test_name() {
-                let fen = concat!("8/8/8/8/8/8/8/8 w ", $castle_rights, " - 0 1");
-                assert_eq!(Position::from(&fen).$position_member, $expected);
+                let fen = concat!("r3k2r/8/8/8/8/8/8/R3K2R w ", $castle_rights, " - 0 1");
+                let rook = Position::from(&fen).castling_rook($color, $king_side);
+                assert_eq!(rook.is_some(), $expected);
             }
         };
     }
 
     // Since castling availability is finite and small, test all possible combniations
-    test_castle!(castling_none_w_king, "-", w_king_castle, false);
-    test_castle!(caslting_none_w_queen, "-", w_queen_castle, false);
-    test_castle!(caslting_none_b_king, "-", b_king_castle, false);
-    test_castle!(caslting_none_b_queen, "-", b_queen_castle, false);
-
-    test_castle!(castling_0_w_king, "K", w_king_castle, true);
-    test_castle!(caslting_0_w_queen, "K", w_queen_castle, false);
-    test_castle!(caslting_0_b_king, "K", b_king_castle, false);
-    test_castle!(caslting_0_b_queen, "K", b_queen_castle, false);
-
-    test_castle!(castling_1_w_king, "k", w_king_castle, false);
-    test_castle!(castling_1_w_queen, "k", w_queen_castle, false);
-    test_castle!(castling_1_b_king, "k", b_king_castle, true);
-    test_castle!(castling_1_b_queen, "k", b_queen_castle, false);
-
-    test_castle!(castling_2_w_king, "Q", w_king_castle, false);
-    test_castle!(castling_2_w_queen, "Q", w_queen_castle, true);
-    test_castle!(castling_2_b_king, "Q", b_king_castle, false);
-    test_castle!(castling_2_b_queen, "Q", b_queen_castle, false);
-
-    test_castle!(castling_3_w_king, "q", w_king_castle, false);
-    test_castle!(castling_3_w_queen, "q", w_queen_castle, false);
-    test_castle!(castling_3_b_king, "q", b_king_castle, false);
-    test_castle!(castling_3_b_queen, "q", b_queen_castle, true);
-
-    test_castle!(castling_4_w_king, "KQ", w_king_castle, true);
-    test_castle!(caslting_4_w_queen, "KQ", w_queen_castle, true);
-    test_castle!(caslting_4_b_king, "KQ", b_king_castle, false);
-    test_castle!(caslting_4_b_queen, "KQ", b_queen_castle, false);
-
-    test_castle!(castling_5_w_king, "Kk", w_king_castle, true);
-    test_castle!(castling_5_w_queen, "Kk", w_queen_castle, false);
-    test_castle!(castling_5_b_king, "Kk", b_king_castle, true);
-    test_castle!(castling_5_b_queen, "Kk", b_queen_castle, false);
-
-    test_castle!(castling_6_w_king, "Kq", w_king_castle, true);
-    test_castle!(castling_6_w_queen, "Kq", w_queen_castle, false);
-    test_castle!(castling_6_b_king, "Kq", b_king_castle, false);
-    test_castle!(castling_6_b_queen, "Kq", b_queen_castle, true);
-
-    test_castle!(castling_7_w_king, "Qk", w_king_castle, false);
-    test_castle!(castling_7_w_queen, "Qk", w_queen_castle, true);
-    test_castle!(castling_7_b_king, "Qk", b_king_castle, true);
-    test_castle!(castling_7_b_queen, "Qk", b_queen_castle, false);
-
-    test_castle!(castling_8_w_king, "Qq", w_king_castle, false);
-    test_castle!(castling_8_w_queen, "Qq", w_queen_castle, true);
-    test_castle!(castling_8_b_king, "Qq", b_king_castle, false);
-    test_castle!(castling_8_b_queen, "Qq", b_queen_castle, true);
-
-    test_castle!(castling_9_w_king, "qk", w_king_castle, false);
-    test_castle!(castling_9_w_queen, "qk", w_queen_castle, false);
-    test_castle!(castling_9_b_king, "qk", b_king_castle, true);
-    test_castle!(castling_9_b_queen, "qk", b_queen_castle, true);
-
-    test_castle!(castling_10_w_king, "KQk", w_king_castle, true);
-    test_castle!(castling_10_w_queen, "KQk", w_queen_castle, true);
-    test_castle!(castling_10_b_king, "KQk", b_king_castle, true);
-    test_castle!(castling_10_b_queen, "KQk", b_queen_castle, false);
-
-    test_castle!(castling_11_w_king, "KQq", w_king_castle, true);
-    test_castle!(castling_11_w_queen, "KQq", w_queen_castle, true);
-    test_castle!(castling_11_b_king, "KQq", b_king_castle, false);
-    test_castle!(castling_11_b_queen, "KQq", b_queen_castle, true);
-
-    test_castle!(castling_12_w_king, "Kkq", w_king_castle, true);
-    test_castle!(castling_12_w_queen, "Kkq", w_queen_castle, false);
-    test_castle!(castling_12_b_king, "Kkq", b_king_castle, true);
-    test_castle!(castling_12_b_queen, "Kkq", b_queen_castle, true);
-
-    test_castle!(castling_13_w_king, "Qkq", w_king_castle, false);
-    test_castle!(castling_13_w_queen, "Qkq", w_queen_castle, true);
-    test_castle!(castling_13_b_king, "Qkq", b_king_castle, true);
-    test_castle!(castling_13_b_queen, "Qkq", b_queen_castle, true);
-
-    test_castle!(castling_14_w_king, "KQkq", w_king_castle, true);
-    test_castle!(castling_14_w_queen, "KQkq", w_queen_castle, true);
-    test_castle!(castling_14_b_king, "KQkq", b_king_castle, true);
-    test_castle!(castling_14_b_queen, "KQkq", b_queen_castle, true);
+    test_castle!(castling_none_w_king, "-", Color::White, true, false);
+    test_castle!(caslting_none_w_queen, "-", Color::White, false, false);
+    test_castle!(caslting_none_b_king, "-", Color::Black, true, false);
+    test_castle!(caslting_none_b_queen, "-", Color::Black, false, false);
+
+    test_castle!(castling_0_w_king, "K", Color::White, true, true);
+    test_castle!(caslting_0_w_queen, "K", Color::White, false, false);
+    test_castle!(caslting_0_b_king, "K", Color::Black, true, false);
+    test_castle!(caslting_0_b_queen, "K", Color::Black, false, false);
+
+    test_castle!(castling_1_w_king, "k", Color::White, true, false);
+    test_castle!(castling_1_w_queen, "k", Color::White, false, false);
+    test_castle!(castling_1_b_king, "k", Color::Black, true, true);
+    test_castle!(castling_1_b_queen, "k", Color::Black, false, false);
+
+    test_castle!(castling_2_w_king, "Q", Color::White, true, false);
+    test_castle!(castling_2_w_queen, "Q", Color::White, false, true);
+    test_castle!(castling_2_b_king, "Q", Color::Black, true, false);
+    test_castle!(castling_2_b_queen, "Q", Color::Black, false, false);
+
+    test_castle!(castling_3_w_king, "q", Color::White, true, false);
+    test_castle!(castling_3_w_queen, "q", Color::White, false, false);
+    test_castle!(castling_3_b_king, "q", Color::Black, true, false);
+    test_castle!(castling_3_b_queen, "q", Color::Black, false, true);
+
+    test_castle!(castling_4_w_king, "KQ", Color::White, true, true);
+    test_castle!(caslting_4_w_queen, "KQ", Color::White, false, true);
+    test_castle!(caslting_4_b_king, "KQ", Color::Black, true, false);
+    test_castle!(caslting_4_b_queen, "KQ", Color::Black, false, false);
+
+    test_castle!(castling_5_w_king, "Kk", Color::White, true, true);
+    test_castle!(castling_5_w_queen, "Kk", Color::White, false, false);
+    test_castle!(castling_5_b_king, "Kk", Color::Black, true, true);
+    test_castle!(castling_5_b_queen, "Kk", Color::Black, false, false);
+
+    test_castle!(castling_6_w_king, "Kq", Color::White, true, true);
+    test_castle!(castling_6_w_queen, "Kq", Color::White, false, false);
+    test_castle!(castling_6_b_king, "Kq", Color::Black, true, false);
+    test_castle!(castling_6_b_queen, "Kq", Color::Black, false, true);
+
+    test_castle!(castling_7_w_king, "Qk", Color::White, true, false);
+    test_castle!(castling_7_w_queen, "Qk", Color::White, false, true);
+    test_castle!(castling_7_b_king, "Qk", Color::Black, true, true);
+    test_castle!(castling_7_b_queen, "Qk", Color::Black, false, false);
+
+    test_castle!(castling_8_w_king, "Qq", Color::White, true, false);
+    test_castle!(castling_8_w_queen, "Qq", Color::White, false, true);
+    test_castle!(castling_8_b_king, "Qq", Color::Black, true, false);
+    test_castle!(castling_8_b_queen, "Qq", Color::Black, false, true);
+
+    test_castle!(castling_9_w_king, "qk", Color::White, true, false);
+    test_castle!(castling_9_w_queen, "qk", Color::White, false, false);
+    test_castle!(castling_9_b_king, "qk", Color::Black, true, true);
+    test_castle!(castling_9_b_queen, "qk", Color::Black, false, true);
+
+    test_castle!(castling_10_w_king, "KQk", Color::White, true, true);
+    test_castle!(castling_10_w_queen, "KQk", Color::White, false, true);
+    test_castle!(castling_10_b_king, "KQk", Color::Black, true, true);
+    test_castle!(castling_10_b_queen, "KQk", Color::Black, false, false);
+
+    test_castle!(castling_11_w_king, "KQq", Color::White, true, true);
+    test_castle!(castling_11_w_queen, "KQq", Color::White, false, true);
+    test_castle!(castling_11_b_king, "KQq", Color::Black, true, false);
+    test_castle!(castling_11_b_queen, "KQq", Color::Black, false, true);
+
+    test_castle!(castling_12_w_king, "Kkq", Color::White, true, true);
+    test_castle!(castling_12_w_queen, "Kkq", Color::White, false, false);
+    test_castle!(castling_12_b_king, "Kkq", Color::Black, true, true);
+    test_castle!(castling_12_b_queen, "Kkq", Color::Black, false, true);
+
+    test_castle!(castling_13_w_king, "Qkq", Color::White, true, false);
+    test_castle!(castling_13_w_queen, "Qkq", Color::White, false, true);
+    test_castle!(castling_13_b_king, "Qkq", Color::Black, true, true);
+    test_castle!(castling_13_b_queen, "Qkq", Color::Black, false, true);
+
+    test_castle!(castling_14_w_king, "KQkq", Color::White, true, true);
+    test_castle!(castling_14_w_queen, "KQkq", Color::White, false, true);
+    test_castle!(castling_14_b_king, "KQkq", Color::Black, true, true);
+    test_castle!(castling_14_b_queen, "KQkq", Color::Black, false, true);
 
     // Test active color of Position construction
     #[test]
     fn active_color_w() {
         let fen = "8/8/8/8/8/8/8/8 w - - 0 1";
-        assert_eq!(Position::from(fen).is_white_move, true);
+        assert!(Position::from(fen).is_white_move);
     }
 
     #[test]
     fn active_color_b() {
         let fen = "8/8/8/8/8/8/8/8 b - - 0 1";
-        assert_eq!(Position::from(fen).is_white_move, false);
+        assert!(!Position::from(fen).is_white_move);
     }
 
     // Test en passant square of Position construction
@@ -964,13 +2131,14 @@ mod tests {
     test_full_number!(full_number_6, "5", 5);
     test_full_number!(full_number_7, "9", 9);
     test_full_number!(full_number_8, "17", 17);
+    test_full_number!(full_number_9, "300", 300);
 
-    // Test sq_to_bitboard
+    // Test sq_num
     macro_rules! test_sq_to_bb {
         ($test_name:ident, $file:expr, $rank:expr, $expected:expr) => {
             #[test]
             fn $test_name() {
-                assert_eq!(sq_to_bitboard($file, $rank), $expected);
+                assert_eq!(1u64 << sq_num($file, $rank), $expected);
             }
         };
     }
@@ -1007,14 +2175,16 @@ mod tests {
             ],
             passant_sq: 0, // En Passant square
 
-            w_king_castle: true,
-            w_queen_castle: true,
-            b_king_castle: true,
-            b_queen_castle: true,
+            castling_rooks: 0x8100000000000081,
 
             is_white_move: true,
             hlf_clock: 0,
             full_num: 1,
+
+            variant: Variant::Standard,
+            checks: [0, 0],
+            hands: [[0; 5]; 2],
+            promoted: 0,
         };
         assert_eq!(start_position, expected);
     }
@@ -1038,6 +2208,302 @@ mod tests {
         assert_eq!(pos.evaluate(), isize::MAX);
     }
 
+    // Perft results from https://www.chessprogramming.org/Perft_Results
+    macro_rules! test_perft {
+        ($test_name:ident, $fen:expr, $depth:literal, $expected:literal) => {
+            #[test]
+            fn $test_name() {
+                assert_eq!(Position::from($fen).perft($depth), $expected);
+            }
+        };
+    }
+
+    test_perft!(perft_startpos_1, STARTPOS, 1, 20);
+    test_perft!(perft_startpos_2, STARTPOS, 2, 400);
+    test_perft!(perft_startpos_3, STARTPOS, 3, 8902);
+    test_perft!(perft_startpos_4, STARTPOS, 4, 197281);
+    test_perft!(perft_complex_2_1, COMPLEX_POS_2, 1, 48);
+    test_perft!(perft_complex_2_2, COMPLEX_POS_2, 2, 2039);
+    test_perft!(perft_complex_2_3, COMPLEX_POS_2, 3, 97862);
+    test_perft!(perft_complex_3_4, COMPLEX_POS_3, 4, 43238);
+    test_perft!(perft_complex_4_3, COMPLEX_POS_4, 3, 9467);
+    test_perft!(perft_complex_5_3, COMPLEX_POS_5, 3, 62379);
+    test_perft!(perft_complex_6_3, COMPLEX_POS_6, 3, 89890);
+
+    // Chess960 perft results from https://www.chessprogramming.org/Chess960_Perft_Results
+    const CHESS960_POS_1: &str =
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+    const CHESS960_POS_2: &str = "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9";
+    const CHESS960_POS_3: &str = "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9";
+
+    test_perft!(perft_chess960_1_3, CHESS960_POS_1, 3, 12189);
+    test_perft!(perft_chess960_1_4, CHESS960_POS_1, 4, 326672);
+    test_perft!(perft_chess960_2_3, CHESS960_POS_2, 3, 18002);
+    test_perft!(perft_chess960_3_4, CHESS960_POS_3, 4, 273318);
+
+    #[test]
+    fn chess960_start_positions() {
+        assert_eq!(Position::chess960(518), Position::new());
+        assert_eq!(
+            Position::chess960(0).to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            Position::chess960(959).to_shredder_fen(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1"
+        );
+        for number in 0..960 {
+            assert_eq!(
+                Position::chess960(number)
+                    .castling_rights()
+                    .iter()
+                    .flatten()
+                    .count(),
+                4
+            );
+        }
+    }
+
+    #[test]
+    fn chess960_fen_round_trip() {
+        for fen in [CHESS960_POS_1, CHESS960_POS_2, CHESS960_POS_3] {
+            assert_eq!(Position::from(fen).to_shredder_fen(), fen);
+        }
+        // An inner rook is named by its file in X-FEN
+        let fen = "1r2k1rr/8/8/8/8/8/8/RR2K2R w KBk - 0 1";
+        assert_eq!(Position::from(fen).to_fen(), fen);
+        assert_eq!(
+            Position::from(fen).to_shredder_fen(),
+            "1r2k1rr/8/8/8/8/8/8/RR2K2R w HBh - 0 1"
+        );
+    }
+
+    // In Chess960 the king and rook may start on or next to their castled
+    // squares, and castling only needs the squares they cross to be empty
+    #[test]
+    fn chess960_castling() {
+        let mut position = Position::from("4k3/8/8/8/8/8/8/1R4KR w HB - 0 1");
+        let king_side = str_to_move("g1h1", position).unwrap();
+        let queen_side = str_to_move("g1b1", position).unwrap();
+        assert!(position.moves().contains(&king_side));
+        assert!(position.moves().contains(&queen_side));
+        assert_eq!(move_to_uci(queen_side, false), "g1c1");
+
+        position.play_move(king_side);
+        assert_eq!(position.to_fen(), "4k3/8/8/8/8/8/8/1R3RK1 b - - 1 1");
+
+        // The rook the king castles with may not be pinned to the king's
+        // destination
+        let pinned = Position::from("4k3/8/8/8/8/8/8/rR1K4 w B - 0 1");
+        assert_eq!(str_to_move("d1b1", pinned), None);
+    }
+
+    #[test]
+    fn str_to_move_only_finds_legal_moves() {
+        let no_rights = Position::from("4k3/8/8/8/8/8/8/4K2R w - - 0 1");
+        assert_eq!(str_to_move("e1g1", no_rights), None);
+        assert_eq!(str_to_move("e1e3", no_rights), None);
+        assert_eq!(str_to_move("e7e5", no_rights), None);
+        assert_eq!(str_to_move("P@e4", no_rights), None);
+        assert_eq!(str_to_move("h1", no_rights), None);
+
+        let castling = Position::from("4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        let mov = str_to_move("e1g1", castling).unwrap();
+        assert_eq!(mov.kind(), MoveKind::Castling);
+        assert_eq!(str_to_move("e1h1", castling), Some(mov));
+    }
+
+    #[test]
+    fn move_strings_round_trip() {
+        let position = Position::from(COMPLEX_POS_2);
+        for mov in position.moves() {
+            assert_eq!(str_to_move(&move_to_str(mov), position).unwrap(), mov);
+        }
+        let promotion = Position::from("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        for mov in promotion.moves() {
+            assert_eq!(str_to_move(&move_to_str(mov), promotion).unwrap(), mov);
+        }
+        assert_eq!(
+            move_to_str(str_to_move("b7b8n", promotion).unwrap()),
+            "b7b8n"
+        );
+    }
+
+    // Position::to_fen() should reproduce the FEN a position was built from
+    macro_rules! test_to_fen {
+        ($test_name:ident, $fen:expr) => {
+            #[test]
+            fn $test_name() {
+                assert_eq!(Position::from($fen).to_fen(), $fen);
+            }
+        };
+    }
+
+    test_to_fen!(to_fen_startpos, STARTPOS);
+    test_to_fen!(to_fen_complex_2, COMPLEX_POS_2);
+    test_to_fen!(to_fen_complex_3_b, COMPLEX_POS_3_B);
+    test_to_fen!(to_fen_complex_4, COMPLEX_POS_4);
+    test_to_fen!(to_fen_complex_5, COMPLEX_POS_5);
+    test_to_fen!(to_fen_complex_6_b, COMPLEX_POS_6_B);
+    test_to_fen!(
+        to_fen_passant,
+        "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2"
+    );
+
+    #[test]
+    fn hash_identifies_positions() {
+        let mut position = Position::new();
+        let start_hash = position.hash();
+        for mov in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            position.play_move(str_to_move(mov, position).unwrap());
+        }
+        assert_eq!(position.hash(), start_hash);
+
+        assert_ne!(Position::from(STARTPOS_B).hash(), start_hash);
+        assert_ne!(
+            Position::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Kkq - 0 1").hash(),
+            start_hash
+        );
+    }
+
+    #[test]
+    fn insufficient_material() {
+        assert!(Position::from("8/8/4k3/8/8/3K4/8/8 w - - 0 1").is_insufficient_material());
+        assert!(Position::from("8/8/4k3/8/8/3KN3/8/8 w - - 0 1").is_insufficient_material());
+        assert!(!Position::from("8/8/4k3/8/8/3KNN2/8/8 w - - 0 1").is_insufficient_material());
+        assert!(!Position::from("8/8/4k3/8/8/3K4/7P/8 w - - 0 1").is_insufficient_material());
+        assert!(!Position::new().is_insufficient_material());
+    }
+
+    // Static exchange evaluation of a move in the FEN position
+    macro_rules! test_see {
+        ($test_name:ident, $fen:literal, $move_str:literal, $expected:expr) => {
+            #[test]
+            fn $test_name() {
+                let position = Position::from($fen);
+                let mov = str_to_move($move_str, position).unwrap();
+                assert_eq!(position.see(mov), $expected);
+                assert!(position.see_ge(mov, $expected));
+                assert!(!position.see_ge(mov, $expected + 1));
+            }
+        };
+    }
+
+    test_see!(
+        see_undefended_pawn,
+        "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+        "e1e5",
+        100
+    );
+    test_see!(
+        see_knight_for_pawn,
+        "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+        "d3e5",
+        -220
+    );
+    test_see!(
+        see_pawn_takes_queen,
+        "4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1",
+        "e4d5",
+        900
+    );
+    test_see!(
+        see_rook_for_pawn,
+        "4k3/8/2p5/3p4/8/8/3R4/3RK3 w - - 0 1",
+        "d2d5",
+        -300
+    );
+    test_see!(
+        see_xray_rook,
+        "3r1k2/8/8/3p4/8/8/3R4/3RK3 w - - 0 1",
+        "d2d5",
+        100
+    );
+    test_see!(
+        see_xray_behind_pawn,
+        "4k3/8/2p5/3p4/4P3/5B2/8/4K3 w - - 0 1",
+        "e4d5",
+        100
+    );
+    test_see!(
+        see_king_cannot_recapture,
+        "3rk3/8/8/8/8/8/3q4/3RK3 b - - 0 1",
+        "d2d1",
+        500
+    );
+    test_see!(
+        see_quiet_move_hangs_queen,
+        "4k3/8/4p3/8/8/8/8/3QK3 w - - 0 1",
+        "d1d5",
+        -900
+    );
+    test_see!(
+        see_en_passant,
+        "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        "e5d6",
+        100
+    );
+    test_see!(
+        see_promotion,
+        "4k3/P7/8/8/8/8/8/4K3 w - - 0 1",
+        "a7a8q",
+        800
+    );
+    test_see!(
+        see_castling,
+        "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        "e1g1",
+        0
+    );
+
+    #[test]
+    fn gives_check() {
+        let position = Position::from("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1");
+        assert!(position.gives_check(str_to_move("d5d8", position).unwrap()));
+        assert!(position.gives_check(str_to_move("d5f7", position).unwrap()));
+        assert!(!position.gives_check(str_to_move("d5d6", position).unwrap()));
+        assert!(!position.gives_check(str_to_move("e1e7", position).unwrap()));
+    }
+
+    #[test]
+    fn push_to_seventh() {
+        let position = Position::from("4k3/8/1P6/8/8/p7/4p3/4K3 w - - 0 1");
+        assert!(position.is_push_to_seventh(str_to_move("b6b7", position).unwrap()));
+        assert!(!position.is_push_to_seventh(str_to_move("e1d2", position).unwrap()));
+        let position = Position::from("4k3/8/1P6/8/8/p7/4p3/3K4 b - - 0 1");
+        assert!(position.is_push_to_seventh(str_to_move("a3a2", position).unwrap()));
+        assert!(!position.is_push_to_seventh(str_to_move("e2e1q", position).unwrap()));
+        assert!(!position.is_push_to_seventh(str_to_move("e8e7", position).unwrap()));
+    }
+
+    #[test]
+    fn non_pawn_material() {
+        assert!(Position::new().has_non_pawn_material());
+        assert!(!Position::from("8/5p2/4k3/8/8/3K4/7P/8 w - - 0 1").has_non_pawn_material());
+        assert!(Position::from("8/5p2/4k3/8/8/3K4/7P/7R w - - 0 1").has_non_pawn_material());
+        assert!(!Position::from("8/5p2/4k3/8/8/3K4/7P/7R b - - 0 1").has_non_pawn_material());
+    }
+
+    #[test]
+    fn null_move() {
+        let mut position =
+            Position::from("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3");
+        position.play_null_move();
+        assert_eq!(
+            position.to_fen(),
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 4"
+        );
+    }
+
+    #[test]
+    fn check_detection() {
+        assert!(!Position::new().is_in_check());
+        let checked =
+            Position::from("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(checked.is_in_check());
+        assert!(checked.moves().is_empty());
+    }
+
     // Position::play_move() testing
     macro_rules! test_play_move {
         ($test_name:ident, $starting_position:expr, $move:expr, $expected:expr) => {
@@ -1045,7 +2511,7 @@ mod tests {
             fn $test_name() {
                 let mut starting_position = Position::from($starting_position);
                 let expected_position = Position::from($expected);
-                let mov = str_to_move($move, starting_position);
+                let mov = str_to_move($move, starting_position).unwrap();
                 starting_position.play_move(mov);
                 assert_eq!(starting_position, expected_position);
             }
@@ -1331,7 +2797,8 @@ mod tests {
             #[test]
             fn $test_name() {
                 let starting_position = Position::from($starting_position);
-                let mut actual = starting_position.generate_knight_moves();
+                let mut actual = Vec::new();
+                starting_position.generate_knight_moves(&mut actual);
                 actual.sort();
                 let mut expected = $expected;
                 expected.sort();
@@ -1342,7 +2809,11 @@ mod tests {
 
     macro_rules! leaper_move {
         ($origin_sq: expr, $dest_sq: expr) => {
-            ($origin_sq | ($dest_sq << DEST_BITS_OFFSET))
+            Move::new(
+                Square::from_index($origin_sq),
+                Square::from_index($dest_sq),
+                MoveKind::Normal,
+            )
         };
     }
 
@@ -1403,6 +2874,8 @@ mod tests {
         ]
     );
 
+    test_generate_leapers!(complex_3_leaper_moves_b, COMPLEX_POS_3_B, vec![]);
+
     test_generate_leapers!(
         complex_4_leaper_moves,
         COMPLEX_POS_4,
@@ -1515,4 +2988,539 @@ mod tests {
         "4k3/8/8/b7/8/8/3N4/4K3 w KQkq - 0 1",
         vec![]
     );
+
+    macro_rules! test_parse_san {
+        ($test_name:ident, $fen:literal, $($san:literal => $expected:expr),*) => {
+            #[test]
+            fn $test_name() {
+                let position = Position::from($fen);
+                $(
+                    let expected: Option<&str> = $expected;
+                    assert_eq!(
+                        position.parse_san($san).ok(),
+                        expected.map(|x| str_to_move(x, position).unwrap()),
+                        "{}",
+                        $san
+                    );
+                )*
+            }
+        };
+    }
+
+    test_parse_san!(
+        parse_san_start,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "e4" => Some("e2e4"),
+        "e3" => Some("e2e3"),
+        "Nf3" => Some("g1f3"),
+        "Ng1f3" => Some("g1f3"),
+        "Nf3+" => Some("g1f3"),
+        "e5" => None,
+        "Bc4" => None,
+        "O-O" => None,
+        "" => None,
+        "xyz" => None
+    );
+
+    test_parse_san!(
+        parse_san_disambiguation,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "Nd5" => None,
+        "Ncd5" => None,
+        "Ng4" => Some("e5g4"),
+        "Nxd7" => Some("e5d7"),
+        "Rb1" => Some("a1b1"),
+        "Bxa6" => Some("e2a6"),
+        "dxe6" => Some("d5e6"),
+        "gxh3" => Some("g2h3"),
+        "O-O" => Some("e1g1"),
+        "O-O-O" => Some("e1c1"),
+        "0-0" => Some("e1g1")
+    );
+
+    test_parse_san!(
+        parse_san_en_passant,
+        "1n2k3/2P5/8/8/3pP3/8/8/4K3 b - e3 0 1",
+        "dxe3" => Some("d4e3"),
+        "d3" => Some("d4d3"),
+        "Nc6" => Some("b8c6"),
+        "Kd7" => Some("e8d7")
+    );
+
+    test_parse_san!(
+        parse_san_promotion,
+        "1n2k3/2P5/8/8/8/8/8/4K3 w - - 0 1",
+        "c8=Q+" => Some("c7c8q"),
+        "c8Q" => Some("c7c8q"),
+        "cxb8=N" => Some("c7b8n"),
+        "cxb8R" => Some("c7b8r"),
+        "c8" => None
+    );
+
+    macro_rules! test_move_to_san {
+        ($test_name:ident, $fen:literal, $($mov:literal => $expected:literal),*) => {
+            #[test]
+            fn $test_name() {
+                let position = Position::from($fen);
+                $(
+                    let mov = str_to_move($mov, position).unwrap();
+                    assert_eq!(position.move_to_san(mov), $expected);
+                    assert_eq!(position.parse_san($expected), Ok(mov));
+                )*
+            }
+        };
+    }
+
+    test_move_to_san!(
+        move_to_san_kiwipete,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "e2a6" => "Bxa6",
+        "e5f7" => "Nxf7",
+        "d5e6" => "dxe6",
+        "c3b1" => "Nb1",
+        "e1g1" => "O-O",
+        "e1c1" => "O-O-O",
+        "a2a4" => "a4",
+        "f3f6" => "Qxf6"
+    );
+
+    test_move_to_san!(
+        move_to_san_disambiguation,
+        "4k3/8/8/R6R/8/8/1N3N2/R3K3 w - - 0 1",
+        "a5d5" => "Rad5",
+        "h5d5" => "Rhd5",
+        "a1a3" => "R1a3",
+        "b2d3" => "Nbd3",
+        "f2d3" => "Nfd3",
+        "f2h3" => "Nh3"
+    );
+
+    test_move_to_san!(
+        move_to_san_checks,
+        "1n2k3/2P5/8/8/3pP3/8/8/4K2R b K e3 0 1",
+        "d4e3" => "dxe3",
+        "e8d7" => "Kd7"
+    );
+
+    test_move_to_san!(
+        move_to_san_promotions,
+        "1n2k3/2P5/8/8/8/8/8/4K2R w K - 0 1",
+        "c7c8q" => "c8=Q+",
+        "c7b8n" => "cxb8=N",
+        "c7b8r" => "cxb8=R+",
+        "h1h8" => "Rh8+"
+    );
+
+    test_move_to_san!(
+        move_to_san_mate,
+        "6k1/1p3ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+        "a1a8" => "Ra8#"
+    );
+
+    #[test]
+    fn parse_san_errors() {
+        let position =
+            Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(
+            position.parse_san("Nd5"),
+            Err(String::from("Illegal move: Nd5"))
+        );
+        assert_eq!(
+            position.parse_san("z9"),
+            Err(String::from("Invalid move: z9"))
+        );
+        assert_eq!(position.parse_san(""), Err(String::from("Invalid move: ")));
+        let position = Position::from("4k3/8/8/R6R/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            position.parse_san("Rd5"),
+            Err(String::from("Ambiguous move: Rd5"))
+        );
+        assert!(position.parse_san("Rad5").is_ok());
+    }
+
+    // Move accessors: origin, destination, kind and promotion piece
+    macro_rules! test_move_accessors {
+        ($test_name:ident, $fen:expr, $move:expr, $kind:expr) => {
+            #[test]
+            fn $test_name() {
+                let position = Position::from($fen);
+                let mov = str_to_move($move, position).unwrap();
+                assert_eq!(mov.from().to_string(), &$move[..2]);
+                assert_eq!(mov.to().to_string(), &$move[2..4]);
+                assert_eq!(mov.kind(), $kind);
+                assert_eq!(Move::new(mov.from(), mov.to(), mov.kind()), mov);
+                assert!(position.moves().contains(&mov));
+                assert_eq!(mov.to_string(), $move);
+            }
+        };
+    }
+
+    test_move_accessors!(move_normal, STARTPOS, "g1f3", MoveKind::Normal);
+    test_move_accessors!(move_double_push, STARTPOS, "e2e4", MoveKind::DoublePawnPush);
+    test_move_accessors!(
+        move_en_passant,
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "e5f6",
+        MoveKind::EnPassant
+    );
+    test_move_accessors!(
+        move_promotion,
+        "8/1P6/8/8/8/8/8/k6K w - - 0 1",
+        "b7b8r",
+        MoveKind::Promotion(PieceType::Rook)
+    );
+
+    // Castling is encoded as the king capturing its own rook, whichever way
+    // UCI writes it
+    #[test]
+    fn move_castling() {
+        let position = Position::from("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        let mov = str_to_move("e8c8", position).unwrap();
+        assert_eq!(mov, str_to_move("e8a8", position).unwrap());
+        assert_eq!(mov.kind(), MoveKind::Castling);
+        assert_eq!(
+            (mov.from().to_string(), mov.to().to_string()),
+            ("e8".into(), "a8".into())
+        );
+        assert_eq!(mov.to_string(), "e8c8");
+        assert_eq!(move_to_uci(mov, true), "e8a8");
+        assert!(position.moves().contains(&mov));
+    }
+
+    #[test]
+    fn move_promotion_piece() {
+        let position = Position::from("8/1P6/8/8/8/8/8/k6K w - - 0 1");
+        let pieces: Vec<Option<PieceType>> = ["b7b8q", "b7b8n", "b7b8b"]
+            .iter()
+            .map(|x| str_to_move(x, position).unwrap().promotion())
+            .collect();
+        let expected = [PieceType::Queen, PieceType::Knight, PieceType::Bishop];
+        assert_eq!(pieces, expected.map(Some));
+        assert_eq!(str_to_move("b7b8q", position).unwrap().bits(), 0x7E71);
+        assert_eq!(str_to_move("h1h2", position).unwrap().promotion(), None);
+        assert_eq!(Move::NONE.from(), Move::NONE.to());
+    }
+
+    // Antichess pawns may promote to a king, which has an encoding of its own
+    #[test]
+    fn move_king_promotion() {
+        let position = Position::from_variant("8/1P6/8/8/8/8/8/k6K w - - 0 1", Variant::Antichess);
+        let mov = str_to_move("b7b8k", position).unwrap();
+        assert_eq!(mov.kind(), MoveKind::Promotion(PieceType::King));
+        assert_eq!(mov, Move::new(mov.from(), mov.to(), mov.kind()));
+        assert_eq!(move_to_str(mov), "b7b8k");
+        assert_eq!(position.move_to_san(mov), "b8=K");
+        assert_eq!(position.parse_san("b8=K"), Ok(mov));
+        assert!(position.moves().contains(&mov));
+        assert!(!Position::from("8/1P6/8/8/8/8/8/k6K w - - 0 1")
+            .moves()
+            .contains(&mov));
+
+        let mut promoted = position;
+        promoted.play_move(mov);
+        assert_eq!(promoted.to_fen(), "1K6/8/8/8/8/8/8/k6K b - - 0 1");
+    }
+
+    #[test]
+    fn try_from_invalid_fen() {
+        let invalid = [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[K] w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 70000",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +a+0",
+        ];
+        for fen in invalid {
+            assert!(Position::try_from(fen).is_err(), "{}", fen);
+        }
+
+        // Missing clocks default to the start of a game
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+        assert_eq!(Position::try_from(fen), Ok(Position::new()));
+    }
+
+    // Three-check FEN holds the checks each side has left, and playing a
+    // check uses one up
+    #[test]
+    fn three_check_fen() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w Q - 2+3 0 1";
+        let mut position = Position::from_variant(fen, Variant::ThreeCheck);
+        assert_eq!(position.checks_given(Color::White), 1);
+        assert_eq!(position.to_fen(), fen);
+
+        let hash = position.hash();
+        position.play_move(str_to_move("a1a8", position).unwrap());
+        assert_eq!(position.checks_given(Color::White), 2);
+        assert_eq!(position.to_fen(), "R3k3/8/8/8/8/8/8/4K3 b - - 1+3 1 1");
+        assert_eq!(
+            Position::from("R3k3/8/8/8/8/8/8/4K3 b - - 0 1 +2+0").checks,
+            [2, 0]
+        );
+
+        // The same placement with different checks left is a different
+        // position
+        let mut other =
+            Position::from_variant("4k3/8/8/8/8/8/8/R3K3 w Q - 3+3 0 1", Variant::ThreeCheck);
+        assert_ne!(other.hash(), hash);
+        other.checks = position.checks;
+        other.checks[0] -= 1;
+        assert_eq!(other.hash(), hash);
+    }
+
+    // Crazyhouse drops hold the dropped piece where the origin square would be
+    #[test]
+    fn move_drop() {
+        let fen = "4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1";
+        let position = Position::from_variant(fen, Variant::Crazyhouse);
+        let mov = str_to_move("N@f6", position).unwrap();
+        assert_eq!(mov.kind(), MoveKind::Drop(PieceType::Knight));
+        assert_eq!(mov.dropped(), Some(PieceType::Knight));
+        assert_eq!(mov.to().to_string(), "f6");
+        assert_eq!(mov, Move::new(mov.to(), mov.to(), mov.kind()));
+        assert_eq!(move_to_str(mov), "N@f6");
+        assert_eq!(position.move_to_san(mov), "N@f6+");
+        assert_eq!(position.parse_san("N@f6"), Ok(mov));
+        assert!(position.moves().contains(&mov));
+
+        let pawn = str_to_move("P@d7", position).unwrap();
+        assert_eq!(position.parse_san("@d7+"), Ok(pawn));
+        assert!(position.parse_san("P@d8").is_err());
+        assert_eq!(str_to_move("e1e2", position).unwrap().dropped(), None);
+
+        let mut dropped = position;
+        dropped.play_move(pawn);
+        assert_eq!(dropped.in_hand(Color::White, PieceType::Pawn), 0);
+        assert_eq!(dropped.to_fen(), "4k3/3P4/8/8/8/8/8/4K3[N] b - - 0 1");
+    }
+
+    // Captured pieces join the capturer's hand, and promoted pieces return to
+    // it as pawns
+    #[test]
+    fn crazyhouse_captures() {
+        let fen = "1r2k3/P2n4/8/8/8/8/8/4K3[] w - - 0 1";
+        let mut position = Position::from_variant(fen, Variant::Crazyhouse);
+        assert_eq!(position.to_fen(), fen);
+        position.play_move(str_to_move("a7b8q", position).unwrap());
+        assert_eq!(position.in_hand(Color::White, PieceType::Rook), 1);
+        assert_eq!(position.to_fen(), "1Q~2k3/3n4/8/8/8/8/8/4K3[R] b - - 0 1");
+        position.play_move(str_to_move("d7b8", position).unwrap());
+        assert_eq!(position.to_fen(), "1n2k3/8/8/8/8/8/8/4K3[Rp] w - - 0 2");
+
+        let fen = "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR[] w KQkq d6 0 3";
+        let mut position = Position::from_variant(fen, Variant::Crazyhouse);
+        position.play_move(str_to_move("e5d6", position).unwrap());
+        assert_eq!(position.in_hand(Color::White, PieceType::Pawn), 1);
+        assert!(position.moves().iter().all(|x| x.dropped().is_none()));
+    }
+
+    #[test]
+    fn crazyhouse_fen() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5Q~2/PPPP1PPP/RNB1KBNR[QNPPbp] w KQkq - 2 3";
+        let position = Position::from_variant(fen, Variant::Crazyhouse);
+        assert_eq!(position.to_fen(), fen);
+        assert_eq!(position.in_hand(Color::White, PieceType::Pawn), 2);
+        assert_eq!(position.in_hand(Color::Black, PieceType::Bishop), 1);
+        assert_eq!(position.in_hand(Color::Black, PieceType::King), 0);
+        assert_eq!(position.promoted, 1u64 << 21);
+
+        // Holdings may also be written as a ninth rank
+        let ninth_rank = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5Q~2/PPPP1PPP/RNB1KBNR/PbQpPN w KQkq - 2 3";
+        let other = Position::from_variant(ninth_rank, Variant::Crazyhouse);
+        assert_eq!(other.to_fen(), fen);
+        assert_eq!(other.hash(), position.hash());
+
+        let mut fewer = other;
+        fewer.hands[0][W_QUEEN] -= 1;
+        assert_ne!(fewer.hash(), position.hash());
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let position =
+            Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1");
+        let square = |x: &str| x.parse::<Square>().unwrap();
+        let white_knight = Piece::new(Color::White, PieceType::Knight);
+
+        assert_eq!(position.side_to_move(), Color::Black);
+        assert_eq!(position.piece_on(square("e5")), Some(white_knight));
+        assert_eq!(
+            position.piece_on(square("e6")).map(|x| x.to_char()),
+            Some('p')
+        );
+        assert_eq!(position.piece_on(square("e3")), None);
+
+        let knights: Vec<String> = position
+            .bitboard(white_knight)
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(knights, ["c3", "e5"]);
+        assert_eq!(position.occupied(Color::White).count(), 16);
+        assert_eq!(
+            position.occupied(Color::White) & position.occupied(Color::Black),
+            Bitboard::EMPTY
+        );
+
+        assert!(position.is_attacked(square("d7"), Color::White));
+        assert!(!position.is_attacked(square("a5"), Color::White));
+        assert!(position.is_attacked(square("h5"), Color::Black));
+    }
+
+    // The hand-written knight and king move tables that were used before move
+    // generation switched to the generated attack tables
+    const KNIGHT_MOVES: [&[u16]; 64] = [
+        &[640, 1088],
+        &[705, 1025, 1153],
+        &[514, 770, 1090, 1218],
+        &[579, 835, 1155, 1283],
+        &[644, 900, 1220, 1348],
+        &[709, 965, 1285, 1413],
+        &[774, 1350, 1478],
+        &[839, 1415],
+        &[136, 1160, 1608],
+        &[201, 1225, 1545, 1673],
+        &[10, 266, 1034, 1290, 1610, 1738],
+        &[75, 331, 1099, 1355, 1675, 1803],
+        &[140, 396, 1164, 1420, 1740, 1868],
+        &[205, 461, 1229, 1485, 1805, 1933],
+        &[270, 1294, 1870, 1998],
+        &[335, 1359, 1935],
+        &[80, 656, 1680, 2128],
+        &[17, 145, 721, 1745, 2065, 2193],
+        &[82, 210, 530, 786, 1554, 1810, 2130, 2258],
+        &[147, 275, 595, 851, 1619, 1875, 2195, 2323],
+        &[212, 340, 660, 916, 1684, 1940, 2260, 2388],
+        &[277, 405, 725, 981, 1749, 2005, 2325, 2453],
+        &[342, 470, 790, 1814, 2390, 2518],
+        &[407, 855, 1879, 2455],
+        &[600, 1176, 2200, 2648],
+        &[537, 665, 1241, 2265, 2585, 2713],
+        &[602, 730, 1050, 1306, 2074, 2330, 2650, 2778],
+        &[667, 795, 1115, 1371, 2139, 2395, 2715, 2843],
+        &[732, 860, 1180, 1436, 2204, 2460, 2780, 2908],
+        &[797, 925, 1245, 1501, 2269, 2525, 2845, 2973],
+        &[862, 990, 1310, 2334, 2910, 3038],
+        &[927, 1375, 2399, 2975],
+        &[1120, 1696, 2720, 3168],
+        &[1057, 1185, 1761, 2785, 3105, 3233],
+        &[1122, 1250, 1570, 1826, 2594, 2850, 3170, 3298],
+        &[1187, 1315, 1635, 1891, 2659, 2915, 3235, 3363],
+        &[1252, 1380, 1700, 1956, 2724, 2980, 3300, 3428],
+        &[1317, 1445, 1765, 2021, 2789, 3045, 3365, 3493],
+        &[1382, 1510, 1830, 2854, 3430, 3558],
+        &[1447, 1895, 2919, 3495],
+        &[1640, 2216, 3240, 3688],
+        &[1577, 1705, 2281, 3305, 3625, 3753],
+        &[1642, 1770, 2090, 2346, 3114, 3370, 3690, 3818],
+        &[1707, 1835, 2155, 2411, 3179, 3435, 3755, 3883],
+        &[1772, 1900, 2220, 2476, 3244, 3500, 3820, 3948],
+        &[1837, 1965, 2285, 2541, 3309, 3565, 3885, 4013],
+        &[1902, 2030, 2350, 3374, 3950, 4078],
+        &[1967, 2415, 3439, 4015],
+        &[2160, 2736, 3760],
+        &[2097, 2225, 2801, 3825],
+        &[2162, 2290, 2610, 2866, 3634, 3890],
+        &[2227, 2355, 2675, 2931, 3699, 3955],
+        &[2292, 2420, 2740, 2996, 3764, 4020],
+        &[2357, 2485, 2805, 3061, 3829, 4085],
+        &[2422, 2550, 2870, 3894],
+        &[2487, 2935, 3959],
+        &[2680, 3256],
+        &[2617, 2745, 3321],
+        &[2682, 2810, 3130, 3386],
+        &[2747, 2875, 3195, 3451],
+        &[2812, 2940, 3260, 3516],
+        &[2877, 3005, 3325, 3581],
+        &[2942, 3070, 3390],
+        &[3007, 3455],
+    ];
+    const KING_MOVES: [&[u16]; 64] = [
+        &[64, 512, 576],
+        &[1, 129, 513, 577, 641],
+        &[66, 194, 578, 642, 706],
+        &[131, 259, 643, 707, 771],
+        &[196, 324, 708, 772, 836],
+        &[261, 389, 773, 837, 901],
+        &[326, 454, 838, 902, 966],
+        &[391, 903, 967],
+        &[8, 72, 584, 1032, 1096],
+        &[9, 73, 137, 521, 649, 1033, 1097, 1161],
+        &[74, 138, 202, 586, 714, 1098, 1162, 1226],
+        &[139, 203, 267, 651, 779, 1163, 1227, 1291],
+        &[204, 268, 332, 716, 844, 1228, 1292, 1356],
+        &[269, 333, 397, 781, 909, 1293, 1357, 1421],
+        &[334, 398, 462, 846, 974, 1358, 1422, 1486],
+        &[399, 463, 911, 1423, 1487],
+        &[528, 592, 1104, 1552, 1616],
+        &[529, 593, 657, 1041, 1169, 1553, 1617, 1681],
+        &[594, 658, 722, 1106, 1234, 1618, 1682, 1746],
+        &[659, 723, 787, 1171, 1299, 1683, 1747, 1811],
+        &[724, 788, 852, 1236, 1364, 1748, 1812, 1876],
+        &[789, 853, 917, 1301, 1429, 1813, 1877, 1941],
+        &[854, 918, 982, 1366, 1494, 1878, 1942, 2006],
+        &[919, 983, 1431, 1943, 2007],
+        &[1048, 1112, 1624, 2072, 2136],
+        &[1049, 1113, 1177, 1561, 1689, 2073, 2137, 2201],
+        &[1114, 1178, 1242, 1626, 1754, 2138, 2202, 2266],
+        &[1179, 1243, 1307, 1691, 1819, 2203, 2267, 2331],
+        &[1244, 1308, 1372, 1756, 1884, 2268, 2332, 2396],
+        &[1309, 1373, 1437, 1821, 1949, 2333, 2397, 2461],
+        &[1374, 1438, 1502, 1886, 2014, 2398, 2462, 2526],
+        &[1439, 1503, 1951, 2463, 2527],
+        &[1568, 1632, 2144, 2592, 2656],
+        &[1569, 1633, 1697, 2081, 2209, 2593, 2657, 2721],
+        &[1634, 1698, 1762, 2146, 2274, 2658, 2722, 2786],
+        &[1699, 1763, 1827, 2211, 2339, 2723, 2787, 2851],
+        &[1764, 1828, 1892, 2276, 2404, 2788, 2852, 2916],
+        &[1829, 1893, 1957, 2341, 2469, 2853, 2917, 2981],
+        &[1894, 1958, 2022, 2406, 2534, 2918, 2982, 3046],
+        &[1959, 2023, 2471, 2983, 3047],
+        &[2088, 2152, 2664, 3112, 3176],
+        &[2089, 2153, 2217, 2601, 2729, 3113, 3177, 3241],
+        &[2154, 2218, 2282, 2666, 2794, 3178, 3242, 3306],
+        &[2219, 2283, 2347, 2731, 2859, 3243, 3307, 3371],
+        &[2284, 2348, 2412, 2796, 2924, 3308, 3372, 3436],
+        &[2349, 2413, 2477, 2861, 2989, 3373, 3437, 3501],
+        &[2414, 2478, 2542, 2926, 3054, 3438, 3502, 3566],
+        &[2479, 2543, 2991, 3503, 3567],
+        &[2608, 2672, 3184, 3632, 3696],
+        &[2609, 2673, 2737, 3121, 3249, 3633, 3697, 3761],
+        &[2674, 2738, 2802, 3186, 3314, 3698, 3762, 3826],
+        &[2739, 2803, 2867, 3251, 3379, 3763, 3827, 3891],
+        &[2804, 2868, 2932, 3316, 3444, 3828, 3892, 3956],
+        &[2869, 2933, 2997, 3381, 3509, 3893, 3957, 4021],
+        &[2934, 2998, 3062, 3446, 3574, 3958, 4022, 4086],
+        &[2999, 3063, 3511, 4023, 4087],
+        &[3128, 3192, 3704],
+        &[3129, 3193, 3257, 3641, 3769],
+        &[3194, 3258, 3322, 3706, 3834],
+        &[3259, 3323, 3387, 3771, 3899],
+        &[3324, 3388, 3452, 3836, 3964],
+        &[3389, 3453, 3517, 3901, 4029],
+        &[3454, 3518, 3582, 3966, 4094],
+        &[3519, 3583, 4031],
+    ];
+
+    macro_rules! test_leaper_table {
+        ($test_name:ident, $attacks:path, $table:expr) => {
+            #[test]
+            fn $test_name() {
+                for (sq, expected) in $table.iter().enumerate() {
+                    let mut moves = Vec::new();
+                    push_moves(&mut moves, sq, $attacks(sq));
+                    let bits: Vec<u16> = moves.iter().map(|x| x.bits()).collect();
+                    assert_eq!(bits, *expected);
+                }
+            }
+        };
+    }
+
+    test_leaper_table!(knight_table, attacks::knight_attacks, KNIGHT_MOVES);
+    test_leaper_table!(king_table, attacks::king_attacks, KING_MOVES);
 }
@@ -0,0 +1,1486 @@
+// search.rs finds the best move in a position with an iterative deepening
+// principal variation search, followed by a quiescence search of captures,
+// checks and check evasions at the leaves. Results are shared through the
+// transposition table, and moves are ordered by the statistics kept in
+// movepick::History.
+//
+// Moves that are unlikely to matter are searched less deeply (late move
+// reductions) or not at all (null move, futility and late move pruning, and
+// razoring). Each of these can be switched off with a hidden UCI option.
+// Forcing moves are searched more deeply instead: checks, pawn pushes to the
+// seventh rank, and TT moves that are much better than any alternative.
+//
+// Each iteration of the iterative deepening starts with a narrow aspiration
+// window around the previous score, widened whenever the score falls outside
+// of it. The principal variation is collected in a triangular table as the
+// search returns, so it only ever holds moves that were actually searched.
+//
+// Several threads can search together (Lazy SMP): each runs its own iterative
+// deepening, and they help one another only through the shared transposition
+// table.
+
+use crate::evaluate;
+use crate::movelist::{MoveList, MAX_DROP_MOVES, MAX_MOVES};
+use crate::movepick::{self, History, MovePicker, PieceTo};
+use crate::nnue::{AccumulatorStack, Network};
+use crate::options::Options;
+use crate::position::{Move, Position};
+use crate::skill;
+use crate::tt::{Bound, Entry, TranspositionTable};
+use crate::variant::Outcome;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Scores are in centipawns from the point of view of the side to move. A mate
+// found 'n' plies from the root scores MATE - n.
+pub const MATE: i32 = 32000;
+pub const INFINITY: i32 = 32001;
+pub const MAX_PLY: usize = 128;
+
+// Scores beyond this bound are mate scores
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+// Stack size of the threads that search. Each ply of the search keeps several
+// move lists on the stack, which needs more than a thread gets by default.
+pub const STACK_SIZE: usize = 32 << 20;
+
+// How often (in nodes) the clock is checked when searching with a time limit.
+// Must be a power of two.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Aspiration windows are used from this depth, starting this wide on either
+// side of the previous score.
+const ASPIRATION_DEPTH: u32 = 4;
+const ASPIRATION_WINDOW: i32 = 25;
+
+// Helper threads skip some iterations, so that they do not all search the
+// same depth at the same time. Helper 'i' uses entry (i - 1) % 20, and skips
+// an iteration when (depth + phase) / size is odd.
+const SKIP_SIZE: [u32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+// Margins (in centipawns per ply of depth) and depth limits of the pruning
+const REVERSE_FUTILITY_DEPTH: u32 = 8;
+const REVERSE_FUTILITY_MARGIN: i32 = 80;
+const RAZORING_DEPTH: u32 = 3;
+const RAZORING_MARGIN_BASE: i32 = 300;
+const RAZORING_MARGIN: i32 = 200; // Per ply of depth squared
+const FUTILITY_DEPTH: u32 = 6;
+const FUTILITY_MARGIN: i32 = 100;
+const LATE_MOVE_PRUNING_DEPTH: u32 = 8;
+const NULL_MOVE_DEPTH: u32 = 3;
+
+// Singular extensions are tried from this depth, for TT moves searched at
+// least this close to the current depth.
+const SINGULAR_DEPTH: u32 = 8;
+const SINGULAR_TT_DEPTH_MARGIN: u32 = 3;
+
+// With dynamic contempt, each point of Elo the opponent is estimated to be
+// weaker by adds 1/CONTEMPT_ELO_DIVISOR of a centipawn of contempt
+const CONTEMPT_ELO_DIVISOR: i64 = 20;
+const MAX_CONTEMPT: i64 = 100;
+
+// History scores are divided by this to give a change in reduction
+const REDUCTION_HISTORY_DIVISOR: i32 = 8192;
+
+lazy_static! {
+    // Late move reductions, indexed by depth and by number of moves searched
+    static ref REDUCTIONS: [[u32; 64]; 64] = {
+        let mut reductions = [[0; 64]; 64];
+        for (depth, row) in reductions.iter_mut().enumerate().skip(1) {
+            for (moves, reduction) in row.iter_mut().enumerate().skip(1) {
+                *reduction = (0.75 + (depth as f64).ln() * (moves as f64).ln() / 2.25) as u32;
+            }
+        }
+        reductions
+    };
+}
+
+// The selective search techniques in use
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Pruning {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility: bool,
+    pub futility: bool,
+    pub late_move_pruning: bool,
+    pub razoring: bool,
+}
+
+impl Pruning {
+    // A plain alpha-beta search, without any pruning or reductions
+    pub const NONE: Pruning = Pruning {
+        null_move: false,
+        late_move_reductions: false,
+        reverse_futility: false,
+        futility: false,
+        late_move_pruning: false,
+        razoring: false,
+    };
+
+    pub fn from_options(options: &Options) -> Pruning {
+        Pruning {
+            null_move: options.get_bool("NullMovePruning"),
+            late_move_reductions: options.get_bool("LateMoveReductions"),
+            reverse_futility: options.get_bool("ReverseFutilityPruning"),
+            futility: options.get_bool("FutilityPruning"),
+            late_move_pruning: options.get_bool("LateMovePruning"),
+            razoring: options.get_bool("Razoring"),
+        }
+    }
+}
+
+impl Default for Pruning {
+    fn default() -> Self {
+        Pruning::from_options(&Options::new())
+    }
+}
+
+// How much the engine dislikes a draw, in centipawns. With dynamic contempt,
+// it grows the weaker the opponent given by 'UCI_Opponent' is than the engine.
+pub fn contempt_from_options(options: &Options) -> i32 {
+    let mut contempt = options.get_int("Contempt");
+    if options.get_bool("DynamicContempt") {
+        let own_elo = if options.get_bool("UCI_LimitStrength") {
+            options.get_int("UCI_Elo")
+        } else {
+            skill::MAX_ELO
+        };
+        // The format is: title, Elo, computer or human, and name
+        let opponent_elo = options.get_str("UCI_Opponent").split_whitespace().nth(1);
+        if let Some(elo) = opponent_elo.and_then(|x| x.parse::<i64>().ok()) {
+            contempt += (own_elo - elo) / CONTEMPT_ELO_DIVISOR;
+        }
+    }
+    contempt.clamp(-MAX_CONTEMPT, MAX_CONTEMPT) as i32
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    // Stop once the main thread has searched this many nodes. Helper threads
+    // are not counted, so that the limit stops a search at the same point
+    // however many threads there are.
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    // Stop once a mate in this many moves is found
+    pub mate: Option<u32>,
+    // Only these moves are searched at the root, or all of them when empty
+    pub search_moves: Vec<Move>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    // The principal variation, starting with the best move
+    pub pv: Vec<Move>,
+}
+
+pub struct Searcher<'a> {
+    limits: Limits,
+    tt: &'a TranspositionTable,
+    history: &'a mut History,
+    network: Option<&'a Network>,
+    pruning: Pruning,
+    contempt: i32,
+    accumulators: Option<AccumulatorStack>,
+    deadline: Option<Instant>,
+    // Set by another thread to abort the search
+    stop: Option<&'a AtomicBool>,
+    // Hashes of every position of the game, followed by those on the path
+    // from the root to the position currently being searched.
+    hashes: Vec<u64>,
+    // The move that led to each ply, for counter moves and continuation history
+    previous_moves: [Option<PieceTo>; MAX_PLY + 1],
+    // Plies of extension on the path to each ply. A line is extended by at
+    // most the depth of the current iteration.
+    extensions: [u32; MAX_PLY + 1],
+    // A move left out of the search at a ply, while testing for singularity
+    excluded: [Option<Move>; MAX_PLY + 1],
+    root_depth: u32,
+    // The number of principal variations searched at the root, each of them
+    // with the best moves of those before it left out.
+    multi_pv: usize,
+    root_excluded: Vec<Move>,
+    // The lines of the last completed iteration, best first
+    lines: Vec<SearchResult>,
+    // Triangular PV table: row 'ply' holds the principal variation from that
+    // ply onwards, of length pv_length[ply].
+    pv_table: Vec<[Move; MAX_PLY + 1]>,
+    pv_length: [usize; MAX_PLY + 1],
+    nodes: u64,
+    stopped: bool,
+    // 0 for the main thread of a search, and 1.. for its helper threads
+    thread_id: usize,
+    // Nodes searched by helper threads, which they add to as they go. The
+    // main thread includes them in the node counts it reports and limits.
+    helper_nodes: Arc<AtomicU64>,
+    flushed_nodes: u64,
+}
+
+impl<'a> Searcher<'a> {
+    pub fn new(
+        limits: Limits,
+        tt: &'a TranspositionTable,
+        history: &'a mut History,
+        network: Option<&'a Network>,
+    ) -> Searcher<'a> {
+        Searcher {
+            deadline: limits.movetime.map(|x| Instant::now() + x),
+            limits,
+            tt,
+            history,
+            network,
+            pruning: Pruning::default(),
+            contempt: 0,
+            accumulators: None,
+            stop: None,
+            hashes: vec![],
+            previous_moves: [None; MAX_PLY + 1],
+            extensions: [0; MAX_PLY + 1],
+            excluded: [None; MAX_PLY + 1],
+            root_depth: 0,
+            multi_pv: 1,
+            root_excluded: vec![],
+            lines: vec![],
+            pv_table: vec![[Move::NONE; MAX_PLY + 1]; MAX_PLY + 1],
+            pv_length: [0; MAX_PLY + 1],
+            nodes: 0,
+            stopped: false,
+            thread_id: 0,
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            flushed_nodes: 0,
+        }
+    }
+
+    pub fn with_pruning(mut self, pruning: Pruning) -> Searcher<'a> {
+        self.pruning = pruning;
+        self
+    }
+
+    pub fn with_stop(mut self, stop: &'a AtomicBool) -> Searcher<'a> {
+        self.stop = Some(stop);
+        self
+    }
+
+    pub fn with_contempt(mut self, contempt: i32) -> Searcher<'a> {
+        self.contempt = contempt;
+        self
+    }
+
+    pub fn with_multi_pv(mut self, multi_pv: usize) -> Searcher<'a> {
+        self.multi_pv = multi_pv.max(1);
+        self
+    }
+
+    // The best lines found by the last search, one for each of its principal
+    // variations, best first
+    pub fn lines(&self) -> &[SearchResult] {
+        &self.lines
+    }
+
+    // Search the position, reporting each completed iteration to 'report'.
+    // 'game' holds the hashes of the positions played before 'position' in the
+    // game, oldest first, and is used to detect repetitions.
+    pub fn search<F: FnMut(&SearchResult)>(
+        &mut self,
+        position: &Position,
+        game: &[u64],
+        mut report: F,
+    ) -> SearchResult {
+        self.accumulators = self.network.map(|x| AccumulatorStack::new(x, position));
+        self.hashes = game.to_vec();
+        self.hashes.push(position.hash());
+        self.history.clear_killers();
+        self.lines.clear();
+
+        // Moves to search that are not legal here are ignored
+        let mut root_moves = position.moves();
+        self.limits.search_moves.retain(|x| root_moves.contains(x));
+        if !self.limits.search_moves.is_empty() {
+            root_moves = self.limits.search_moves.iter().copied().collect();
+        }
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: root_moves.first().copied().into_iter().collect(),
+        };
+        if root_moves.is_empty() {
+            return result;
+        }
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as u32 - 1);
+
+        for depth in 1..=max_depth {
+            if self.thread_id > 0 {
+                let i = (self.thread_id - 1) % SKIP_SIZE.len();
+                if (depth + SKIP_PHASE[i]) / SKIP_SIZE[i] % 2 == 1 {
+                    continue;
+                }
+            }
+            self.root_depth = depth;
+            let mut lines: Vec<SearchResult> = vec![];
+            for i in 0..self.multi_pv.min(root_moves.len()) {
+                let previous = self.lines.get(i).map_or(result.score, |x| x.score);
+                let score = self.aspiration(position, depth, previous);
+                if self.stopped {
+                    break;
+                }
+                let pv = self.pv_table[0][..self.pv_length[0]].to_vec();
+                self.root_excluded.extend(pv.first());
+                lines.push(SearchResult {
+                    best_move: pv.first().copied(),
+                    score,
+                    depth,
+                    nodes: self.total_nodes(),
+                    pv,
+                });
+            }
+            self.root_excluded.clear();
+            if self.stopped {
+                break;
+            }
+
+            lines.sort_by_key(|x| -x.score);
+            result = lines[0].clone();
+            let score = result.score;
+            self.lines = lines;
+            report(&result);
+
+            // When asked for a mate in n moves, search until one is found in
+            // at most 2n - 1 plies.
+            // Otherwise there is nothing left to learn once a forced mate is
+            // found, or when there is a single legal move.
+            let done = match self.limits.mate {
+                Some(moves) => score > MATE - 2 * moves as i32,
+                None => score.abs() >= MATE_BOUND || root_moves.len() <= 1,
+            };
+            if done {
+                break;
+            }
+        }
+
+        self.flush_nodes();
+        result.nodes = self.total_nodes();
+        result
+    }
+
+    // Search with this searcher as the main thread, helped by one more thread
+    // for each of the history tables in 'helpers'. Helpers are stopped once
+    // the main thread finishes, and the best move is then decided by a vote
+    // of all threads, weighted by the depth and score each one reached.
+    pub fn search_with_helpers<F: FnMut(&SearchResult)>(
+        &mut self,
+        helpers: &mut [History],
+        position: &Position,
+        game: &[u64],
+        report: F,
+    ) -> SearchResult {
+        let stop_helpers = AtomicBool::new(false);
+        let limits = Limits {
+            depth: self.limits.depth,
+            search_moves: self.limits.search_moves.clone(),
+            ..Limits::default()
+        };
+        let (tt, network, pruning, contempt) = (self.tt, self.network, self.pruning, self.contempt);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .enumerate()
+                .map(|(i, history)| {
+                    let mut helper = Searcher::new(limits.clone(), tt, history, network)
+                        .with_pruning(pruning)
+                        .with_contempt(contempt)
+                        .with_stop(&stop_helpers);
+                    helper.thread_id = i + 1;
+                    helper.helper_nodes = Arc::clone(&self.helper_nodes);
+                    thread::Builder::new()
+                        .stack_size(STACK_SIZE)
+                        .spawn_scoped(scope, move || helper.search(position, game, |_| ()))
+                        .unwrap()
+                })
+                .collect();
+
+            let mut results = vec![self.search(position, game, report)];
+            stop_helpers.store(true, Ordering::Relaxed);
+            results.extend(handles.into_iter().map(|x| x.join().unwrap()));
+
+            let mut result = vote(&results);
+            result.nodes = self.total_nodes();
+            result
+        })
+    }
+
+    // Search the root with a window around the score of the previous
+    // iteration, widening the side the score falls out of until it fits
+    fn aspiration(&mut self, position: &Position, depth: u32, previous: i32) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = if depth >= ASPIRATION_DEPTH && previous.abs() < MATE_BOUND {
+            (previous - delta, previous + delta)
+        } else {
+            (-INFINITY, INFINITY)
+        };
+
+        loop {
+            // Only drop variants pay for move lists large enough for drops
+            let score = if position.variant().has_drops() {
+                self.alpha_beta::<MAX_DROP_MOVES>(position, depth, 0, alpha, beta)
+            } else {
+                self.alpha_beta::<MAX_MOVES>(position, depth, 0, alpha, beta)
+            };
+            if self.stopped {
+                return score;
+            }
+
+            if score <= alpha {
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta += delta / 2;
+        }
+    }
+
+    // N is the capacity of the move lists, see movelist.rs
+    fn alpha_beta<const N: usize>(
+        &mut self,
+        position: &Position,
+        depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.pv_length[ply] = 0;
+        if self.should_stop() {
+            return 0;
+        }
+        if ply > 0 && self.is_draw(position) {
+            return self.draw_score(ply);
+        }
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence::<N>(position, ply, alpha, beta, true);
+        }
+        self.nodes += 1;
+
+        // Null window searches only need to know whether a move beats alpha.
+        // Everywhere else the exact score is needed.
+        let pv_node = beta - alpha > 1;
+        // Whether this is a search for alternatives to a possibly singular move
+        let excluded = self.excluded[ply];
+
+        let hash = *self.hashes.last().unwrap();
+        let entry = self.tt.probe(hash, ply);
+        let usable = |x: &Entry| !pv_node && excluded.is_none() && x.depth >= depth;
+        if let Some(entry) = entry.filter(usable) {
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+            };
+            if cutoff {
+                return entry.score;
+            }
+        }
+
+        let mut moves = MoveList::<N>::new();
+        position.generate_moves(&mut moves);
+        if moves.is_empty() {
+            return self.outcome_score(position.no_moves_outcome(), ply);
+        }
+        if ply == 0 {
+            if !self.limits.search_moves.is_empty() {
+                moves.retain(|x| self.limits.search_moves.contains(x));
+            }
+            moves.retain(|x| !self.root_excluded.contains(x));
+        }
+
+        let in_check = position.is_in_check();
+        let eval = if in_check {
+            -INFINITY
+        } else {
+            self.evaluate(position)
+        };
+
+        if !pv_node && !in_check && excluded.is_none() {
+            // Reverse futility pruning: the position is so far above beta
+            // that no reply is expected to bring it back down
+            if self.pruning.reverse_futility
+                && depth <= REVERSE_FUTILITY_DEPTH
+                && beta.abs() < MATE_BOUND
+                && eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= beta
+            {
+                return eval;
+            }
+
+            // Razoring: the position is so far below alpha that only captures
+            // could save it, so check that with a quiescence search
+            if self.pruning.razoring
+                && depth <= RAZORING_DEPTH
+                && eval + RAZORING_MARGIN_BASE + RAZORING_MARGIN * (depth * depth) as i32 <= alpha
+            {
+                let score = self.quiescence::<N>(position, ply, alpha, alpha + 1, false);
+                if score <= alpha {
+                    return score;
+                }
+            }
+
+            // Null move pruning: if passing still beats beta, a real move will
+            // too. Not tried after another null move, with only pawns left
+            // where zugzwang makes passing better than any move, or where
+            // passing would dodge a forced capture.
+            if self.pruning.null_move
+                && depth >= NULL_MOVE_DEPTH
+                && eval >= beta
+                && self.previous_moves[ply].is_some()
+                && position.has_non_pawn_material()
+                && !position.variant().forces_captures()
+            {
+                let reduction = 3 + depth / 4;
+                self.previous_moves[ply + 1] = None;
+                self.extensions[ply + 1] = self.extensions[ply];
+                let next = self.make_null_move(position);
+                let score = -self.alpha_beta::<N>(
+                    &next,
+                    depth.saturating_sub(reduction + 1),
+                    ply + 1,
+                    -beta,
+                    -beta + 1,
+                );
+                self.unmake_move();
+
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    // Mates found after passing are not proven
+                    return score.min(MATE_BOUND - 1);
+                }
+            }
+        }
+
+        // Futility pruning: quiet moves other than checks are not expected to
+        // raise alpha
+        let futile = self.pruning.futility
+            && !pv_node
+            && !in_check
+            && depth <= FUTILITY_DEPTH
+            && eval + FUTILITY_MARGIN * depth as i32 <= alpha;
+        // Late move pruning: after enough quiet moves, skip the rest
+        let late_move_limit = if self.pruning.late_move_pruning
+            && !pv_node
+            && !in_check
+            && depth <= LATE_MOVE_PRUNING_DEPTH
+        {
+            3 + (depth * depth) as usize
+        } else {
+            usize::MAX
+        };
+
+        let original_alpha = alpha;
+        let previous = self.previous_moves[ply];
+        let tt_move = entry.and_then(|x| x.best_move);
+        let mut picker = MovePicker::new(position, moves, tt_move, self.history, ply, previous);
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut moves_searched = 0;
+        let mut quiets_tried = vec![];
+
+        while let Some(mov) = picker.next(position, self.history, previous) {
+            if Some(mov) == excluded {
+                continue;
+            }
+            let quiet = !position.is_tactical(mov);
+            if quiet && best_score > -MATE_BOUND {
+                if quiets_tried.len() >= late_move_limit {
+                    picker.skip_quiets();
+                    continue;
+                }
+                if futile && !position.gives_check(mov) {
+                    continue;
+                }
+            }
+
+            // Singular extension: if every alternative to the TT move fails
+            // well below its score, the TT move is forced and worth extending
+            let can_extend = self.extensions[ply] < self.root_depth;
+            let mut extension = 0;
+            if let Some(entry) = entry.filter(|x| {
+                can_extend
+                    && ply > 0
+                    && excluded.is_none()
+                    && depth >= SINGULAR_DEPTH
+                    && x.best_move == Some(mov)
+                    && x.depth + SINGULAR_TT_DEPTH_MARGIN >= depth
+                    && x.bound != Bound::Upper
+                    && x.score.abs() < MATE_BOUND
+            }) {
+                let singular_beta = entry.score - 2 * depth as i32;
+                self.excluded[ply] = Some(mov);
+                let score = self.alpha_beta::<N>(
+                    position,
+                    (depth - 1) / 2,
+                    ply,
+                    singular_beta - 1,
+                    singular_beta,
+                );
+                self.excluded[ply] = None;
+                if self.stopped {
+                    return 0;
+                }
+                if score < singular_beta {
+                    extension = 1;
+                }
+            }
+
+            self.previous_moves[ply + 1] = Some(movepick::piece_to(position, mov));
+            let next = self.make_move(position, mov);
+            if can_extend && (next.is_in_check() || position.is_push_to_seventh(mov)) {
+                extension = 1;
+            }
+            self.extensions[ply + 1] = self.extensions[ply] + extension;
+            let new_depth = depth - 1 + extension;
+
+            let score = if moves_searched == 0 {
+                -self.alpha_beta::<N>(&next, new_depth, ply + 1, -beta, -alpha)
+            } else {
+                // Late move reductions: moves ordered late are searched less
+                // deeply, unless they turn out to beat alpha
+                let mut reduction = 0;
+                if self.pruning.late_move_reductions
+                    && depth >= 3
+                    && moves_searched >= 2
+                    && quiet
+                    && extension == 0
+                    && !in_check
+                    && !next.is_in_check()
+                {
+                    let mut r = REDUCTIONS[depth.min(63) as usize][moves_searched.min(63)] as i32;
+                    r -= pv_node as i32;
+                    r -= self.history.quiet_score(position, mov, previous)
+                        / REDUCTION_HISTORY_DIVISOR;
+                    reduction = r.clamp(0, new_depth as i32 - 1) as u32;
+                }
+
+                // Principal variation search: prove that the move is worse
+                // than the best so far with a null window, and only search it
+                // with the full window if that fails
+                let mut score = -self.alpha_beta::<N>(
+                    &next,
+                    new_depth - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                );
+                if score > alpha && reduction > 0 {
+                    score = -self.alpha_beta::<N>(&next, new_depth, ply + 1, -alpha - 1, -alpha);
+                }
+                if score > alpha && score < beta {
+                    score = -self.alpha_beta::<N>(&next, new_depth, ply + 1, -beta, -alpha);
+                }
+                score
+            };
+            self.unmake_move();
+            moves_searched += 1;
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mov);
+            }
+            if score > alpha {
+                alpha = score;
+                if pv_node {
+                    self.update_pv(ply, mov);
+                }
+            }
+            if score >= beta {
+                self.history
+                    .update(position, mov, &quiets_tried, depth, ply, previous);
+                break;
+            }
+            if quiet {
+                quiets_tried.push(mov);
+            }
+        }
+
+        // The result of a singularity test is not a score for this position,
+        // so it is kept out of the table
+        if excluded.is_some() {
+            return best_score.max(alpha);
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        let entry = Entry {
+            best_move,
+            score: best_score,
+            depth,
+            bound,
+        };
+        self.tt.store(hash, ply, entry);
+        best_score
+    }
+
+    // Search captures and promotions only, until the position is quiet.
+    // Captures that lose material by static exchange are not searched. In
+    // check there is no standing pat and every evasion is searched instead.
+    // With 'checks', quiet checks are tried too, so that a mate threat just
+    // past the horizon is not mistaken for a quiet position.
+    fn quiescence<const N: usize>(
+        &mut self,
+        position: &Position,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        checks: bool,
+    ) -> i32 {
+        self.pv_length[ply] = 0;
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        if let Some(outcome) = position.variant_outcome() {
+            return self.outcome_score(outcome, ply);
+        }
+
+        let mut moves = MoveList::<N>::new();
+        let mut quiet_checks = vec![];
+        let mut picker = if position.is_in_check() {
+            position.generate_moves(&mut moves);
+            if moves.is_empty() {
+                return self.outcome_score(position.no_moves_outcome(), ply);
+            }
+            if ply >= MAX_PLY {
+                return self.evaluate(position);
+            }
+            MovePicker::new(position, moves, None, self.history, ply, None)
+        } else {
+            let stand_pat = self.evaluate(position);
+            if stand_pat >= beta || ply >= MAX_PLY {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+
+            if checks {
+                position.generate_moves(&mut moves);
+                quiet_checks = (moves.iter().copied())
+                    .filter(|&x| !position.is_tactical(x) && position.gives_check(x))
+                    .collect();
+            } else {
+                position.generate_tactical_moves(&mut moves);
+            }
+            MovePicker::tactical(position, moves)
+        };
+
+        while let Some(mov) = picker
+            .next(position, self.history, None)
+            .or_else(|| quiet_checks.pop())
+        {
+            let next = self.make_move(position, mov);
+            let score = -self.quiescence::<N>(&next, ply + 1, -beta, -alpha, false);
+            self.unmake_move();
+
+            if score >= beta {
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, mov);
+            }
+        }
+        alpha
+    }
+
+    fn make_move(&mut self, position: &Position, mov: Move) -> Position {
+        let next = match (self.network, &mut self.accumulators) {
+            (Some(network), Some(accumulators)) => accumulators.play_move(network, position, mov),
+            _ => {
+                let mut next = *position;
+                next.play_move(mov);
+                next
+            }
+        };
+        self.hashes.push(next.hash());
+        next
+    }
+
+    // The move raised alpha: it and the child's PV are the PV at this ply
+    fn update_pv(&mut self, ply: usize, mov: Move) {
+        let length = self.pv_length[ply + 1];
+        let (rows, child) = self.pv_table.split_at_mut(ply + 1);
+        rows[ply][0] = mov;
+        rows[ply][1..=length].copy_from_slice(&child[0][..length]);
+        self.pv_length[ply] = length + 1;
+    }
+
+    fn make_null_move(&mut self, position: &Position) -> Position {
+        let mut next = *position;
+        next.play_null_move();
+        if let Some(accumulators) = &mut self.accumulators {
+            accumulators.play_null_move();
+        }
+        self.hashes.push(next.hash());
+        next
+    }
+
+    fn unmake_move(&mut self) {
+        self.hashes.pop();
+        if let Some(accumulators) = &mut self.accumulators {
+            accumulators.unmake_move();
+        }
+    }
+
+    // Evaluate the position from the side to move's point of view
+    fn evaluate(&self, position: &Position) -> i32 {
+        if let (Some(network), Some(accumulators)) = (self.network, &self.accumulators) {
+            return network.evaluate(accumulators.current(), position.is_white_move);
+        }
+
+        let score = evaluate::evaluate(position, &mut evaluate::NoTrace);
+        if position.is_white_move {
+            score
+        } else {
+            -score
+        }
+    }
+
+    // Draws by the fifty move rule, repetition or insufficient material. A
+    // single repetition is scored as a draw, since the side that could avoid
+    // it would already have done so.
+    fn is_draw(&self, position: &Position) -> bool {
+        if position.hlf_clock >= 100 || position.is_insufficient_material() {
+            return true;
+        }
+
+        let hash = *self.hashes.last().unwrap();
+        self.hashes
+            .iter()
+            .rev()
+            .take(position.hlf_clock as usize + 1)
+            .skip(2)
+            .step_by(2)
+            .any(|&x| x == hash)
+    }
+
+    // A draw is worth -contempt to the side to move at the root, and
+    // +contempt to its opponent
+    fn draw_score(&self, ply: usize) -> i32 {
+        if ply.is_multiple_of(2) {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    // The score of a finished game, preferring the quickest win and the
+    // slowest loss
+    fn outcome_score(&self, outcome: Outcome, ply: usize) -> i32 {
+        match outcome {
+            Outcome::Win => MATE - ply as i32,
+            Outcome::Loss => -MATE + ply as i32,
+            Outcome::Draw => self.draw_score(ply),
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if let Some(stop) = self.stop {
+            self.stopped |= stop.load(Ordering::Relaxed);
+        }
+        if let Some(nodes) = self.limits.nodes {
+            self.stopped |= self.nodes >= nodes;
+        }
+        if self.nodes & (TIME_CHECK_INTERVAL - 1) == 0 {
+            self.flush_nodes();
+            if let Some(deadline) = self.deadline {
+                self.stopped |= Instant::now() >= deadline;
+            }
+        }
+        self.stopped
+    }
+
+    // Add the nodes searched by a helper thread to the shared count
+    fn flush_nodes(&mut self) {
+        if self.thread_id > 0 {
+            let nodes = self.nodes - self.flushed_nodes;
+            self.helper_nodes.fetch_add(nodes, Ordering::Relaxed);
+            self.flushed_nodes = self.nodes;
+        }
+    }
+
+    // Nodes searched by this thread, and by its helpers for the main thread
+    fn total_nodes(&self) -> u64 {
+        if self.thread_id == 0 {
+            self.nodes + self.helper_nodes.load(Ordering::Relaxed)
+        } else {
+            self.nodes
+        }
+    }
+}
+
+// Choose between the results of the threads of a search. Each thread votes
+// for its best move, with a weight growing with its depth and with how much
+// better its score is than the worst one. Ties go to the earlier thread.
+fn vote(results: &[SearchResult]) -> SearchResult {
+    let finished: Vec<&SearchResult> = results.iter().filter(|x| x.depth > 0).collect();
+    let min_score = finished.iter().map(|x| x.score).min().unwrap_or(0);
+    let votes = |mov: Option<Move>| -> i64 {
+        finished
+            .iter()
+            .filter(|x| x.best_move == mov)
+            .map(|x| (x.score - min_score + 20) as i64 * x.depth as i64)
+            .sum()
+    };
+
+    let mut best = &results[0];
+    for &result in &finished {
+        if best.depth == 0 || votes(result.best_move) > votes(best.best_move) {
+            best = result;
+        }
+    }
+    best.clone()
+}
+
+// Search a position with the given limits, using the hand-crafted evaluation
+// and a small transposition table of its own.
+pub fn search(position: &Position, game: &[u64], limits: Limits) -> SearchResult {
+    let tt = TranspositionTable::new(1);
+    let mut history = History::new();
+    Searcher::new(limits, &tt, &mut history, None).search(position, game, |_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::{move_to_str, str_to_move, MoveKind};
+    use crate::types::PieceType;
+    use crate::variant::Variant;
+
+    // Search the FEN position to a fixed depth and check the best move found
+    macro_rules! test_best_move {
+        ($test_name:ident, $fen:literal, $depth:literal, $expected:literal) => {
+            #[test]
+            fn $test_name() {
+                let limits = Limits {
+                    depth: Some($depth),
+                    ..Limits::default()
+                };
+                let result = search(&Position::from($fen), &[], limits);
+                assert_eq!(move_to_str(result.best_move.unwrap()), $expected);
+            }
+        };
+    }
+
+    test_best_move!(
+        mate_in_one_back_rank,
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+        2,
+        "a1a8"
+    );
+    test_best_move!(
+        mate_in_one_black,
+        "r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1",
+        2,
+        "a8a1"
+    );
+    test_best_move!(
+        win_hanging_queen,
+        "rnb1kbnr/pppp1ppp/8/4p1q1/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 0 3",
+        3,
+        "c1g5"
+    );
+    test_best_move!(promote_to_queen, "8/P6k/8/8/8/8/8/K7 w - - 0 1", 3, "a7a8q");
+
+    // The check extension lets a depth 2 search see the mate on the third ply.
+    // Pruning is off, since razoring would give up on the queen sacrifice.
+    #[test]
+    fn check_extension_finds_mate() {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = Limits {
+            depth: Some(2),
+            ..Limits::default()
+        };
+        let position = Position::from("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1");
+        let result = Searcher::new(limits, &tt, &mut history, None)
+            .with_pruning(Pruning::NONE)
+            .search(&position, &[], |_| ());
+        assert_eq!(move_to_str(result.best_move.unwrap()), "d5d8");
+        assert_eq!(result.score, MATE - 3);
+    }
+
+    #[test]
+    fn pv_is_legal() {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = Limits {
+            depth: Some(8),
+            ..Limits::default()
+        };
+        let root = Position::from(ITALIAN);
+        let mut results = vec![];
+        Searcher::new(limits, &tt, &mut history, None)
+            .search(&root, &[], |x| results.push(x.clone()));
+
+        assert_eq!(results.len(), 8);
+        for result in results {
+            assert_eq!(result.pv.first().copied(), result.best_move);
+            let mut position = root;
+            for &mov in &result.pv {
+                assert!(position.moves().contains(&mov));
+                position.play_move(mov);
+            }
+        }
+    }
+
+    #[test]
+    fn pv_ends_in_mate() {
+        let limits = Limits {
+            depth: Some(4),
+            ..Limits::default()
+        };
+        let mut position =
+            Position::from("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1");
+        let result = search(&position, &[], limits);
+        let pv: Vec<String> = result.pv.iter().map(|&x| move_to_str(x)).collect();
+        assert_eq!(pv, ["d5d8", "e7d8", "e1e8"]);
+
+        for mov in result.pv {
+            position.play_move(mov);
+        }
+        assert!(position.is_in_check() && position.moves().is_empty());
+    }
+
+    #[test]
+    fn mate_score() {
+        let limits = Limits {
+            depth: Some(4),
+            ..Limits::default()
+        };
+        let result = search(
+            &Position::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"),
+            &[],
+            limits,
+        );
+        assert_eq!(result.score, MATE - 1);
+        assert!(result.depth < 4);
+    }
+
+    // Reaching the hill wins King of the Hill, and is found like a mate
+    #[test]
+    fn king_of_the_hill_win() {
+        let limits = Limits {
+            depth: Some(4),
+            ..Limits::default()
+        };
+        let fen = "4k3/8/8/8/8/4K3/8/8 w - - 0 1";
+        let position = Position::from_variant(fen, Variant::KingOfTheHill);
+        let result = search(&position, &[], limits);
+        assert_eq!(result.score, MATE - 1);
+        assert!(matches!(
+            move_to_str(result.best_move.unwrap()).as_str(),
+            "e3d4" | "e3e4"
+        ));
+    }
+
+    // In antichess the capture is forced, and leaves the other side without
+    // pieces, which wins for it
+    #[test]
+    fn antichess_forced_capture_loses() {
+        let fen = "8/8/8/8/8/8/8/r1B5 b - - 0 1";
+        let position = Position::from_variant(fen, Variant::Antichess);
+        let result = search(&position, &[], Limits::default());
+        assert_eq!(move_to_str(result.best_move.unwrap()), "a1c1");
+        assert_eq!(result.score, -MATE + 1);
+    }
+
+    // A rook in hand mates on the back rank
+    #[test]
+    fn crazyhouse_drop_mate() {
+        let fen = "6k1/5ppp/8/8/8/8/8/6K1[R] w - - 0 1";
+        let position = Position::from_variant(fen, Variant::Crazyhouse);
+        let result = search(&position, &[], Limits::default());
+        let best_move = result.best_move.unwrap();
+        assert_eq!(best_move.dropped(), Some(PieceType::Rook));
+        assert_eq!(best_move.to().rank().to_char(), '8');
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    // A side in check cannot stand pat on its material: the knight check
+    // forces the king to move, and the queen is lost
+    #[test]
+    fn quiescence_searches_evasions() {
+        let position = Position::from("3q4/6k1/4N3/8/8/8/8/K7 b - - 0 1");
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let mut searcher = Searcher::new(Limits::default(), &tt, &mut history, None);
+        let score = searcher.quiescence::<MAX_MOVES>(&position, 0, -INFINITY, INFINITY, false);
+        assert!(score < 0, "{}", score);
+        assert!(searcher.evaluate(&position) > 0);
+
+        let mated = Position::from("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(
+            searcher.quiescence::<MAX_MOVES>(&mated, 1, -INFINITY, INFINITY, false),
+            -MATE + 1
+        );
+    }
+
+    #[test]
+    fn no_legal_moves() {
+        let position = Position::from("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        let result = search(&position, &[], Limits::default());
+        assert_eq!(result.best_move, None);
+    }
+
+    #[test]
+    fn search_moves_restrict_the_root() {
+        let position = Position::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let king_moves = vec![
+            str_to_move("g1f1", position).unwrap(),
+            str_to_move("g1h1", position).unwrap(),
+        ];
+        let mut search_moves = king_moves.clone();
+        // Illegal moves are ignored
+        let (b1, a2) = ("b1".parse().unwrap(), "a2".parse().unwrap());
+        search_moves.push(Move::new(b1, a2, MoveKind::Normal));
+        let limits = Limits {
+            depth: Some(4),
+            search_moves,
+            ..Limits::default()
+        };
+        let result = search(&position, &[], limits);
+        assert!(king_moves.contains(&result.best_move.unwrap()));
+        assert_eq!(result.depth, 4);
+        assert!(result.score < MATE_BOUND);
+    }
+
+    #[test]
+    fn mate_limit_stops_when_found() {
+        let position = Position::from("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1");
+        let limits = Limits {
+            mate: Some(2),
+            ..Limits::default()
+        };
+        let result = search(&position, &[], limits);
+        assert_eq!(result.score, MATE - 3);
+        assert_eq!(
+            result.best_move,
+            Some(str_to_move("d5d8", position).unwrap())
+        );
+    }
+
+    #[test]
+    fn node_limit_is_deterministic() {
+        let limits = Limits {
+            nodes: Some(5000),
+            ..Limits::default()
+        };
+        let first = search(&Position::new(), &[], limits.clone());
+        let second = search(&Position::new(), &[], limits);
+        assert_eq!(first, second);
+        assert!(first.nodes >= 5000 && first.nodes < 5100);
+        assert!(first.best_move.is_some());
+    }
+
+    #[test]
+    fn repetition_is_a_draw() {
+        // Shuffling the queen and king back and forth repeats the position
+        let mut position = Position::from("6k1/5ppp/8/8/8/8/r7/5QK1 w - - 0 1");
+        let mut history = vec![];
+        for mov in ["f1c4", "g8h8", "c4f1", "h8g8"] {
+            history.push(position.hash());
+            position.play_move(str_to_move(mov, position).unwrap());
+        }
+        let tt = TranspositionTable::new(1);
+        let mut move_history = History::new();
+        let mut searcher = Searcher::new(Limits::default(), &tt, &mut move_history, None);
+        searcher.hashes = history;
+        searcher.hashes.push(position.hash());
+        assert!(searcher.is_draw(&position));
+    }
+
+    #[test]
+    fn contempt_shifts_draw_score() {
+        // Every move leads to a draw by insufficient material
+        let position = Position::from("8/8/4k3/8/8/3K4/8/8 w - - 0 1");
+        for contempt in [0, 30, -30] {
+            let tt = TranspositionTable::new(1);
+            let mut history = History::new();
+            let limits = Limits {
+                depth: Some(3),
+                ..Limits::default()
+            };
+            let result = Searcher::new(limits, &tt, &mut history, None)
+                .with_contempt(contempt)
+                .search(&position, &[], |_| ());
+            assert_eq!(result.score, -contempt);
+        }
+    }
+
+    #[test]
+    fn contempt_from_opponent() {
+        let mut options = Options::new();
+        assert_eq!(contempt_from_options(&options), 0);
+        options.set("Contempt", "10").unwrap();
+        options
+            .set("UCI_Opponent", "none 2000 computer Some Engine")
+            .unwrap();
+        assert_eq!(contempt_from_options(&options), 10);
+
+        options.set("DynamicContempt", "true").unwrap();
+        assert_eq!(contempt_from_options(&options), 50);
+        options
+            .set("UCI_Opponent", "GM 3300 human Someone")
+            .unwrap();
+        assert_eq!(contempt_from_options(&options), -15);
+        options
+            .set("UCI_Opponent", "none none computer Unrated")
+            .unwrap();
+        assert_eq!(contempt_from_options(&options), 10);
+        options
+            .set("UCI_Opponent", "none 100 computer Random Mover")
+            .unwrap();
+        assert_eq!(contempt_from_options(&options), MAX_CONTEMPT as i32);
+    }
+
+    #[test]
+    fn transposition_table_is_reused() {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = Limits {
+            depth: Some(4),
+            ..Limits::default()
+        };
+        let position = Position::from(ITALIAN);
+
+        let first =
+            Searcher::new(limits.clone(), &tt, &mut history, None).search(&position, &[], |_| ());
+        let second = Searcher::new(limits, &tt, &mut history, None).search(&position, &[], |_| ());
+        assert!(second.nodes < first.nodes);
+        assert_eq!(second.best_move, first.best_move);
+        assert!(tt.probe(position.hash(), 0).is_some());
+    }
+
+    const ITALIAN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+    fn nodes_at_depth(pruning: Pruning, depth: u32) -> u64 {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        };
+        Searcher::new(limits, &tt, &mut history, None)
+            .with_pruning(pruning)
+            .search(&Position::from(ITALIAN), &[], |_| ())
+            .nodes
+    }
+
+    #[test]
+    fn pruning_reduces_nodes() {
+        assert!(nodes_at_depth(Pruning::default(), 5) * 4 < nodes_at_depth(Pruning::NONE, 5));
+    }
+
+    // Switching off a single technique should change the search
+    macro_rules! test_pruning_switch {
+        ($test_name:ident, $field:ident) => {
+            #[test]
+            fn $test_name() {
+                let without = Pruning {
+                    $field: false,
+                    ..Pruning::default()
+                };
+                assert_ne!(
+                    nodes_at_depth(without, 6),
+                    nodes_at_depth(Pruning::default(), 6)
+                );
+            }
+        };
+    }
+
+    test_pruning_switch!(switch_null_move, null_move);
+    test_pruning_switch!(switch_late_move_reductions, late_move_reductions);
+    test_pruning_switch!(switch_reverse_futility, reverse_futility);
+    test_pruning_switch!(switch_futility, futility);
+    test_pruning_switch!(switch_late_move_pruning, late_move_pruning);
+    test_pruning_switch!(switch_razoring, razoring);
+
+    #[test]
+    fn pruning_from_options() {
+        let mut options = Options::new();
+        assert_eq!(Pruning::from_options(&options), Pruning::default());
+        options.set("LateMoveReductions", "false").unwrap();
+        let pruning = Pruning::from_options(&options);
+        assert!(!pruning.late_move_reductions);
+        assert!(pruning.null_move && pruning.razoring);
+    }
+
+    #[test]
+    fn stop_flag_aborts_search() {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let stop = AtomicBool::new(true);
+        let result = Searcher::new(Limits::default(), &tt, &mut history, None)
+            .with_stop(&stop)
+            .search(&Position::new(), &[], |_| ());
+        assert_eq!(result.depth, 0);
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn multi_pv_lines() {
+        let position = Position::from(ITALIAN);
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = Limits {
+            depth: Some(5),
+            ..Limits::default()
+        };
+        let mut searcher = Searcher::new(limits, &tt, &mut history, None).with_multi_pv(3);
+        let result = searcher.search(&position, &[], |_| ());
+
+        let lines = searcher.lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].pv, result.pv);
+        assert!(lines.windows(2).all(|x| x[0].score >= x[1].score));
+        assert!(lines.iter().all(|x| x.depth == 5));
+        assert_ne!(lines[0].best_move, lines[1].best_move);
+        assert_ne!(lines[0].best_move, lines[2].best_move);
+        assert_ne!(lines[1].best_move, lines[2].best_move);
+    }
+
+    #[test]
+    fn helpers_find_mate() {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let mut helpers: Vec<History> = (0..3).map(|_| History::new()).collect();
+        let limits = Limits {
+            depth: Some(4),
+            ..Limits::default()
+        };
+        let position = Position::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let result = Searcher::new(limits, &tt, &mut history, None).search_with_helpers(
+            &mut helpers,
+            &position,
+            &[],
+            |_| (),
+        );
+        assert_eq!(move_to_str(result.best_move.unwrap()), "a1a8");
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn helper_nodes_are_counted() {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let mut helpers: Vec<History> = (0..3).map(|_| History::new()).collect();
+        let limits = Limits {
+            nodes: Some(50_000),
+            ..Limits::default()
+        };
+        let mut reported = vec![];
+        let result = Searcher::new(limits, &tt, &mut history, None).search_with_helpers(
+            &mut helpers,
+            &Position::from(ITALIAN),
+            &[],
+            |x| reported.push(x.nodes),
+        );
+        assert!(reported.windows(2).all(|x| x[0] <= x[1]));
+        assert!(result.nodes >= 50_000 && result.nodes >= *reported.last().unwrap());
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn node_limit_counts_the_main_thread() {
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let mut helpers: Vec<History> = (0..3).map(|_| History::new()).collect();
+        let limits = Limits {
+            nodes: Some(20_000),
+            ..Limits::default()
+        };
+        let mut searcher = Searcher::new(limits, &tt, &mut history, None);
+        let result =
+            searcher.search_with_helpers(&mut helpers, &Position::from(ITALIAN), &[], |_| ());
+        assert_eq!(searcher.nodes, 20_000);
+        assert!(result.nodes >= 20_000);
+    }
+
+    #[test]
+    fn no_helpers_is_a_single_thread_search() {
+        let limits = Limits {
+            depth: Some(5),
+            ..Limits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let result = Searcher::new(limits.clone(), &tt, &mut history, None).search_with_helpers(
+            &mut [],
+            &Position::from(ITALIAN),
+            &[],
+            |_| (),
+        );
+        assert_eq!(result, search(&Position::from(ITALIAN), &[], limits));
+    }
+
+    #[test]
+    fn vote_weighs_depth_and_score() {
+        let position = Position::new();
+        let result = |mov: &str, score, depth| SearchResult {
+            best_move: Some(str_to_move(mov, position).unwrap()),
+            score,
+            depth,
+            nodes: 0,
+            pv: vec![str_to_move(mov, position).unwrap()],
+        };
+
+        // Two helpers agreeing outvote the main thread
+        let results = [
+            result("e2e4", 30, 8),
+            result("d2d4", 25, 8),
+            result("d2d4", 25, 7),
+        ];
+        assert_eq!(vote(&results), results[1]);
+
+        // A thread that did not finish an iteration has no vote
+        let results = [result("e2e4", 30, 8), result("d2d4", 500, 0)];
+        assert_eq!(vote(&results), results[0]);
+
+        // Ties go to the main thread
+        let results = [result("e2e4", 30, 8), result("d2d4", 30, 8)];
+        assert_eq!(vote(&results), results[0]);
+    }
+
+    #[test]
+    fn search_with_network() {
+        let network = Network::random(11);
+        let limits = Limits {
+            depth: Some(3),
+            ..Limits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let mut searcher = Searcher::new(limits, &tt, &mut history, Some(&network));
+        let result = searcher.search(&Position::new(), &[], |_| ());
+        assert!(result.best_move.is_some());
+        assert!(result.score.abs() < MATE_BOUND);
+    }
+}
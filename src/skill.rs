@@ -0,0 +1,195 @@
+// skill.rs weakens the engine for less experienced players. A reduced skill
+// level caps the depth and nodes of the search, which then looks at several
+// principal variations and picks among them at random, favouring worse moves
+// more the lower the level.
+//
+// The level is set directly with the 'Skill Level' option, or derived from
+// 'UCI_Elo' when 'UCI_LimitStrength' is on. The Elo scale is only a rough
+// calibration.
+
+use crate::options::Options;
+use crate::position::Move;
+use crate::search::{Limits, SearchResult};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Levels run from 0 to MAX_LEVEL, which is full strength
+pub const MAX_LEVEL: f64 = 20.0;
+
+// The Elo range covered by UCI_Elo, from level 0 up to level MAX_LEVEL - 1
+pub const MIN_ELO: i64 = 1000;
+pub const MAX_ELO: i64 = 2800;
+
+// The number of principal variations to choose from when weakened
+pub const MULTI_PV: usize = 4;
+
+// Worse moves are never pushed up by more than a pawn of random noise
+const MAX_NOISE: i32 = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Skill {
+    pub level: f64,
+}
+
+impl Skill {
+    pub fn from_options(options: &Options) -> Skill {
+        let level = if options.get_bool("UCI_LimitStrength") {
+            let elo = options.get_int("UCI_Elo").clamp(MIN_ELO, MAX_ELO);
+            (elo - MIN_ELO) as f64 / (MAX_ELO - MIN_ELO) as f64 * (MAX_LEVEL - 1.0)
+        } else {
+            options.get_int("Skill Level") as f64
+        };
+        Skill { level }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.level < MAX_LEVEL
+    }
+
+    // Cap the search to one ply per level, and a node budget that doubles
+    // every other level
+    pub fn limit(&self, mut limits: Limits) -> Limits {
+        let depth = 1 + self.level as u32;
+        let nodes = 1000 << (self.level as u32 / 2);
+        limits.depth = Some(limits.depth.map_or(depth, |x| x.min(depth)));
+        limits.nodes = Some(limits.nodes.map_or(nodes, |x| x.min(nodes)));
+        limits
+    }
+
+    // Choose a move among the lines of a search, best first. Each line scores
+    // a random bonus that grows as the level drops, along with a share of
+    // the gap to the best line, so weak levels often pick worse moves.
+    pub fn pick(&self, lines: &[SearchResult], rng: &mut Rng) -> Option<Move> {
+        let top = lines.first()?.score;
+        let weakness = 120.0 - 2.0 * self.level;
+        let noise = (top - lines.last()?.score).min(MAX_NOISE) as f64;
+
+        let mut best = (lines[0].best_move, f64::MIN);
+        for line in lines {
+            let gap = (top - line.score) as f64;
+            let push = (weakness * gap + noise * rng.below(weakness as u64) as f64) / 128.0;
+            let score = line.score as f64 + push;
+            if score >= best.1 {
+                best = (line.best_move, score);
+            }
+        }
+        best.0
+    }
+}
+
+// xorshift64*
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed | 1)
+    }
+
+    // Seeded from the clock, so that games differ
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545F4914F6CDD1D) >> 32) % n.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::{str_to_move, Position};
+
+    fn skill_with(options: &[(&str, &str)]) -> Skill {
+        let mut all = Options::new();
+        for (name, value) in options {
+            all.set(name, value).unwrap();
+        }
+        Skill::from_options(&all)
+    }
+
+    #[test]
+    fn full_strength_by_default() {
+        let skill = skill_with(&[]);
+        assert!(!skill.enabled());
+    }
+
+    #[test]
+    fn level_from_option() {
+        let skill = skill_with(&[("Skill Level", "5")]);
+        assert_eq!(skill.level, 5.0);
+        assert!(skill.enabled());
+    }
+
+    #[test]
+    fn level_from_elo() {
+        let elo = |x: &str| skill_with(&[("UCI_LimitStrength", "true"), ("UCI_Elo", x)]).level;
+        assert_eq!(elo("1000"), 0.0);
+        assert_eq!(elo("2800"), MAX_LEVEL - 1.0);
+        assert!(elo("1500") < elo("2000"));
+        // The Elo is ignored unless strength is limited
+        let skill = skill_with(&[("UCI_Elo", "1000")]);
+        assert!(!skill.enabled());
+    }
+
+    #[test]
+    fn limits_are_capped() {
+        let skill = Skill { level: 4.0 };
+        let limits = skill.limit(Limits::default());
+        assert_eq!(limits.depth, Some(5));
+        assert_eq!(limits.nodes, Some(4000));
+
+        let limits = Limits {
+            depth: Some(2),
+            nodes: Some(1_000_000),
+            ..Limits::default()
+        };
+        assert_eq!(skill.limit(limits).depth, Some(2));
+    }
+
+    fn lines(scores: &[(&str, i32)]) -> Vec<SearchResult> {
+        let position = Position::new();
+        scores
+            .iter()
+            .map(|&(mov, score)| SearchResult {
+                best_move: Some(str_to_move(mov, position).unwrap()),
+                score,
+                depth: 1,
+                nodes: 0,
+                pv: vec![str_to_move(mov, position).unwrap()],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pick_never_fails_with_one_line() {
+        let lines = lines(&[("e2e4", 30)]);
+        let mut rng = Rng::new(1);
+        assert_eq!(
+            Skill { level: 0.0 }.pick(&lines, &mut rng),
+            lines[0].best_move
+        );
+        assert_eq!(Skill { level: 0.0 }.pick(&[], &mut rng), None);
+    }
+
+    #[test]
+    fn weaker_levels_pick_worse_moves() {
+        let lines = lines(&[("e2e4", 50), ("d2d4", 40), ("a2a3", -20), ("g1h3", -40)]);
+        let mut rng = Rng::new(7);
+        let best_picks = |level: f64, rng: &mut Rng| {
+            (0..200)
+                .filter(|_| Skill { level }.pick(&lines, rng) == lines[0].best_move)
+                .count()
+        };
+        let weak = best_picks(0.0, &mut rng);
+        let strong = best_picks(19.0, &mut rng);
+        assert!(weak < 200);
+        assert!(weak < strong);
+    }
+}
@@ -0,0 +1,215 @@
+// tt.rs implements the transposition table: a hash table of search results,
+// keyed by position hash, that lets the search reuse work done on positions
+// reached through different move orders.
+//
+// Entries are stored as two atomic words, the data and the key xor'ed with the
+// data, so that the table can be shared between search threads without locks.
+// An entry torn by a concurrent write fails the key check and is ignored.
+
+use crate::position::Move;
+use crate::search::MATE_BOUND;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower, // The score is at least this (a beta cutoff)
+    Upper, // The score is at most this (no move raised alpha)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Entry {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+impl Entry {
+    // Data word layout: bits 0-15 move, 16-31 score, 32-39 depth, 40-41 bound
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        self.best_move.unwrap_or(Move::NONE).bits() as u64
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth.min(255) as u64) << 32
+            | bound << 40
+    }
+
+    fn unpack(data: u64) -> Option<Entry> {
+        let bound = match (data >> 40) & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        let best_move = Move::from_bits(data as u16);
+        Some(Entry {
+            best_move: if best_move == Move::NONE {
+                None
+            } else {
+                Some(best_move)
+            },
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: ((data >> 32) & 0xFF) as u32,
+            bound,
+        })
+    }
+}
+
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let count = (size_mb.max(1) << 20) / std::mem::size_of::<Slot>();
+        let slots = (0..count)
+            .map(|_| Slot {
+                key: AtomicU64::new(0),
+                data: AtomicU64::new(0),
+            })
+            .collect();
+        TranspositionTable { slots }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, hash: u64) -> &Slot {
+        // Map the hash onto the table size without a modulo
+        let index = ((hash as u128 * self.slots.len() as u128) >> 64) as usize;
+        &self.slots[index]
+    }
+
+    // Look up a position. Mate scores are converted back from being relative
+    // to the stored position to being relative to the root, 'ply' moves away.
+    pub fn probe(&self, hash: u64, ply: usize) -> Option<Entry> {
+        let slot = self.slot(hash);
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ data != hash {
+            return None;
+        }
+
+        let mut entry = Entry::unpack(data)?;
+        if entry.score >= MATE_BOUND {
+            entry.score -= ply as i32;
+        } else if entry.score <= -MATE_BOUND {
+            entry.score += ply as i32;
+        }
+        Some(entry)
+    }
+
+    // Store a search result, always replacing the previous entry in the slot
+    // unless it is a deeper search of the same position.
+    pub fn store(&self, hash: u64, ply: usize, mut entry: Entry) {
+        let slot = self.slot(hash);
+        let old_data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ old_data == hash {
+            if let Some(old) = Entry::unpack(old_data) {
+                if old.depth > entry.depth && entry.bound != Bound::Exact {
+                    return;
+                }
+                // Keep the best move of a previous search of this position
+                entry.best_move = entry.best_move.or(old.best_move);
+            }
+        }
+
+        // Mate scores are stored relative to this position
+        if entry.score >= MATE_BOUND {
+            entry.score += ply as i32;
+        } else if entry.score <= -MATE_BOUND {
+            entry.score -= ply as i32;
+        }
+
+        let data = entry.pack();
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(DEFAULT_SIZE_MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    fn entry(score: i32, depth: u32, bound: Bound) -> Entry {
+        Entry {
+            best_move: Some(Move::from_bits(0x1234)),
+            score,
+            depth,
+            bound,
+        }
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.probe(42, 0), None);
+
+        tt.store(42, 3, entry(-150, 7, Bound::Lower));
+        assert_eq!(tt.probe(42, 3), Some(entry(-150, 7, Bound::Lower)));
+        assert_eq!(tt.probe(43, 3), None);
+
+        tt.clear();
+        assert_eq!(tt.probe(42, 3), None);
+    }
+
+    #[test]
+    fn mate_scores_are_ply_relative() {
+        let tt = TranspositionTable::new(1);
+        tt.store(7, 4, entry(MATE - 6, 2, Bound::Exact));
+        assert_eq!(tt.probe(7, 4).unwrap().score, MATE - 6);
+        assert_eq!(tt.probe(7, 1).unwrap().score, MATE - 3);
+
+        tt.store(8, 4, entry(-MATE + 6, 2, Bound::Exact));
+        assert_eq!(tt.probe(8, 2).unwrap().score, -MATE + 4);
+    }
+
+    #[test]
+    fn deeper_entries_are_kept() {
+        let tt = TranspositionTable::new(1);
+        tt.store(9, 0, entry(10, 8, Bound::Lower));
+        tt.store(9, 0, entry(20, 3, Bound::Upper));
+        assert_eq!(tt.probe(9, 0).unwrap().score, 10);
+
+        tt.store(9, 0, entry(30, 3, Bound::Exact));
+        assert_eq!(tt.probe(9, 0).unwrap().score, 30);
+    }
+
+    #[test]
+    fn best_move_survives_moveless_store() {
+        let tt = TranspositionTable::new(1);
+        tt.store(11, 0, entry(10, 2, Bound::Exact));
+        let moveless = Entry {
+            best_move: None,
+            ..entry(-5, 4, Bound::Upper)
+        };
+        tt.store(11, 0, moveless);
+        assert_eq!(
+            tt.probe(11, 0).unwrap().best_move,
+            Some(Move::from_bits(0x1234))
+        );
+    }
+}
@@ -0,0 +1,544 @@
+// tune.rs implements Texel tuning (https://www.chessprogramming.org/Texel%27s_Tuning_Method)
+// of the evaluation weights in eval_params.rs. A file of positions labelled
+// with their game result is loaded, and the weights are adjusted by gradient
+// descent to minimise the mean squared error between each result and the
+// sigmoid of the position's evaluation.
+//
+// Since the evaluation is a linear function of its weights, each position is
+// reduced once up front to the number of times every weight applies to it
+// (white minus black). The error and its gradient can then be computed
+// without re-running the evaluation.
+//
+// Usage: challenger-rs tune <positions file> [epochs] [output file]
+//
+// The output file defaults to src/eval_params.rs, so that run from the root
+// of the repository the tuned weights are built into the next engine.
+
+use crate::eval_params::{MOBILITY, PIECE_VALUES};
+use crate::evaluate::{self, Param, Trace, MAX_PHASE};
+use crate::position::Position;
+
+use std::fmt::Write;
+use std::fs;
+
+const PIECE_NAMES: [&str; 6] = ["Pawn", "Rook", "Knight", "Bishop", "Queen", "King"];
+
+// Offsets of each group of weights within the flattened parameter vector
+const PIECE_VALUE_OFFSET: usize = 0;
+const PST_OFFSET: usize = PIECE_VALUE_OFFSET + 6;
+const DOUBLED_PAWN_INDEX: usize = PST_OFFSET + 6 * 64;
+const ISOLATED_PAWN_INDEX: usize = DOUBLED_PAWN_INDEX + 1;
+const PASSED_PAWN_OFFSET: usize = ISOLATED_PAWN_INDEX + 1;
+const BISHOP_PAIR_INDEX: usize = PASSED_PAWN_OFFSET + 8;
+const ROOK_OPEN_FILE_INDEX: usize = BISHOP_PAIR_INDEX + 1;
+const ROOK_SEMI_OPEN_FILE_INDEX: usize = ROOK_OPEN_FILE_INDEX + 1;
+const MOBILITY_OFFSET: usize = ROOK_SEMI_OPEN_FILE_INDEX + 1;
+const KING_ATTACK_INDEX: usize = MOBILITY_OFFSET + 6;
+const PAWN_SHIELD_INDEX: usize = KING_ATTACK_INDEX + 1;
+pub const PARAM_COUNT: usize = PAWN_SHIELD_INDEX + 1;
+
+const DEFAULT_EPOCHS: usize = 2000;
+const DEFAULT_OUTPUT: &str = "src/eval_params.rs";
+const LEARNING_RATE: f64 = 1.0;
+
+fn index(param: Param) -> usize {
+    match param {
+        Param::PieceValue(piece) => PIECE_VALUE_OFFSET + piece,
+        Param::Pst(piece, sq) => PST_OFFSET + piece * 64 + sq,
+        Param::DoubledPawn => DOUBLED_PAWN_INDEX,
+        Param::IsolatedPawn => ISOLATED_PAWN_INDEX,
+        Param::PassedPawn(rank) => PASSED_PAWN_OFFSET + rank,
+        Param::BishopPair => BISHOP_PAIR_INDEX,
+        Param::RookOpenFile => ROOK_OPEN_FILE_INDEX,
+        Param::RookSemiOpenFile => ROOK_SEMI_OPEN_FILE_INDEX,
+        Param::Mobility(piece) => MOBILITY_OFFSET + piece,
+        Param::KingAttack => KING_ATTACK_INDEX,
+        Param::PawnShield => PAWN_SHIELD_INDEX,
+    }
+}
+
+// Every weight, in the order of the flattened parameter vector
+fn all_params() -> Vec<Param> {
+    let mut params = Vec::with_capacity(PARAM_COUNT);
+    params.extend((0..6).map(Param::PieceValue));
+    for piece in 0..6 {
+        params.extend((0..64).map(|sq| Param::Pst(piece, sq)));
+    }
+    params.push(Param::DoubledPawn);
+    params.push(Param::IsolatedPawn);
+    params.extend((0..8).map(Param::PassedPawn));
+    params.push(Param::BishopPair);
+    params.push(Param::RookOpenFile);
+    params.push(Param::RookSemiOpenFile);
+    params.extend((0..6).map(Param::Mobility));
+    params.push(Param::KingAttack);
+    params.push(Param::PawnShield);
+    params
+}
+
+// Weights being tuned, as (middlegame, endgame) pairs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weights(pub Vec<[f64; 2]>);
+
+impl Default for Weights {
+    // The weights currently compiled into the evaluation
+    fn default() -> Self {
+        let weights = all_params()
+            .into_iter()
+            .map(|param| {
+                let score = evaluate::param_value(param);
+                [score.mg() as f64, score.eg() as f64]
+            })
+            .collect();
+        Weights(weights)
+    }
+}
+
+// Collects the net (white minus black) number of times each weight is applied
+struct CoefficientTrace {
+    coefficients: Vec<i32>,
+}
+
+impl Trace for CoefficientTrace {
+    fn param(&mut self, param: Param, is_white: bool, count: i32) {
+        let sign = if is_white { 1 } else { -1 };
+        self.coefficients[index(param)] += sign * count;
+    }
+}
+
+// A labelled training position, reduced to its evaluation coefficients
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    coefficients: Vec<(u16, i16)>,
+    phase: f64,
+    result: f64,
+}
+
+impl Entry {
+    pub fn new(position: &Position, result: f64) -> Entry {
+        let mut trace = CoefficientTrace {
+            coefficients: vec![0; PARAM_COUNT],
+        };
+        evaluate::evaluate(position, &mut trace);
+
+        let coefficients = trace
+            .coefficients
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count != 0)
+            .map(|(index, &count)| (index as u16, count as i16))
+            .collect();
+
+        Entry {
+            coefficients,
+            phase: evaluate::game_phase(position) as f64 / MAX_PHASE as f64,
+            result,
+        }
+    }
+
+    // The white-relative evaluation of this entry under the given weights
+    pub fn evaluate(&self, weights: &Weights) -> f64 {
+        let (mg, eg) = self
+            .coefficients
+            .iter()
+            .fold((0.0, 0.0), |(mg, eg), &(index, count)| {
+                let weight = weights.0[index as usize];
+                (mg + weight[0] * count as f64, eg + weight[1] * count as f64)
+            });
+        mg * self.phase + eg * (1.0 - self.phase)
+    }
+}
+
+// Map a centipawn evaluation to an expected score between 0 and 1
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+pub fn mean_squared_error(entries: &[Entry], weights: &Weights, k: f64) -> f64 {
+    let total: f64 = entries
+        .iter()
+        .map(|entry| (entry.result - sigmoid(k, entry.evaluate(weights))).powi(2))
+        .sum();
+    total / entries.len() as f64
+}
+
+// Find the scaling constant K that best fits the current evaluation to the
+// results, by repeatedly narrowing a scan around the best value so far.
+pub fn optimal_k(entries: &[Entry], weights: &Weights) -> f64 {
+    let (mut start, mut end, mut step) = (0.0, 10.0, 1.0);
+    let mut best = start;
+    for _ in 0..6 {
+        let mut best_error = f64::MAX;
+        let mut k = start;
+        while k <= end {
+            let error = mean_squared_error(entries, weights, k);
+            if error < best_error {
+                best_error = error;
+                best = k;
+            }
+            k += step;
+        }
+        start = (best - step).max(0.0);
+        end = best + step;
+        step /= 10.0;
+    }
+    best
+}
+
+fn gradient(entries: &[Entry], weights: &Weights, k: f64) -> Vec<[f64; 2]> {
+    let mut gradient = vec![[0.0; 2]; PARAM_COUNT];
+    for entry in entries {
+        let s = sigmoid(k, entry.evaluate(weights));
+        let error = (entry.result - s) * s * (1.0 - s);
+        let mg = error * entry.phase;
+        let eg = error * (1.0 - entry.phase);
+        for &(index, count) in entry.coefficients.iter() {
+            gradient[index as usize][0] += mg * count as f64;
+            gradient[index as usize][1] += eg * count as f64;
+        }
+    }
+
+    // Constant factors of the derivative, including its sign
+    let scale = -2.0 * k * 10f64.ln() / 400.0 / entries.len() as f64;
+    for g in gradient.iter_mut() {
+        g[0] *= scale;
+        g[1] *= scale;
+    }
+    gradient
+}
+
+// Minimise the error with the Adam optimiser, calling 'report' with the epoch
+// and current error every 100 epochs.
+pub fn tune<F>(entries: &[Entry], weights: &mut Weights, k: f64, epochs: usize, mut report: F)
+where
+    F: FnMut(usize, f64),
+{
+    let (beta1, beta2, epsilon) = (0.9, 0.999, 1e-8);
+    let mut momentum = vec![[0.0; 2]; PARAM_COUNT];
+    let mut velocity = vec![[0.0; 2]; PARAM_COUNT];
+
+    for epoch in 1..=epochs {
+        let gradient = gradient(entries, weights, k);
+        for i in 0..PARAM_COUNT {
+            for phase in 0..2 {
+                let g = gradient[i][phase];
+                momentum[i][phase] = beta1 * momentum[i][phase] + (1.0 - beta1) * g;
+                velocity[i][phase] = beta2 * velocity[i][phase] + (1.0 - beta2) * g * g;
+                let m = momentum[i][phase] / (1.0 - beta1.powi(epoch as i32));
+                let v = velocity[i][phase] / (1.0 - beta2.powi(epoch as i32));
+                weights.0[i][phase] -= LEARNING_RATE * m / (v.sqrt() + epsilon);
+            }
+        }
+        if epoch % 100 == 0 {
+            report(epoch, mean_squared_error(entries, weights, k));
+        }
+    }
+}
+
+// Parse a game result, either as a score for white or in PGN notation
+fn parse_result(result: &str) -> Option<f64> {
+    let result = result.trim().trim_matches(|c| "[]\";".contains(c));
+    match result {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => result.parse().ok().filter(|r| (0.0..=1.0).contains(r)),
+    }
+}
+
+// Parse a single labelled position. Two formats are understood:
+//
+//   <fen> | <score> | <result>     (as written by the datagen command)
+//   <fen> [<result>]               (e.g. "... w - - 0 1 [0.5]" or EPD 'c9 "1-0";')
+//
+// The halfmove clock and fullmove number may be omitted from the FEN.
+pub fn parse_entry(line: &str) -> Result<(Position, f64), String> {
+    let (fen, labels) = match line.find('|') {
+        Some(_) => {
+            let fields: Vec<&str> = line.split('|').collect();
+            (fields[0], fields[fields.len() - 1])
+        }
+        None => ("", line),
+    };
+    let tokens: Vec<&str> = if fen.is_empty() {
+        labels.split_whitespace().collect()
+    } else {
+        fen.split_whitespace().collect()
+    };
+    if tokens.len() < 4 {
+        return Err(format!("Invalid FEN in '{}'", line));
+    }
+
+    // The FEN ends after the halfmove clock and fullmove number, if present
+    let clocks = tokens[4..].iter().take(2).take_while(is_number).count();
+    let fen_len = 4 + clocks;
+
+    // Without separators, the result is the last token after the FEN that
+    // looks like one, which skips over EPD opcodes such as 'c9'
+    let result = if fen.is_empty() {
+        tokens[fen_len..]
+            .iter()
+            .rev()
+            .find_map(|token| parse_result(token))
+    } else {
+        parse_result(labels)
+    };
+    let result = result.ok_or(format!("No game result in '{}'", line))?;
+
+    let mut fen_tokens = tokens[..fen_len].to_vec();
+    fen_tokens.extend(["0", "1"][clocks..].iter());
+    let position =
+        Position::try_from(&fen_tokens.join(" ")).map_err(|e| format!("{} in '{}'", e, line))?;
+    Ok((position, result))
+}
+
+fn is_number(token: &&&str) -> bool {
+    token.parse::<u32>().is_ok()
+}
+
+pub fn load_entries(contents: &str) -> Result<Vec<Entry>, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_entry(line).map(|(position, result)| Entry::new(&position, result)))
+        .collect()
+}
+
+fn score(weight: [f64; 2]) -> String {
+    format!(
+        "Score({:>4}, {:>4})",
+        weight[0].round() as i32,
+        weight[1].round() as i32
+    )
+}
+
+fn write_array(out: &mut String, comment: &str, name: &str, weights: &[[f64; 2]]) {
+    writeln!(out, "// {}", comment).unwrap();
+    writeln!(out, "#[rustfmt::skip]").unwrap();
+    writeln!(out, "pub const {}: [Score; {}] = [", name, weights.len()).unwrap();
+    for weight in weights {
+        writeln!(out, "    {},", score(*weight)).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+}
+
+fn write_scalar(out: &mut String, name: &str, weight: [f64; 2]) {
+    let (mg, eg) = (weight[0].round() as i32, weight[1].round() as i32);
+    writeln!(out, "pub const {}: Score = Score({}, {});", name, mg, eg).unwrap();
+}
+
+// Write the weights as the Rust source of eval_params.rs. 'origin' says where
+// the weights came from, in lines for the header comment.
+pub fn write_params(weights: &Weights, origin: &[&str]) -> String {
+    let w = &weights.0;
+    let mut out = String::new();
+
+    out.push_str("// eval_params.rs holds the weights used by challenger's evaluation function.\n");
+    for line in origin {
+        writeln!(out, "// {}", line).unwrap();
+    }
+    out.push_str(
+        "// Running the 'tune' subcommand (see tune.rs) replaces this file.\n\
+         \n\
+         use crate::evaluate::Score;\n\n",
+    );
+
+    let piece_values = &w[PIECE_VALUE_OFFSET..PIECE_VALUE_OFFSET + PIECE_VALUES.len()];
+    let comment = "Material values, indexed like the 'pieces' array";
+    write_array(&mut out, comment, "PIECE_VALUES", piece_values);
+
+    out.push_str("\n// Piece square tables from white's point of view, with the 8th rank first\n");
+    out.push_str("#[rustfmt::skip]\npub const PST: [[Score; 64]; 6] = [\n");
+    for (piece, name) in PIECE_NAMES.iter().enumerate() {
+        writeln!(out, "    [ // {}", name).unwrap();
+        let table = &w[PST_OFFSET + piece * 64..PST_OFFSET + (piece + 1) * 64];
+        for rank in table.chunks(8) {
+            let row: Vec<String> = rank.iter().map(|weight| score(*weight)).collect();
+            writeln!(out, "        {},", row.join(", ")).unwrap();
+        }
+        out.push_str("    ],\n");
+    }
+    out.push_str("];\n\n// Pawn structure\n");
+
+    write_scalar(&mut out, "DOUBLED_PAWN", w[DOUBLED_PAWN_INDEX]);
+    write_scalar(&mut out, "ISOLATED_PAWN", w[ISOLATED_PAWN_INDEX]);
+    out.push('\n');
+
+    let passed = &w[PASSED_PAWN_OFFSET..PASSED_PAWN_OFFSET + 8];
+    let comment = "Passed pawn bonus by relative rank";
+    write_array(&mut out, comment, "PASSED_PAWN", passed);
+
+    out.push_str("\n// Pieces\n");
+    write_scalar(&mut out, "BISHOP_PAIR", w[BISHOP_PAIR_INDEX]);
+    write_scalar(&mut out, "ROOK_OPEN_FILE", w[ROOK_OPEN_FILE_INDEX]);
+    write_scalar(
+        &mut out,
+        "ROOK_SEMI_OPEN_FILE",
+        w[ROOK_SEMI_OPEN_FILE_INDEX],
+    );
+    out.push('\n');
+
+    let mobility = &w[MOBILITY_OFFSET..MOBILITY_OFFSET + MOBILITY.len()];
+    let comment = "Mobility bonus per safe square attacked, indexed like the 'pieces' array";
+    write_array(&mut out, comment, "MOBILITY", mobility);
+
+    out.push_str("\n// King safety\n");
+    write_scalar(&mut out, "KING_ATTACK", w[KING_ATTACK_INDEX]);
+    write_scalar(&mut out, "PAWN_SHIELD", w[PAWN_SHIELD_INDEX]);
+    out
+}
+
+// Entry point for the 'tune' subcommand
+pub fn run(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or("Usage: challenger-rs tune <positions file> [epochs] [output file]")?;
+    let epochs = match args.get(1) {
+        Some(epochs) => epochs.parse().map_err(|_| "Invalid epoch count")?,
+        None => DEFAULT_EPOCHS,
+    };
+    let output = args.get(2).map_or(DEFAULT_OUTPUT, String::as_str);
+
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let entries = load_entries(&contents)?;
+    if entries.is_empty() {
+        return Err(format!("{}: no positions found", path));
+    }
+    println!("Loaded {} positions", entries.len());
+
+    let mut weights = Weights::default();
+    let k = optimal_k(&entries, &weights);
+    let initial_error = mean_squared_error(&entries, &weights, k);
+    println!("K = {:.4}, initial error = {:.6}", k, initial_error);
+
+    tune(&entries, &mut weights, k, epochs, |epoch, error| {
+        println!("Epoch {:>6}: error = {:.6}", epoch, error);
+    });
+
+    let origin = [
+        &format!("Tuned on the {} positions of {},", entries.len(), path)[..],
+        &format!("over {} epochs.", epochs),
+    ];
+    let source = write_params(&weights, &origin);
+    fs::write(output, source).map_err(|e| format!("{}: {}", output, e))?;
+    println!("Wrote tuned weights to {}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_match_layout() {
+        let params = all_params();
+        assert_eq!(params.len(), PARAM_COUNT);
+        for (i, param) in params.into_iter().enumerate() {
+            assert_eq!(index(param), i);
+        }
+    }
+
+    #[test]
+    fn write_default_params_reproduces_source() {
+        let origin = [
+            "These are hand-picked starting values, not yet tuned: material from PeSTO",
+            "and piece-square tables from the Simplified Evaluation Function, both on",
+            "the Chess Programming Wiki, with the remaining terms set by hand.",
+        ];
+        let source = include_str!("eval_params.rs");
+        assert_eq!(write_params(&Weights::default(), &origin), source);
+    }
+
+    // The coefficient model must agree exactly with the real evaluation
+    macro_rules! test_entry_evaluation {
+        ($test_name:ident, $fen:expr) => {
+            #[test]
+            fn $test_name() {
+                let position = Position::from($fen);
+                let entry = Entry::new(&position, 0.5);
+                let expected = evaluate::evaluate(&position, &mut evaluate::NoTrace) as f64;
+                assert!((entry.evaluate(&Weights::default()) - expected).abs() < 1.0);
+            }
+        };
+    }
+
+    test_entry_evaluation!(
+        entry_eval_startpos,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+    );
+    test_entry_evaluation!(
+        entry_eval_complex_2,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+    );
+    test_entry_evaluation!(
+        entry_eval_complex_5,
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8"
+    );
+    test_entry_evaluation!(
+        entry_eval_endgame,
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"
+    );
+
+    macro_rules! test_parse_entry {
+        ($test_name:ident, $line:expr, $fen:expr, $result:expr) => {
+            #[test]
+            fn $test_name() {
+                assert_eq!(parse_entry($line), Ok((Position::from($fen), $result)));
+            }
+        };
+    }
+
+    test_parse_entry!(
+        parse_datagen_format,
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 3 40 | 112 | 1.0",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 3 40",
+        1.0
+    );
+    test_parse_entry!(
+        parse_bracket_format,
+        "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1 [0.5]",
+        "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1",
+        0.5
+    );
+    test_parse_entry!(
+        parse_epd_format,
+        "4k3/8/8/8/8/8/4P3/4K3 w - - c9 \"0-1\";",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+        0.0
+    );
+    test_parse_entry!(
+        parse_pgn_draw,
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 5 1/2-1/2",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 5 1",
+        0.5
+    );
+
+    #[test]
+    fn parse_entry_without_result() {
+        assert!(parse_entry("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn parse_entry_invalid_fen() {
+        assert!(parse_entry("4k3/8/8/8/8/8/4P3/4K3 x - - 0 1 [1.0]").is_err());
+        assert!(parse_entry("4k3/8/8/8/8/8/4P3 w - - 0 1 | 20 | 1-0").is_err());
+        assert!(parse_entry("4k3/8/8/8/8/8/4P3/4K3 w - - 0 70000 [0.5]").is_err());
+    }
+
+    #[test]
+    fn tuning_reduces_error() {
+        // White is winning every game with an extra pawn, so tuning should
+        // learn to value the pawn more highly.
+        let contents = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 [1.0]\n\
+                        4k3/8/8/8/8/4P3/8/4K3 b - - 0 1 [1.0]\n\
+                        4k3/8/8/8/8/8/8/4K3 w - - 0 1 [0.5]\n";
+        let entries = load_entries(contents).unwrap();
+        let mut weights = Weights::default();
+        let before = mean_squared_error(&entries, &weights, 1.0);
+
+        tune(&entries, &mut weights, 1.0, 200, |_, _| ());
+
+        assert!(mean_squared_error(&entries, &weights, 1.0) < before);
+    }
+}
@@ -0,0 +1,423 @@
+// types.rs defines the basic vocabulary of the board: colors, files, ranks,
+// squares, pieces and bitboards. Each is a thin wrapper around the integer
+// the engine uses internally, so values can only be built in range and the
+// compiler tells a square from a piece index. Squares are numbered from 0 for
+// a1 to 63 for h8, matching the bits of a bitboard.
+
+use std::fmt;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn from_is_white(is_white: bool) -> Color {
+        if is_white {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+
+    pub fn is_white(self) -> bool {
+        self == Color::White
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Not for Color {
+    type Output = Color;
+
+    fn not(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct File(u8);
+
+impl File {
+    pub fn from_index(index: usize) -> Option<File> {
+        (index < 8).then_some(File(index as u8))
+    }
+
+    pub fn from_char(c: char) -> Option<File> {
+        ('a'..='h').contains(&c).then(|| File(c as u8 - b'a'))
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self.0) as char
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rank(u8);
+
+impl Rank {
+    pub fn from_index(index: usize) -> Option<Rank> {
+        (index < 8).then_some(Rank(index as u8))
+    }
+
+    pub fn from_char(c: char) -> Option<Rank> {
+        ('1'..='8').contains(&c).then(|| Rank(c as u8 - b'1'))
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self.0) as char
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    pub fn new(file: File, rank: Rank) -> Square {
+        Square(rank.0 * 8 + file.0)
+    }
+
+    // Panics unless 'index' is below 64
+    pub fn from_index(index: usize) -> Square {
+        assert!(index < 64, "Square index out of range: {}", index);
+        Square(index as u8)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> File {
+        File(self.0 % 8)
+    }
+
+    pub fn rank(self) -> Rank {
+        Rank(self.0 / 8)
+    }
+
+    pub fn bitboard(self) -> Bitboard {
+        Bitboard(1 << self.0)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file().to_char(), self.rank().to_char())
+    }
+}
+
+impl FromStr for Square {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Square, String> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(file), Some(rank), None) => File::from_char(file)
+                .zip(Rank::from_char(rank))
+                .map(|(file, rank)| Square::new(file, rank))
+                .ok_or_else(|| format!("Invalid square: {}", s)),
+            _ => Err(format!("Invalid square: {}", s)),
+        }
+    }
+}
+
+// Piece types are numbered in the order of the 'pieces' array of a position
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PieceType {
+    Pawn,
+    Rook,
+    Knight,
+    Bishop,
+    Queen,
+    King,
+}
+
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Rook,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Queen,
+        PieceType::King,
+    ];
+
+    pub fn from_index(index: usize) -> Option<PieceType> {
+        PieceType::ALL.get(index).copied()
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    // The letter used by FEN and SAN, in upper case
+    pub fn to_char(self) -> char {
+        ['P', 'R', 'N', 'B', 'Q', 'K'][self as usize]
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub color: Color,
+    pub piece_type: PieceType,
+}
+
+impl Piece {
+    pub fn new(color: Color, piece_type: PieceType) -> Piece {
+        Piece { color, piece_type }
+    }
+
+    // The piece stored at an index of the 'pieces' array of a position. The
+    // indices of the color bitboards hold no single piece.
+    pub fn from_index(index: usize) -> Option<Piece> {
+        let color = Color::from_is_white(index < 7);
+        PieceType::from_index(index % 7).map(|x| Piece::new(color, x))
+    }
+
+    pub fn index(self) -> usize {
+        self.piece_type.index() + 7 * self.color.index()
+    }
+
+    // The letter used by FEN: upper case for white, lower case for black
+    pub fn to_char(self) -> char {
+        match self.color {
+            Color::White => self.piece_type.to_char(),
+            Color::Black => self.piece_type.to_char().to_ascii_lowercase(),
+        }
+    }
+}
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+// A set of squares, one bit per square. Iterating over a bitboard yields its
+// squares from a1 to h8.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Bitboard(pub u64);
+
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const FULL: Bitboard = Bitboard(u64::MAX);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub fn contains(self, sq: Square) -> bool {
+        self.0 & (1 << sq.0) != 0
+    }
+
+    // The lowest square in the set, if any
+    pub fn first(self) -> Option<Square> {
+        (self.0 != 0).then(|| Square(self.0.trailing_zeros() as u8))
+    }
+}
+
+impl Iterator for Bitboard {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        let sq = self.first()?;
+        self.0 &= self.0 - 1;
+        Some(sq)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.count() as usize;
+        (count, Some(count))
+    }
+}
+
+impl From<Square> for Bitboard {
+    fn from(sq: Square) -> Bitboard {
+        sq.bitboard()
+    }
+}
+
+// Shown as a board from white's side, with 'X' on the squares in the set
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rank in (0..8).rev() {
+            let row: Vec<&str> = (0..8)
+                .map(|file| match self.0 & 1 << (rank * 8 + file) {
+                    0 => ".",
+                    _ => "X",
+                })
+                .collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! bitboard_op {
+    ($op:ident, $method:ident, $assign_op:ident, $assign_method:ident) => {
+        impl $op for Bitboard {
+            type Output = Bitboard;
+
+            fn $method(self, other: Bitboard) -> Bitboard {
+                Bitboard(self.0.$method(other.0))
+            }
+        }
+
+        impl $assign_op for Bitboard {
+            fn $assign_method(&mut self, other: Bitboard) {
+                self.0.$assign_method(other.0);
+            }
+        }
+    };
+}
+
+bitboard_op!(BitAnd, bitand, BitAndAssign, bitand_assign);
+bitboard_op!(BitOr, bitor, BitOrAssign, bitor_assign);
+bitboard_op!(BitXor, bitxor, BitXorAssign, bitxor_assign);
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
+    }
+}
+
+// Shifting moves every square up (<<) or down (>>) the board by the given
+// number of squares. Squares shifted off the board are lost.
+impl Shl<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shl(self, n: u32) -> Bitboard {
+        Bitboard(self.0.checked_shl(n).unwrap_or(0))
+    }
+}
+
+impl Shr<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shr(self, n: u32) -> Bitboard {
+        Bitboard(self.0.checked_shr(n).unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(!Color::White, Color::Black);
+        assert_eq!(!Color::Black, Color::White);
+        assert_eq!(Color::from_is_white(false), Color::Black);
+        assert_eq!(Color::Black.index(), 1);
+    }
+
+    #[test]
+    fn squares() {
+        let e4: Square = "e4".parse().unwrap();
+        assert_eq!(e4.index(), 28);
+        assert_eq!(e4.file().to_char(), 'e');
+        assert_eq!(e4.rank().index(), 3);
+        assert_eq!(e4.to_string(), "e4");
+        assert_eq!(
+            Square::new(File::from_char('h').unwrap(), Rank::from_char('8').unwrap()).index(),
+            63
+        );
+        assert_eq!(Square::from_index(0).to_string(), "a1");
+        assert!("i1".parse::<Square>().is_err());
+        assert!("a9".parse::<Square>().is_err());
+        assert!("a10".parse::<Square>().is_err());
+        assert!(File::from_index(8).is_none());
+        assert!(Rank::from_index(7).is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn square_out_of_range() {
+        Square::from_index(64);
+    }
+
+    #[test]
+    fn pieces() {
+        let knight = Piece::new(Color::Black, PieceType::Knight);
+        assert_eq!(knight.index(), 9);
+        assert_eq!(Piece::from_index(9), Some(knight));
+        assert_eq!(knight.to_string(), "n");
+        assert_eq!(Piece::from_index(5).unwrap().to_char(), 'K');
+        assert_eq!(Piece::from_index(6), None);
+        assert_eq!(Piece::from_index(13), None);
+        for index in (0..13).filter(|&x| x != 6) {
+            assert_eq!(Piece::from_index(index).unwrap().index(), index);
+        }
+    }
+
+    #[test]
+    fn bitboard_operations() {
+        let a1 = Square::from_index(0);
+        let h8 = Square::from_index(63);
+        let corners = Bitboard::from(a1) | h8.bitboard();
+        assert_eq!(corners.count(), 2);
+        assert!(corners.contains(h8));
+        assert_eq!(corners & a1.bitboard(), a1.bitboard());
+        assert_eq!(corners ^ a1.bitboard(), h8.bitboard());
+        assert_eq!((!corners).count(), 62);
+        assert_eq!(a1.bitboard() << 9, Square::from_index(9).bitboard());
+        assert_eq!(h8.bitboard() >> 63, a1.bitboard());
+        assert_eq!(h8.bitboard() << 1, Bitboard::EMPTY);
+        assert_eq!(a1.bitboard() << 64, Bitboard::EMPTY);
+
+        let mut set = Bitboard::EMPTY;
+        set |= corners;
+        set &= !a1.bitboard();
+        assert_eq!(set.first(), Some(h8));
+        assert!(Bitboard::EMPTY.is_empty());
+    }
+
+    #[test]
+    fn bitboard_iteration() {
+        let squares: Vec<String> = Bitboard(0x8100_0000_0000_0042)
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(squares, vec!["b1", "g1", "a8", "h8"]);
+        assert_eq!(Bitboard::FULL.count(), 64);
+        let mut empty = Bitboard::EMPTY;
+        assert_eq!(empty.next(), None);
+    }
+
+    #[test]
+    fn bitboard_display() {
+        let e4 = "e4".parse::<Square>().unwrap().bitboard();
+        assert_eq!(
+            (e4 | Bitboard(1)).to_string(),
+            ". . . . . . . .\n\
+             . . . . . . . .\n\
+             . . . . . . . .\n\
+             . . . . . . . .\n\
+             . . . . X . . .\n\
+             . . . . . . . .\n\
+             . . . . . . . .\n\
+             X . . . . . . .\n"
+        );
+    }
+}
@@ -3,13 +3,41 @@
 // outlined in http://wbec-ridderkerk.nl/html/UCIProtocol.html) into
 // challenger-specific logic for implementing them.
 
+use crate::evaluate;
 use crate::gamestate::GameState;
-use crate::position::Position;
+use crate::options;
+use crate::position::{self, Position};
+use crate::search::{self, Limits, Pruning, SearchResult, Searcher};
+use crate::skill::{self, Rng, Skill};
 
 use regex::RegexSet;
 use std::io::Write;
-use std::sync::mpsc;
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::{Duration, Instant};
+
+// Time kept in reserve for communication with the GUI, in milliseconds
+const MOVE_OVERHEAD: u64 = 50;
+
+// The number of moves assumed to remain when the GUI does not say
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+// The arguments of a 'go' command, which end a 'searchmoves' list
+const GO_ARGUMENTS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
 
 /// The entry point for running the Challenger engine. Spawns two threads, one
 /// accepting UCI commands from stdin and one processing those commands.
@@ -39,53 +67,239 @@ impl Command {
     // Execute the challenger-specific logic for a given UCI command.
     fn execute(&self, game_state: &mut GameState, string_buf: &mut Vec<u8>) {
         let tokens = self.tokens();
+
+        // Commands that change what is being searched end any running search
+        if let "go" | "stop" | "position" | "ucinewgame" | "setoption" = tokens[0] {
+            game_state.stop_search();
+        }
+
         match tokens[0] {
-            "uci" => writeln!(string_buf, "id name Challenger\nid author folksgl\nuciok").unwrap(),
+            "uci" => {
+                writeln!(string_buf, "id name Challenger\nid author folksgl").unwrap();
+                for option in options::OPTIONS.iter().filter(|x| !x.hidden) {
+                    writeln!(string_buf, "{}", option).unwrap();
+                }
+                writeln!(string_buf, "uciok").unwrap();
+            }
             "debug" => game_state.debug = tokens[1] == "on",
             "isready" => writeln!(string_buf, "readyok").unwrap(),
-            "ucinewgame" => game_state.reset_game(),
+            "ucinewgame" => game_state.new_game(),
             "position" => {
-                let mut skip = 2;
+                // The position description runs up to the 'moves' token
+                let end = tokens.iter().position(|&x| x == "moves");
+                let end = end.unwrap_or(tokens.len());
                 if tokens[1] == "startpos" {
                     game_state.reset_game();
                 } else {
-                    let fen = &tokens[1..=6].join(" ");
-                    game_state.game_position = Position::from(fen);
-                    skip = 7;
+                    // A FEN that cannot be parsed leaves the position as it was
+                    let fen = &tokens[1..end].join(" ");
+                    match Position::try_from_variant(fen, game_state.variant()) {
+                        Ok(position) => game_state.game_position = position,
+                        Err(message) => {
+                            writeln!(string_buf, "info string {}", message).unwrap();
+                            return;
+                        }
+                    }
                 }
 
-                tokens.iter().skip(skip).for_each(|x| {
-                    game_state
-                        .game_position
-                        .play_move(crate::position::str_to_move(&x, game_state.game_position))
-                });
+                // Moves are played up to the first one that is not legal
+                for x in tokens.iter().skip(end + 1) {
+                    match position::str_to_move(x, game_state.game_position) {
+                        Some(mov) => game_state.play_move(mov),
+                        None => break,
+                    }
+                }
+            }
+            "go" => {
+                let limits = parse_go(&tokens[1..], &game_state.game_position);
+                go(game_state, limits, std::io::stdout());
+            }
+            "stop" => (), // The search was stopped above
+            "setoption" => {
+                // Option names and values may contain spaces
+                let tokens = &tokens[1..];
+                let tokens = tokens.strip_prefix(&["name"]).unwrap_or(tokens);
+                let split = tokens.iter().position(|&x| x == "value");
+                let (name, value) = match split {
+                    Some(i) => (tokens[..i].join(" "), tokens[i + 1..].join(" ")),
+                    None => (tokens.join(" "), String::new()),
+                };
+                if let Err(message) = game_state.set_option(&name, &value) {
+                    writeln!(string_buf, "info string {}", message).unwrap();
+                }
+            }
+            // Non-standard command: print a breakdown of the evaluation terms
+            "eval" => {
+                let position = &game_state.game_position;
+                write!(string_buf, "{}", evaluate::trace(position)).unwrap();
+                if let Some(network) = game_state.active_network() {
+                    let score = network.evaluate_position(position) as f64 / 100.0;
+                    writeln!(string_buf, "NNUE evaluation: {:.2} (white side)", score).unwrap();
+                }
             }
             _ => writeln!(string_buf, "something else").unwrap(),
         }
     }
 
     fn tokens(&self) -> Vec<&str> {
-        return self.uci_string.split_whitespace().collect();
+        self.uci_string.split_whitespace().collect()
     }
 }
 
+// Translate the arguments of a 'go' command into search limits. With a clock,
+// the time for this move is an even share of the time left plus most of the
+// increment. Moves after 'searchmoves' that are not legal are ignored.
+fn parse_go(tokens: &[&str], position: &Position) -> Limits {
+    let mut limits = Limits::default();
+    let (mut time, mut increment, mut moves_to_go) = (None, 0, DEFAULT_MOVES_TO_GO);
+    let is_white_move = position.is_white_move;
+
+    if let Some(i) = tokens.iter().position(|&x| x == "searchmoves") {
+        limits.search_moves = tokens[i + 1..]
+            .iter()
+            .take_while(|x| !GO_ARGUMENTS.contains(x))
+            .filter_map(|x| position::str_to_move(x, *position))
+            .collect();
+    }
+
+    for pair in tokens.windows(2) {
+        let value = match pair[1].parse::<u64>() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        match (pair[0], is_white_move) {
+            ("depth", _) => limits.depth = Some(value as u32),
+            ("nodes", _) => limits.nodes = Some(value),
+            ("mate", _) => limits.mate = Some(value as u32),
+            ("movetime", _) => limits.movetime = Some(Duration::from_millis(value)),
+            ("movestogo", _) => moves_to_go = value.max(1),
+            ("wtime", true) | ("btime", false) => time = Some(value),
+            ("winc", true) | ("binc", false) => increment = value,
+            _ => (),
+        }
+    }
+
+    if let (None, Some(time)) = (limits.movetime, time) {
+        let budget = time / moves_to_go + increment * 3 / 4;
+        let budget = budget.min(time.saturating_sub(MOVE_OVERHEAD)).max(1);
+        limits.movetime = Some(Duration::from_millis(budget));
+    }
+    limits
+}
+
+// Search the current position in a background thread, writing 'info' lines
+// for each completed iteration and the final 'bestmove' to the output. At a
+// reduced skill level, the search is capped and the move picked at random
+// from several principal variations. Book moves are played without a search,
+// unless the GUI restricts the moves to search.
+fn go<W: Write + Send + 'static>(game_state: &mut GameState, limits: Limits, mut output: W) {
+    let chess960 = game_state.options.get_bool("UCI_Chess960");
+    if let Some(mov) = game_state
+        .book_move()
+        .filter(|_| limits.search_moves.is_empty())
+    {
+        writeln!(output, "bestmove {}", position::move_to_uci(mov, chess960)).unwrap();
+        output.flush().unwrap();
+        return;
+    }
+
+    let skill = Skill::from_options(&game_state.options);
+    let (limits, multi_pv) = if skill.enabled() {
+        (skill.limit(limits), skill::MULTI_PV)
+    } else {
+        (limits, 1)
+    };
+    let position = game_state.game_position;
+    let game_hashes = game_state.game_hashes.clone();
+    let tt = Arc::clone(&game_state.tt);
+    let histories = Arc::clone(&game_state.histories);
+    let pruning = Pruning::from_options(&game_state.options);
+    let contempt = search::contempt_from_options(&game_state.options);
+    let network = if game_state.options.get_bool("Use NNUE") {
+        game_state.network.clone()
+    } else {
+        None
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = Arc::clone(&stop);
+
+    let search = thread::Builder::new().stack_size(search::STACK_SIZE);
+    let handle = search.spawn(move || {
+        let start = Instant::now();
+        let mut histories = histories.lock().unwrap();
+        let (history, helpers) = histories.split_first_mut().unwrap();
+        let mut searcher = Searcher::new(limits, &tt, history, network.as_deref())
+            .with_pruning(pruning)
+            .with_contempt(contempt)
+            .with_stop(&thread_stop)
+            .with_multi_pv(multi_pv);
+        let result = searcher.search_with_helpers(helpers, &position, &game_hashes, |x| {
+            writeln!(output, "{}", info_line(x, start.elapsed(), chess960)).unwrap();
+            output.flush().unwrap();
+        });
+
+        let best_move = if skill.enabled() {
+            let pick = skill.pick(searcher.lines(), &mut Rng::from_time());
+            pick.or(result.best_move)
+        } else {
+            result.best_move
+        };
+        let best_move =
+            best_move.map_or("0000".to_string(), |x| position::move_to_uci(x, chess960));
+        writeln!(output, "bestmove {}", best_move).unwrap();
+        output.flush().unwrap();
+    });
+    game_state.set_search(stop, handle.expect("Failed to start the search thread"));
+}
+
+// An 'info' line for a completed iteration, with castling in the principal
+// variation written the Chess960 way when 'chess960' is set
+fn info_line(result: &SearchResult, elapsed: Duration, chess960: bool) -> String {
+    let score = if result.score >= search::MATE_BOUND {
+        format!("mate {}", (search::MATE - result.score + 1) / 2)
+    } else if result.score <= -search::MATE_BOUND {
+        format!("mate -{}", (search::MATE + result.score) / 2)
+    } else {
+        format!("cp {}", result.score)
+    };
+    let millis = elapsed.as_millis() as u64;
+    let mut line = format!(
+        "info depth {} score {} nodes {} nps {} time {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.nodes * 1000 / millis.max(1),
+        millis
+    );
+    if !result.pv.is_empty() {
+        let pv: Vec<String> = result
+            .pv
+            .iter()
+            .map(|&x| position::move_to_uci(x, chess960))
+            .collect();
+        line += &format!(" pv {}", pv.join(" "));
+    }
+    line
+}
+
 // Validate that the input is a well-formed UCI command string. Return the
 // command tokens in a vector, or Err() if invalid.
 fn validate_input_string(input: &str) -> Result<String, &str> {
     let input = input.trim();
 
     lazy_static! {
-        static ref UCI_REGEX_SET: RegexSet = RegexSet::new(&[
-            r"^(?:uci|isready|ucinewgame|stop|ponderhit)$",
+        static ref UCI_REGEX_SET: RegexSet = RegexSet::new([
+            r"^(?:uci|isready|ucinewgame|stop|ponderhit|eval)$",
             r"^debug (?:on|off)$",
-            r"^position (?:startpos|(?:[rnbqkp12345678RNBQKP]{1,8}/){7}[rnbqkp12345678RNBQKP]{1,8} (w|b) (?:-|[KQkq]{1,4}) (?:-|[a-h][1-8]) (?:\d)+ (?:\d)+)(?: moves(?: [a-h][1-8][a-h][1-8][rnbqRNBQ]?)+)?$",
-            r"^go(?: ponder| infinite| (?:wtime|btime|winc|binc|movestogo|depth|nodes|mate|movetime) [\d]+| searchmoves(?: [a-h][1-8][a-h][1-8][rnbqRNBQ]?)+)*$",
-            r"^setoption [[:word:]]+(?: value [[:word:]]+)?$"
+            r"^position (?:startpos|(?:[rnbqkp12345678RNBQKP~]{1,16}/){7}[rnbqkp12345678RNBQKP~]{1,16}(?:\[[PNBRQpnbrq]*\]|/[PNBRQpnbrq]*)? (w|b) (?:-|[KQA-Hkqa-h]{1,4}) (?:-|[a-h][1-8])(?: \d\+\d)? (?:\d)+ (?:\d)+(?: \+\d\+\d)?)(?: moves(?: (?:[a-h][1-8][a-h][1-8][rnbqkRNBQK]?|[PNBRQ]@[a-h][1-8]))+)?$",
+            r"^go(?: ponder| infinite| (?:wtime|btime|winc|binc|movestogo|depth|nodes|mate|movetime) [\d]+| searchmoves(?: (?:[a-h][1-8][a-h][1-8][rnbqkRNBQK]?|[PNBRQ]@[a-h][1-8]))+)*$",
+            r"^setoption [[:word:]]+(?: value [[:word:]]+)?$",
+            r"^setoption name \S.*$",
         ]).unwrap();
     }
 
     // Match the input against known Universal Chess Interface (UCI) commands
-    if UCI_REGEX_SET.is_match(&input) {
+    if UCI_REGEX_SET.is_match(input) {
         Ok(String::from(input))
     } else {
         Err("Command failed UCI regex validation")
@@ -97,18 +311,18 @@ fn validate_input_string(input: &str) -> Result<String, &str> {
 fn producer(tx: mpsc::Sender<Command>) {
     loop {
         let mut buffer = String::new();
-        std::io::stdin().read_line(&mut buffer).unwrap();
+        let read = std::io::stdin().read_line(&mut buffer).unwrap();
 
         let input = buffer.trim();
 
-        if input == "quit" {
+        if input == "quit" || read == 0 {
             // Breaking out of this loop causes the Sender end of the Channel to
             // close, which will cause the Receiver loop in `consumer` to end.
             break;
         }
 
         // If a valid Command can be constructed, send it to the engine
-        let uci_command = match Command::from(&input) {
+        let uci_command = match Command::from(input) {
             Ok(x) => x,
             Err(_) => continue,
         };
@@ -125,13 +339,16 @@ fn consumer(rx: mpsc::Receiver<Command>) {
         let mut string_buf: Vec<u8> = Vec::new();
         command.execute(&mut game_state, &mut string_buf);
         print!("{}", String::from_utf8(string_buf).unwrap());
+        std::io::stdout().flush().unwrap();
     }
+    game_state.stop_search();
 }
 
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use crate::variant::{Outcome, Variant};
 
     // Macro for defining tests that validate good input strings against a known
     // set of tokens that should be returned by that input.
@@ -222,6 +439,11 @@ mod tests {
     test_valid_command!(valid_setoption_2, "setoption name value 1");
     test_valid_command!(valid_setoption_3, "setoption asdf_1234");
     test_valid_command!(valid_setoption_4, "setoption asdf_1234 value asdf_1234");
+    test_valid_command!(valid_setoption_5, "setoption name Use NNUE value true");
+    test_valid_command!(
+        valid_setoption_6,
+        "setoption name EvalFile value nets/my-net.nnue"
+    );
 
     // Invalid setoption
     test_invalid_command!(invalid_setoption_1, "isetoption");
@@ -353,6 +575,38 @@ mod tests {
         valid_position_30,
         "position 8/8/8/8/8/8/8/8 w KQkq - 0 1 moves d5f8B"
     );
+    test_valid_command!(
+        valid_position_31,
+        "position bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"
+    );
+    test_valid_command!(
+        valid_position_32,
+        "position nrbbqkrn/pppppppp/8/8/8/8/PPPPPPPP/NRBBQKRN w GBgb - 0 1 moves f1g1"
+    );
+    test_valid_command!(
+        valid_position_33,
+        "position rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1 moves e2e4"
+    );
+    test_valid_command!(
+        valid_position_34,
+        "position rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0"
+    );
+    test_valid_command!(
+        valid_position_35,
+        "position 8/1P6/8/8/8/8/8/8 w - - 0 1 moves b7b8k"
+    );
+    test_valid_command!(
+        valid_position_36,
+        "position rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1 moves e2e4 N@f3"
+    );
+    test_valid_command!(
+        valid_position_37,
+        "position r1bqkbnr/pppp1ppp/2n5/4p3/4P3/8/PPPP1PPP/RNBQKBNR/Pn w KQkq - 0 3"
+    );
+    test_valid_command!(
+        valid_position_38,
+        "position 3Q~k3/8/8/8/8/8/8/4K3[Ppq] b - - 0 1 moves P@d7"
+    );
     // Invalid position
     test_invalid_command!(invalid_position_1, "uposition");
     test_invalid_command!(invalid_position_2, "positione");
@@ -427,6 +681,14 @@ mod tests {
         invalid_position_40,
         "position 8/8/8/8/8/8/8/8 w KQkq - 0 0 moves a1a1w"
     );
+    test_invalid_command!(
+        invalid_position_41,
+        "position 8/8/8/8/8/8/8/8[K] w - - 0 0 moves K@e4"
+    );
+    test_invalid_command!(
+        invalid_position_42,
+        "position 8/8/8/8/8/8/8/8 w - - 0 0 moves p@e4"
+    );
 
     // Valid go
     test_valid_command!(valid_go_1, "go");
@@ -437,6 +699,7 @@ mod tests {
     test_valid_command!(valid_go_6, "go nodes 7");
     test_valid_command!(valid_go_7, "go mate 09");
     test_valid_command!(valid_go_8, "go infinite searchmoves a1a2 a2a4q");
+    test_valid_command!(valid_go_9, "go depth 2 searchmoves Q@h7 e2e4");
     // Invalid go
     test_invalid_command!(invalid_go_1, "ugo");
     test_invalid_command!(invalid_go_2, "gon");
@@ -495,6 +758,15 @@ mod tests {
     test_invalid_command!(invalid_ponderhit_14, "ponderhit$");
     test_invalid_command!(invalid_ponderhit_15, "ponderhit\nisready");
 
+    // Valid eval
+    test_valid_command!(valid_eval_1, "eval");
+    test_valid_command!(valid_eval_2, "\teval\n");
+    // Invalid eval
+    test_invalid_command!(invalid_eval_1, "evall");
+    test_invalid_command!(invalid_eval_2, "eval 1");
+    test_invalid_command!(invalid_eval_3, "ev al");
+    test_invalid_command!(invalid_eval_4, "go eval");
+
     // Test command creation (does Command::tokens get properly populated)
     macro_rules! test_command_tokens {
         ($test_name:ident, $input_str:literal, $expected:expr) => {
@@ -584,7 +856,7 @@ mod tests {
         let mut game_state = GameState::new();
         run_command(&mut game_state, "debug on");
 
-        assert_eq!(game_state.debug, true);
+        assert!(game_state.debug);
     }
 
     #[test]
@@ -592,7 +864,7 @@ mod tests {
         let mut game_state = GameState::new();
         run_command(&mut game_state, "debug off");
 
-        assert_eq!(game_state.debug, false);
+        assert!(!game_state.debug);
     }
 
     // Test command execution output
@@ -612,11 +884,332 @@ mod tests {
     test_execute_output!(
         test_output_uci,
         "uci",
-        "id name Challenger\nid author folksgl\nuciok\n"
+        "id name Challenger\nid author folksgl\n\
+         option name Hash type spin default 16 min 1 max 4096\n\
+         option name Threads type spin default 1 min 1 max 256\n\
+         option name Use NNUE type check default false\n\
+         option name EvalFile type string default <empty>\n\
+         option name Skill Level type spin default 20 min 0 max 20\n\
+         option name UCI_LimitStrength type check default false\n\
+         option name UCI_Elo type spin default 1500 min 1000 max 2800\n\
+         option name Contempt type spin default 0 min -100 max 100\n\
+         option name DynamicContempt type check default false\n\
+         option name UCI_Opponent type string default <empty>\n\
+         option name UCI_Variant type combo default chess var chess var kingofthehill var 3check var antichess var crazyhouse\n\
+         option name UCI_Chess960 type check default false\n\
+         option name OwnBook type check default false\n\
+         option name BookFile type string default <empty>\n\
+         option name BookDepth type spin default 20 min 1 max 255\n\
+         option name BookBestMove type check default false\n\
+         uciok\n"
+    );
+    test_execute_output!(
+        test_output_setoption_unknown,
+        "setoption name Nonexistent value 1",
+        "info string No such option: Nonexistent\n"
+    );
+    test_execute_output!(
+        test_output_setoption_invalid,
+        "setoption name Use NNUE value maybe",
+        "info string Invalid value for Use NNUE: maybe\n"
     );
+    test_execute_output!(
+        test_output_setoption_valid,
+        "setoption name use nnue value true",
+        ""
+    );
+
+    #[test]
+    fn command_position_variant() {
+        let mut game_state = GameState::new();
+        run_command(&mut game_state, "setoption name UCI_Variant value 3check");
+        run_command(&mut game_state, "position startpos moves e2e4 d7d5 f1b5");
+        let position = game_state.game_position;
+        assert_eq!(position.variant(), Variant::ThreeCheck);
+        assert_eq!(
+            position.to_fen(),
+            "rnbqkbnr/ppp1pppp/8/1B1p4/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 2+3 1 2"
+        );
+
+        run_command(
+            &mut game_state,
+            "position 4k3/8/8/8/8/8/8/R3K3 w Q - 1+3 0 1 moves a1a8",
+        );
+        assert_eq!(
+            game_state.game_position.variant_outcome(),
+            Some(Outcome::Loss)
+        );
+    }
+
+    #[test]
+    fn command_position_stops_at_illegal_move() {
+        let mut game_state = GameState::new();
+        run_command(&mut game_state, "position startpos moves e2e4 e2e4 d7d5");
+        assert_eq!(
+            game_state.game_position.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(game_state.game_hashes.len(), 1);
+    }
+
+    #[test]
+    fn command_position_invalid_fen() {
+        let mut game_state = GameState::new();
+        run_command(&mut game_state, "position startpos moves e2e4");
+        let position = game_state.game_position;
+        let mut output = vec![];
+        let command = Command::from("position 4k3/8/8/8/8/8/8/4K3 w - - 0 70000 moves e1e2");
+        command.unwrap().execute(&mut game_state, &mut output);
+        assert_eq!(game_state.game_position, position);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .starts_with("info string Invalid fullmove number"));
+    }
+
+    #[test]
+    fn command_position_crazyhouse() {
+        let mut game_state = GameState::new();
+        run_command(
+            &mut game_state,
+            "setoption name UCI_Variant value crazyhouse",
+        );
+        run_command(
+            &mut game_state,
+            "position startpos moves e2e4 d7d5 e4d5 d8d5 b1c3 d5a5 P@d4",
+        );
+        assert_eq!(
+            game_state.game_position.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/q7/3P4/2N5/PPPP1PPP/R1BQKBNR[p] b KQkq - 0 4"
+        );
+    }
+
+    #[test]
+    fn command_setoption_use_nnue() {
+        let mut game_state = GameState::new();
+        run_command(&mut game_state, "setoption name Use NNUE value true");
+        assert!(game_state.options.get_bool("Use NNUE"));
+        run_command(&mut game_state, "setoption name Use NNUE value false");
+        assert!(!game_state.options.get_bool("Use NNUE"));
+    }
+
+    #[test]
+    fn test_output_eval_nnue() {
+        let mut game_state = GameState::new();
+        game_state.network = Some(std::sync::Arc::new(crate::nnue::Network::random(9)));
+        run_command(&mut game_state, "setoption name Use NNUE value true");
+
+        let mut string_buf: Vec<u8> = Vec::new();
+        Command::from("eval")
+            .unwrap()
+            .execute(&mut game_state, &mut string_buf);
+        assert!(String::from_utf8(string_buf)
+            .unwrap()
+            .contains("NNUE evaluation: "));
+    }
 
     test_execute_output!(test_output_isready, "isready", "readyok\n");
 
+    #[test]
+    fn test_output_eval() {
+        let mut game_state = GameState::new();
+        run_command(&mut game_state, "position startpos moves e2e4");
+
+        let mut string_buf: Vec<u8> = Vec::new();
+        let command = Command::from("eval").unwrap();
+        command.execute(&mut game_state, &mut string_buf);
+
+        let expected = evaluate::trace(&game_state.game_position).to_string();
+        assert_eq!(String::from_utf8(string_buf).unwrap(), expected);
+        assert!(expected.contains("Material"));
+        assert!(expected.contains("King safety"));
+    }
+
+    // Collects the output of a background search
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_go_depth() {
+        let mut game_state = GameState::new();
+        run_command(&mut game_state, "position startpos moves e2e4");
+        let tokens = ["depth", "3"];
+        let limits = parse_go(&tokens, &game_state.game_position);
+
+        let output = SharedBuffer::default();
+        go(&mut game_state, limits, output.clone());
+        game_state.wait_search();
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("info depth 1 score cp "));
+        assert!(lines[2].starts_with("info depth 3 score cp "));
+        let best_move = lines[3].strip_prefix("bestmove ").unwrap();
+        let position = game_state.game_position;
+        assert!(position
+            .moves()
+            .contains(&position::str_to_move(best_move, position).unwrap()));
+    }
+
+    #[test]
+    fn test_stop_ends_search() {
+        let mut game_state = GameState::new();
+        let output = SharedBuffer::default();
+        go(&mut game_state, Limits::default(), output.clone());
+        run_command(&mut game_state, "stop");
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(output.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_go_no_moves() {
+        let mut game_state = GameState::new();
+        run_command(&mut game_state, "position 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        let output = SharedBuffer::default();
+        go(&mut game_state, Limits::default(), output.clone());
+        game_state.wait_search();
+        assert_eq!(*output.0.lock().unwrap(), b"bestmove 0000\n");
+    }
+
+    // Test 'go' argument parsing into search limits
+    macro_rules! test_parse_go {
+        ($test_name:ident, $input_str:literal, $fen:expr, $expected:expr) => {
+            #[test]
+            fn $test_name() {
+                let tokens: Vec<&str> = $input_str.split_whitespace().collect();
+                let position = Position::from($fen);
+                assert_eq!(parse_go(&tokens, &position), $expected);
+            }
+        };
+    }
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const AFTER_E4: &str = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+
+    test_parse_go!(parse_go_infinite, "infinite", START, Limits::default());
+    test_parse_go!(
+        parse_go_depth_nodes,
+        "depth 5 nodes 1000",
+        START,
+        Limits {
+            depth: Some(5),
+            nodes: Some(1000),
+            ..Limits::default()
+        }
+    );
+    test_parse_go!(
+        parse_go_movetime,
+        "movetime 250 wtime 100000",
+        START,
+        Limits {
+            movetime: Some(Duration::from_millis(250)),
+            ..Limits::default()
+        }
+    );
+    test_parse_go!(
+        parse_go_white_clock,
+        "wtime 60000 btime 1000 winc 1000 binc 0",
+        START,
+        Limits {
+            movetime: Some(Duration::from_millis(2750)),
+            ..Limits::default()
+        }
+    );
+    test_parse_go!(
+        parse_go_black_clock,
+        "wtime 60000 btime 10000 movestogo 5",
+        AFTER_E4,
+        Limits {
+            movetime: Some(Duration::from_millis(2000)),
+            ..Limits::default()
+        }
+    );
+    test_parse_go!(
+        parse_go_low_clock,
+        "wtime 40 winc 2000",
+        START,
+        Limits {
+            movetime: Some(Duration::from_millis(1)),
+            ..Limits::default()
+        }
+    );
+
+    test_parse_go!(
+        parse_go_mate,
+        "mate 3",
+        START,
+        Limits {
+            mate: Some(3),
+            ..Limits::default()
+        }
+    );
+    test_parse_go!(
+        parse_go_searchmoves,
+        "searchmoves e7e5 e2e4 g8f6 depth 4",
+        AFTER_E4,
+        Limits {
+            depth: Some(4),
+            search_moves: vec![
+                position::str_to_move("e7e5", Position::from(AFTER_E4)).unwrap(),
+                position::str_to_move("g8f6", Position::from(AFTER_E4)).unwrap(),
+            ],
+            ..Limits::default()
+        }
+    );
+
+    #[test]
+    fn test_info_line() {
+        let mut position = Position::new();
+        let mut pv = vec![];
+        for x in ["e2e4", "e7e5", "g1f3"] {
+            pv.push(position::str_to_move(x, position).unwrap());
+            position.play_move(*pv.last().unwrap());
+        }
+        let mut result = SearchResult {
+            best_move: Some(pv[0]),
+            score: 35,
+            depth: 7,
+            nodes: 5000,
+            pv,
+        };
+        assert_eq!(
+            info_line(&result, Duration::from_millis(500), false),
+            "info depth 7 score cp 35 nodes 5000 nps 10000 time 500 pv e2e4 e7e5 g1f3"
+        );
+
+        result.score = search::MATE - 3;
+        assert!(info_line(&result, Duration::from_millis(0), false).contains("score mate 2 "));
+        result.score = -search::MATE + 4;
+        assert!(info_line(&result, Duration::from_millis(0), false).contains("score mate -2 "));
+    }
+
+    #[test]
+    fn test_info_line_chess960_castling() {
+        let position = Position::from("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 1,
+            nodes: 1,
+            pv: vec![position::str_to_move("e1g1", position).unwrap()],
+        };
+        let line = info_line(&result, Duration::from_millis(0), false);
+        assert!(line.ends_with(" pv e1g1"), "{}", line);
+        let line = info_line(&result, Duration::from_millis(0), true);
+        assert!(line.ends_with(" pv e1h1"), "{}", line);
+    }
+
     // Test 'position' command Position construction
     macro_rules! test_uci_position {
         ($test_name:ident, $input_str:literal, $expected:expr) => {
@@ -638,6 +1231,17 @@ mod tests {
         Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
     );
 
+    test_uci_position!(
+        test_position_startpos_moves,
+        "position startpos moves e2e4 c7c5 g1f3",
+        Position::from("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+    );
+    test_uci_position!(
+        test_position_fen_moves,
+        "position 8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1 moves e2e4",
+        Position::from("8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1")
+    );
+
     test_uci_position!(
         test_position_complex3,
         "position 8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
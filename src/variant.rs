@@ -0,0 +1,254 @@
+// variant.rs defines the chess variants challenger can play. A Variant is
+// stored in every Position, and consulted by move generation, game end
+// detection and the evaluation wherever the rules differ from standard chess.
+
+use crate::position::*;
+
+// The four squares in the middle of the board, which a king must reach to win
+// King of the Hill
+pub const CENTER: u64 = (D_FILE | E_FILE) & (RANK_4 | RANK_5);
+
+// The number of checks that win a game of Three-check
+pub const CHECKS_TO_WIN: u8 = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // A king that reaches the center wins
+    KingOfTheHill,
+    // The third check wins
+    ThreeCheck,
+    // Captures are compulsory, the king is an ordinary piece, and the side
+    // that loses all of its pieces or cannot move wins
+    Antichess,
+    // Captured pieces join the capturer's hand, and may be dropped on any
+    // empty square instead of moving
+    Crazyhouse,
+}
+
+// The result of a game, from the point of view of the side to move
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 5] = [
+        Variant::Standard,
+        Variant::KingOfTheHill,
+        Variant::ThreeCheck,
+        Variant::Antichess,
+        Variant::Crazyhouse,
+    ];
+
+    // The name used by the UCI_Variant option, as understood by GUIs
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "3check",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        Variant::ALL
+            .iter()
+            .find(|x| x.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub fn start_fen(self) -> &'static str {
+        match self {
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            _ => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        }
+    }
+
+    // Whether the king can be checked, and so must be kept out of check.
+    // Castling also needs a royal king.
+    pub fn has_royal_king(self) -> bool {
+        self != Variant::Antichess
+    }
+
+    // Whether captured pieces go to the capturer's hand, to be dropped later
+    pub fn has_drops(self) -> bool {
+        self == Variant::Crazyhouse
+    }
+
+    // Whether a side that can capture must do so
+    pub fn forces_captures(self) -> bool {
+        self == Variant::Antichess
+    }
+
+    // Whether a pawn may promote to a king
+    pub fn promotes_to_king(self) -> bool {
+        self == Variant::Antichess
+    }
+
+    // Whether a position without enough material to checkmate is drawn. A
+    // king walking to the center or losing all of its pieces needs none, and
+    // in Crazyhouse captured material is never gone for good.
+    pub fn draws_by_insufficient_material(self) -> bool {
+        matches!(self, Variant::Standard | Variant::ThreeCheck)
+    }
+
+    // The result of a game already ended by a rule of the variant, before any
+    // moves of the side to move are looked at
+    pub fn outcome(self, position: &Position) -> Option<Outcome> {
+        let own = (!position.is_white_move as usize) * 7;
+        let enemy = 7 - own;
+        match self {
+            Variant::Standard | Variant::Crazyhouse => None,
+            Variant::KingOfTheHill if position.pieces[W_KING + enemy] & CENTER != 0 => {
+                Some(Outcome::Loss)
+            }
+            Variant::KingOfTheHill if position.pieces[W_KING + own] & CENTER != 0 => {
+                Some(Outcome::Win)
+            }
+            Variant::KingOfTheHill => None,
+            Variant::ThreeCheck
+                if position.checks[position.is_white_move as usize] >= CHECKS_TO_WIN =>
+            {
+                Some(Outcome::Loss)
+            }
+            Variant::ThreeCheck => None,
+            Variant::Antichess if position.pieces[W_PIECES + own] == 0 => Some(Outcome::Win),
+            Variant::Antichess => None,
+        }
+    }
+
+    // The result of a game in which the side to move has no legal moves
+    pub fn no_moves_outcome(self, in_check: bool) -> Outcome {
+        match self {
+            Variant::Antichess => Outcome::Win,
+            _ if in_check => Outcome::Loss,
+            _ => Outcome::Draw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Perft results of larger trees are those published for the Lichess
+    // variants, and small ones are counted by hand
+    macro_rules! test_perft {
+        ($test_name:ident, $variant:expr, $fen:expr, $depth:literal, $expected:literal) => {
+            #[test]
+            fn $test_name() {
+                let position = Position::from_variant($fen, $variant);
+                assert_eq!(position.perft($depth), $expected);
+            }
+        };
+    }
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    // A king on e3 is a move from the hill, which ends the game
+    test_perft!(
+        perft_king_of_the_hill_1,
+        Variant::KingOfTheHill,
+        "8/8/8/8/8/4K3/8/k7 w - - 0 1",
+        2,
+        18
+    );
+    test_perft!(
+        perft_king_of_the_hill_2,
+        Variant::KingOfTheHill,
+        Variant::KingOfTheHill.start_fen(),
+        4,
+        197281
+    );
+    // With one check left each, every check ends the game
+    test_perft!(
+        perft_three_check_1,
+        Variant::ThreeCheck,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 0 1",
+        3,
+        97848
+    );
+    test_perft!(perft_three_check_2, Variant::ThreeCheck, KIWIPETE, 3, 97862);
+    test_perft!(
+        perft_antichess_1,
+        Variant::Antichess,
+        Variant::Antichess.start_fen(),
+        4,
+        153299
+    );
+    // The capture is forced, and the pawn may promote to a king
+    test_perft!(
+        perft_antichess_2,
+        Variant::Antichess,
+        "8/1P6/8/8/8/8/1p6/R7 b - - 0 1",
+        1,
+        5
+    );
+    test_perft!(
+        perft_crazyhouse_1,
+        Variant::Crazyhouse,
+        Variant::Crazyhouse.start_fen(),
+        4,
+        197281
+    );
+    test_perft!(
+        perft_crazyhouse_2,
+        Variant::Crazyhouse,
+        "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+        2,
+        75353
+    );
+    // A check from the rook is met by a king move or by dropping the knight
+    // in between
+    test_perft!(
+        perft_crazyhouse_3,
+        Variant::Crazyhouse,
+        "4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1",
+        1,
+        6
+    );
+
+    #[test]
+    fn names_round_trip() {
+        for variant in Variant::ALL {
+            assert_eq!(Variant::from_name(variant.name()), Some(variant));
+        }
+        assert_eq!(Variant::from_name("3Check"), Some(Variant::ThreeCheck));
+        assert_eq!(Variant::from_name("zh"), None);
+    }
+
+    #[test]
+    fn king_of_the_hill_outcome() {
+        let variant = Variant::KingOfTheHill;
+        let on_hill = Position::from_variant("4k3/8/8/8/4K3/8/8/8 b - - 0 1", variant);
+        assert_eq!(variant.outcome(&on_hill), Some(Outcome::Loss));
+        let near_hill = Position::from_variant("4k3/8/8/8/8/4K3/8/8 b - - 0 1", variant);
+        assert_eq!(variant.outcome(&near_hill), None);
+    }
+
+    #[test]
+    fn three_check_outcome() {
+        let variant = Variant::ThreeCheck;
+        let third = Position::from_variant("4k3/8/8/8/8/8/8/4K2R b - - 0 1 +3+1", variant);
+        assert_eq!(variant.outcome(&third), Some(Outcome::Loss));
+        let second = Position::from_variant("4k3/8/8/8/8/8/8/4K2R b - - 0 1 +2+1", variant);
+        assert_eq!(variant.outcome(&second), None);
+    }
+
+    #[test]
+    fn antichess_outcome() {
+        let variant = Variant::Antichess;
+        let no_pieces = Position::from_variant("8/8/8/8/8/8/8/4K3 b - - 0 1", variant);
+        assert_eq!(variant.outcome(&no_pieces), Some(Outcome::Win));
+        assert_eq!(variant.no_moves_outcome(false), Outcome::Win);
+        assert_eq!(Variant::Standard.no_moves_outcome(false), Outcome::Draw);
+        assert_eq!(Variant::Standard.no_moves_outcome(true), Outcome::Loss);
+    }
+}
@@ -0,0 +1,45 @@
+// Tests of the 'bench' subcommand of the engine binary.
+
+use std::process::Command;
+
+fn bench(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_challenger-rs"))
+        .arg("bench")
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+// The total of the 'Nodes searched' line
+fn nodes(output: &str) -> u64 {
+    let line = output
+        .lines()
+        .find(|x| x.starts_with("Nodes searched"))
+        .unwrap();
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+#[test]
+fn bench_signature_is_stable() {
+    let (success, first) = bench(&["3"]);
+    assert!(success);
+    assert_eq!(
+        first.lines().filter(|x| x.starts_with("Position")).count(),
+        50
+    );
+    assert!(first.contains("Nodes/second"));
+
+    let (_, second) = bench(&["3", "1", "16"]);
+    assert_eq!(nodes(&first), nodes(&second));
+    assert!(nodes(&bench(&["4"]).1) > nodes(&first));
+}
+
+#[test]
+fn bench_rejects_bad_arguments() {
+    assert!(!bench(&["0"]).0);
+    assert!(!bench(&["3", "many"]).0);
+}
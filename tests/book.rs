@@ -0,0 +1,45 @@
+// Tests of the 'book' subcommand of the engine binary.
+
+use challenger_rs::book::Book;
+use challenger_rs::position::{move_to_str, Position};
+
+use std::fs;
+use std::process::Command;
+
+fn book(args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_challenger-rs"))
+        .arg("book")
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+#[test]
+fn build_reads_back() {
+    let dir = std::env::temp_dir();
+    let pgn = dir.join("challenger_book_test.pgn");
+    let bin = dir.join("challenger_book_test.bin");
+    fs::write(
+        &pgn,
+        "[Result \"1-0\"]\n1. e4 e5 1-0\n\n[Result \"1/2-1/2\"]\n1. e4 c5 1/2-1/2\n",
+    )
+    .unwrap();
+    let (pgn_path, bin_path) = (pgn.to_str().unwrap(), bin.to_str().unwrap());
+
+    assert!(book(&["build", pgn_path, bin_path, "min-games=2"]));
+    let built = Book::load(bin_path).unwrap();
+    let moves: Vec<(String, u16)> = built
+        .moves(&Position::new())
+        .iter()
+        .map(|&(mov, weight)| (move_to_str(mov), weight))
+        .collect();
+    assert_eq!(moves, vec![(String::from("e2e4"), 3)]);
+    assert_eq!(built.len(), 1);
+
+    assert!(!book(&["build", pgn_path, bin_path, "min-games=two"]));
+    assert!(!book(&["build", "no/such/games.pgn", bin_path]));
+    fs::remove_file(pgn).unwrap();
+    fs::remove_file(bin).unwrap();
+}
@@ -0,0 +1,42 @@
+// Tests of the 'epdtest' subcommand of the engine binary.
+
+use std::fs;
+use std::process::Command;
+
+fn epdtest(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_challenger-rs"))
+        .arg("epdtest")
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn epdtest_reports_solved_positions() {
+    let path = std::env::temp_dir().join("challenger_epdtest.epd");
+    fs::write(
+        &path,
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";\n\
+         r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - dm 2; id \"mate\";\n\
+         rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm a3; id \"start\";\n",
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+
+    let (success, output) = epdtest(&[path, "depth=6"]);
+    assert!(success);
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].contains("WAC.001") && lines[0].contains("solved"));
+    assert!(lines[0].contains("Qg6"));
+    assert!(lines[1].contains("mate") && lines[1].contains("solved"));
+    assert!(lines[2].contains("start") && lines[2].contains("failed"));
+    assert!(output.contains("Solved          : 2 / 3 (66.7%)"));
+
+    assert!(!epdtest(&[path, "depth=none"]).0);
+    assert!(!epdtest(&["no/such/suite.epd"]).0);
+    fs::remove_file(path).unwrap();
+}
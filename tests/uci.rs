@@ -0,0 +1,200 @@
+// Tests that drive the engine binary over UCI, as a GUI would.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+// Send 'commands' to a fresh engine and collect everything it prints. Like a
+// GUI, wait for the 'bestmove' ending each search before sending more.
+fn run_engine(commands: &[&str]) -> Vec<String> {
+    let mut engine = Command::new(env!("CARGO_BIN_EXE_challenger-rs"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = engine.stdin.take().unwrap();
+    let mut stdout = BufReader::new(engine.stdout.take().unwrap()).lines();
+
+    let mut lines = vec![];
+    for command in commands {
+        writeln!(stdin, "{}", command).unwrap();
+        stdin.flush().unwrap();
+        if command.starts_with("go") {
+            loop {
+                let line = stdout.next().unwrap().unwrap();
+                let done = line.starts_with("bestmove");
+                lines.push(line);
+                if done {
+                    break;
+                }
+            }
+        }
+    }
+
+    writeln!(stdin, "quit").unwrap();
+    drop(stdin);
+    engine.wait().unwrap();
+    lines
+}
+
+// The value following 'key' in an 'info' line
+fn info_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let mut tokens = line.split_whitespace();
+    tokens.find(|&x| x == key)?;
+    tokens.next()
+}
+
+// Remove the timing dependent parts of an 'info' line
+fn without_timing(line: &str) -> String {
+    let mut tokens = vec![];
+    let mut iter = line.split_whitespace();
+    while let Some(x) = iter.next() {
+        match x {
+            "time" | "nps" => {
+                iter.next();
+            }
+            _ => tokens.push(x),
+        }
+    }
+    tokens.join(" ")
+}
+
+#[test]
+fn go_mate_finds_mate_in_two() {
+    let output = run_engine(&[
+        "position r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1",
+        "go mate 2",
+    ]);
+    let last_info = output.iter().rev().find(|x| x.starts_with("info")).unwrap();
+    assert!(last_info.contains("score mate 2 "), "{}", last_info);
+    assert_eq!(output.last().unwrap(), "bestmove d5d8");
+}
+
+#[test]
+fn go_nodes_is_deterministic() {
+    let search = [
+        "ucinewgame",
+        "position startpos moves e2e4 c7c5",
+        "go nodes 20000",
+    ];
+    let commands: Vec<&str> = search.iter().chain(search.iter()).copied().collect();
+    let output = run_engine(&commands);
+
+    let split = output
+        .iter()
+        .position(|x| x.starts_with("bestmove"))
+        .unwrap()
+        + 1;
+    let (first, second) = output.split_at(split);
+    let first: Vec<String> = first.iter().map(|x| without_timing(x)).collect();
+    let second: Vec<String> = second.iter().map(|x| without_timing(x)).collect();
+    assert_eq!(first, second);
+    assert_eq!(
+        first,
+        run_engine(&search)
+            .iter()
+            .map(|x| without_timing(x))
+            .collect::<Vec<_>>()
+    );
+
+    for line in output.iter().filter(|x| x.starts_with("info")) {
+        let nodes: u64 = info_value(line, "nodes").unwrap().parse().unwrap();
+        assert!(nodes <= 20000, "{}", line);
+    }
+}
+
+#[test]
+fn malformed_fen_keeps_the_position() {
+    let output = run_engine(&[
+        "position startpos moves e2e4",
+        "position 88/8/8/8/8/8/8/8 w - - 0 1",
+        "position 4k3/8/8/8/8/8/8/4K3 w - - 0 70000 moves e1e2",
+        "go depth 3",
+    ]);
+    let errors: Vec<&String> = output
+        .iter()
+        .filter(|x| x.starts_with("info string Invalid"))
+        .collect();
+    assert_eq!(errors.len(), 2, "{:?}", output);
+    // Black is still to move after 1. e4, from its back two ranks
+    let best_move = output.last().unwrap();
+    let from_rank = best_move.chars().nth("bestmove a".len()).unwrap();
+    assert!(from_rank == '7' || from_rank == '8', "{}", best_move);
+}
+
+#[test]
+fn go_searchmoves_restricts_root_moves() {
+    // Only the king may move, so the back rank mate must not be played
+    let output = run_engine(&[
+        "position 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+        "go depth 4 searchmoves g1f2 g1h2",
+    ]);
+    for line in output.iter().filter(|x| x.starts_with("info")) {
+        let first_move = info_value(line, "pv").unwrap();
+        assert!(["g1f2", "g1h2"].contains(&first_move), "{}", line);
+    }
+    let best_move = output.last().unwrap();
+    assert!(["bestmove g1f2", "bestmove g1h2"].contains(&best_move.as_str()));
+}
+
+#[test]
+fn go_searchmoves_with_a_single_move() {
+    let output = run_engine(&["position startpos", "go depth 5 searchmoves a2a3"]);
+    assert_eq!(output.last().unwrap(), "bestmove a2a3");
+}
+
+#[test]
+fn skill_level_caps_the_search() {
+    let output = run_engine(&[
+        "setoption name Skill Level value 0",
+        "position startpos",
+        "go depth 10",
+    ]);
+    let depths: Vec<u32> = output
+        .iter()
+        .filter_map(|x| info_value(x, "depth")?.parse().ok())
+        .collect();
+    assert_eq!(depths, vec![1]);
+    assert!(output.last().unwrap().starts_with("bestmove "));
+}
+
+#[test]
+fn limit_strength_by_elo() {
+    let output = run_engine(&[
+        "setoption name UCI_LimitStrength value true",
+        "setoption name UCI_Elo value 1400",
+        "position startpos",
+        "go depth 10",
+    ]);
+    assert!(output.iter().all(|x| info_value(x, "depth") <= Some("5")));
+    assert!(output.last().unwrap().starts_with("bestmove "));
+}
+
+#[test]
+fn own_book_move_is_played() {
+    use challenger_rs::book::{self, Entry};
+    use challenger_rs::position::Position;
+
+    // A book with the single move 1. d4
+    let entry = Entry {
+        key: book::key(&Position::new()),
+        mov: 11 << 6 | 27,
+        weight: 1,
+        learn: 0,
+    };
+    let path = std::env::temp_dir().join("challenger_uci_test.bin");
+    std::fs::write(&path, entry.encode()).unwrap();
+    let book_file = format!("setoption name BookFile value {}", path.to_str().unwrap());
+
+    let output = run_engine(&[
+        &book_file,
+        "setoption name OwnBook value true",
+        "position startpos",
+        "go depth 5",
+        "position startpos moves d2d4",
+        "go depth 1",
+    ]);
+    assert_eq!(output[0], "bestmove d2d4");
+    // Out of the book, the engine searches
+    assert!(output[1].starts_with("info depth 1"));
+    std::fs::remove_file(path).unwrap();
+}